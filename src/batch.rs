@@ -0,0 +1,241 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::core::{DeathCause, Direction, DirectionEvent, GameRngPlugin, GameState, GameStatePlugin, GridPosition, PlayerId};
+use crate::food::{self, FoodPlugin};
+use crate::game_board::board;
+use crate::level::Level;
+use crate::snake;
+use crate::snake::brain::BotKind;
+use crate::tick::TickClock;
+
+// Games that outlast this many ticks are stopped and counted as timed out, since
+// a bot can circle forever without ever eating or dying.
+pub const DEFAULT_MAX_TICKS: u64 = 100_000;
+
+// An open board of the given size with the snake starting halfway down the left
+// edge and the first food in the middle.
+pub fn open_level(grid_size: (i32, i32)) -> Level {
+    let (width, height) = grid_size;
+    Level {
+        board: board::Desc { grid_size, cell_size: 1, boundary: board::BoundaryMode::Wrap, obstacles: Vec::new() },
+        snake: snake::helpers::InitParams::single_player(GridPosition::new(0, height / 2), Direction::Right, 3),
+        food: food::InitParams { start_position: GridPosition::new(width / 2, height / 2), spawn_points: Vec::new() }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameResult {
+    // the length of every snake, in player order
+    pub lengths: Vec<usize>,
+    pub ticks: u64,
+    // None for a game that timed out
+    pub death_cause: Option<DeathCause>
+}
+
+#[derive(Default, Debug)]
+pub struct BatchResults {
+    pub games: Vec<GameResult>
+}
+
+impl BatchResults {
+    pub fn summary(&self) -> String {
+        let lengths = self.games.iter().flat_map(|game| game.lengths.iter().copied()).collect::<Vec<_>>();
+        let ticks = self.games.iter().map(|game| game.ticks as usize).collect::<Vec<_>>();
+        let mut causes: Vec<(String, usize)> = Vec::new();
+        for game in self.games.iter() {
+            let cause = match game.death_cause {
+                Some(cause) => format!("{:?}", cause),
+                None => String::from("timed out")
+            };
+            match causes.iter_mut().find(|(counted, _)| *counted == cause) {
+                Some((_, count)) => *count += 1,
+                None => causes.push((cause, 1))
+            }
+        }
+        causes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let causes = causes
+            .iter()
+            .map(|(cause, count)| format!("{} {}", cause, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "games {}\nlength {}\nticks {}\ndeaths {}",
+            self.games.len(),
+            describe(&lengths),
+            describe(&ticks),
+            causes
+        )
+    }
+}
+
+fn describe(values: &[usize]) -> String {
+    let mean = values.iter().sum::<usize>() as f64 / values.len().max(1) as f64;
+    format!(
+        "mean {:.1} min {} max {}",
+        mean,
+        values.iter().min().unwrap_or(&0),
+        values.iter().max().unwrap_or(&0)
+    )
+}
+
+// Plays `games` games back to back with every snake steered by `bot`, one tick
+// per frame, then prints the results and exits. Needs no window or renderer, so
+// it goes with MinimalPlugins.
+pub struct BatchPlugin {
+    pub level: Level,
+    pub bot: BotKind,
+    pub games: usize,
+    pub seed: u64,
+    pub max_ticks: u64
+}
+
+impl Plugin for BatchPlugin {
+    fn build(&self, app: &mut App) {
+        let mut level = self.level.clone();
+        level.snake.bots = (0..level.snake.player_count()).map(|player| (PlayerId(player), self.bot.clone())).collect();
+        add_headless_game(app, &level, self.seed)
+            .insert_resource(BatchRun { games: self.games, max_ticks: self.max_ticks, start_tick: 0 })
+            .init_resource::<BatchResults>()
+            .add_enter_system(GameState::COUNTDOWN, start_game)
+            .add_system(time_out_game.run_in_state(GameState::RUNNING))
+            .add_enter_system(GameState::DEAD, finish_game);
+    }
+}
+
+// The rules of the game on `level` with nothing to draw or read input from,
+// ticking once a frame from the first frame. The game over pause is long enough
+// that whatever runs it decides when the next game starts.
+pub fn add_headless_game<'a>(app: &'a mut App, level: &Level, seed: u64) -> &'a mut App {
+    app
+        .add_plugin(GameStatePlugin {
+            tick_time_sec: 0.,
+            game_over_pause_sec: 3600.,
+            countdown_sec: 0.,
+            initial_state: GameState::COUNTDOWN
+        })
+        .add_plugin(GameRngPlugin { seed })
+        .add_event::<DirectionEvent>()
+        .insert_resource(level.board.clone())
+        .add_startup_system(board::spawn_obstacles)
+        .add_plugin(FoodPlugin { init_params: level.food.clone() })
+        .add_plugin(snake::plugin::SnakePlugin { init_params: level.snake.clone() })
+        .insert_resource(TickClock::unpaced())
+}
+
+struct BatchRun {
+    games: usize,
+    max_ticks: u64,
+    start_tick: u64
+}
+
+fn start_game(mut run: ResMut<BatchRun>, clock: Res<TickClock>) {
+    run.start_tick = clock.tick();
+}
+
+fn time_out_game(run: Res<BatchRun>, clock: Res<TickClock>, mut commands: Commands) {
+    if clock.tick() - run.start_tick >= run.max_ticks {
+        commands.insert_resource(NextState(GameState::DEAD));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn finish_game(
+    run: Res<BatchRun>,
+    clock: Res<TickClock>,
+    game_board: Res<board::Desc>,
+    death_cause: Option<Res<DeathCause>>,
+    snake_query: Query<(&PlayerId, &GridPosition), snake::helpers::WithAnySnakeType>,
+    mut results: ResMut<BatchResults>,
+    mut exit_events: EventWriter<AppExit>,
+    mut commands: Commands
+) {
+    let mut lengths = Vec::new();
+    // segments still growing in are off the board and don't count yet
+    for (player, _) in snake_query.iter().filter(|(_, grid_pos)| game_board.is_inside(grid_pos)) {
+        if lengths.len() <= player.0 {
+            lengths.resize(player.0 + 1, 0);
+        }
+        lengths[player.0] += 1;
+    }
+    results.games.push(GameResult {
+        lengths,
+        ticks: clock.tick() - run.start_tick,
+        death_cause: death_cause.map(|cause| *cause)
+    });
+    if results.games.len() < run.games {
+        commands.insert_resource(NextState(GameState::COUNTDOWN));
+    } else {
+        println!("{}", results.summary());
+        exit_events.send(AppExit);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::Events;
+    use super::*;
+
+    fn run_batch(level: Level, bot: BotKind, games: usize, max_ticks: u64) -> App {
+        let mut app = App::default();
+        app.add_plugins(MinimalPlugins);
+        app.add_plugin(BatchPlugin { level, bot, games, seed: 7, max_ticks });
+        for _ in 0..100_000 {
+            if !app.world.resource::<Events<AppExit>>().is_empty() {
+                break;
+            }
+            app.update();
+        }
+        app
+    }
+
+    #[test]
+    fn plays_every_game_then_exits() {
+        let app = run_batch(open_level((6, 6)), BotKind::Bfs, 3, DEFAULT_MAX_TICKS);
+        let results = app.world.resource::<BatchResults>();
+        assert_eq!(results.games.len(), 3);
+        assert!(!app.world.resource::<Events<AppExit>>().is_empty());
+        for game in results.games.iter() {
+            assert_eq!(game.lengths.len(), 1);
+            assert!(game.lengths[0] >= 4);
+            assert!(game.ticks > 0);
+            assert!(game.death_cause.is_some());
+        }
+    }
+
+    #[test]
+    fn long_games_time_out() {
+        let app = run_batch(open_level((6, 6)), BotKind::Hamiltonian, 2, 20);
+        let results = app.world.resource::<BatchResults>();
+        assert_eq!(results.games.len(), 2);
+        for game in results.games.iter() {
+            assert_eq!(game.death_cause, None);
+            assert_eq!(game.ticks, 20);
+        }
+    }
+
+    #[test]
+    fn lengths_leave_out_segments_still_growing_in() {
+        // two ticks in, two of the four segments are on the board
+        let app = run_batch(open_level((6, 6)), BotKind::Hamiltonian, 1, 2);
+        assert_eq!(app.world.resource::<BatchResults>().games[0].lengths, vec![2]);
+    }
+
+    #[test]
+    fn summary_counts_deaths() {
+        let results = BatchResults {
+            games: vec![
+                GameResult { lengths: vec![4], ticks: 10, death_cause: Some(DeathCause::BitSelf) },
+                GameResult { lengths: vec![8], ticks: 30, death_cause: Some(DeathCause::HitWall) },
+                GameResult { lengths: vec![6], ticks: 20, death_cause: Some(DeathCause::BitSelf) },
+                GameResult { lengths: vec![9, 3], ticks: 100, death_cause: None },
+            ]
+        };
+        assert_eq!(results.summary(), "\
+games 4
+length mean 6.0 min 3 max 9
+ticks mean 40.0 min 10 max 100
+deaths BitSelf 2, HitWall 1, timed out 1");
+    }
+}
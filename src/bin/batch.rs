@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+use bevy::prelude::*;
+use bevy_snake::batch::{self, BatchPlugin};
+use bevy_snake::level::Level;
+use bevy_snake::snake::brain::BotKind;
+
+// Plays bot games with no window as fast as they'll go and prints the results:
+//
+//   batch --games 100 --seed 1 --size 20x15 --bot bfs
+//
+// `--level` plays a level file instead of an open board of `--size`. External
+// bots, `--bot exec:COMMAND` or `--bot http://HOST:PORT`, get `--move-timeout`
+// milliseconds to answer.
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+        .cloned()
+}
+
+fn parse_size(size: &str) -> Result<(i32, i32), String> {
+    size.split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .filter(|(width, height)| *width > 0 && *height > 0)
+        .ok_or_else(|| format!("invalid board size '{}', expected WIDTHxHEIGHT", size))
+}
+
+fn main() {
+    let games = arg_value("--games")
+        .map(|games| games.parse::<usize>().ok().filter(|games| *games > 0).expect("--games must be a positive integer"))
+        .unwrap_or(100);
+    let seed = arg_value("--seed")
+        .map(|seed| seed.parse::<u64>().expect("--seed must be a positive integer"))
+        .unwrap_or_else(rand::random);
+    let max_ticks = arg_value("--max-ticks")
+        .map(|ticks| ticks.parse::<u64>().expect("--max-ticks must be a positive integer"))
+        .unwrap_or(batch::DEFAULT_MAX_TICKS);
+    let mut bot: BotKind = arg_value("--bot")
+        .map(|bot| bot.parse().unwrap_or_else(|e| panic!("{}", e)))
+        .unwrap_or(BotKind::Bfs);
+    if let (BotKind::External { timeout_ms, .. } | BotKind::Battlesnake { timeout_ms, .. }, Some(timeout)) =
+        (&mut bot, arg_value("--move-timeout"))
+    {
+        *timeout_ms = timeout.parse().expect("--move-timeout must be a positive integer");
+    }
+    let mut level = match arg_value("--level") {
+        Some(path) => Level::load(&PathBuf::from(path)).unwrap_or_else(|e| panic!("failed to load level {}", e)),
+        None => {
+            let size = arg_value("--size").unwrap_or_else(|| String::from("15x15"));
+            batch::open_level(parse_size(&size).unwrap_or_else(|e| panic!("{}", e)))
+        }
+    };
+    if let Some(mode) = arg_value("--boundary") {
+        level.board.boundary = mode.parse().unwrap_or_else(|e| panic!("{}", e));
+    }
+    println!("{} games of {:?} on {}x{}, seed {}", games, bot, level.board.grid_size.0, level.board.grid_size.1, seed);
+
+    App::new()
+        .add_plugins(MinimalPlugins)
+        .add_plugin(BatchPlugin { level, bot, games, seed, max_ticks })
+        .run();
+}
@@ -1,7 +1,10 @@
 use bevy::prelude::*;
 use iyes_loopless::prelude::*;
+use rand::{RngCore, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Component, Clone, Copy, PartialEq, Debug)]
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub struct GridPosition { pub x: i32, pub y: i32 }
 
 impl GridPosition {
@@ -15,12 +18,87 @@ impl GridPosition {
     }
 }
 
-#[derive(PartialEq, Clone, Debug)]
-pub enum Direction { Up, Down, Left, Right }
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum Direction { Up, Down, Left, #[default] Right }
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+impl Direction {
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left
+        }
+    }
+
+    pub fn perpendicular(&self) -> [Direction; 2] {
+        match self {
+            Direction::Up | Direction::Down => [Direction::Left, Direction::Right],
+            Direction::Left | Direction::Right => [Direction::Up, Direction::Down]
+        }
+    }
+}
+
+// FNV-1a, which hashes the same on every platform and Rust version.
+pub fn checksum(values: impl Iterator<Item = i64>) -> u64 {
+    let mut hash = 0xcbf29ce484222325_u64;
+    for byte in values.flat_map(i64::to_le_bytes) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Which snake an entity or input belongs to, counting from 0 for player 1.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+pub struct PlayerId(pub usize);
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DirectionEvent {
+    pub player: PlayerId,
+    pub direction: Direction
+}
+
+// Inserted as a resource alongside the switch to GameState::DEAD
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum DeathCause {
+    BitSelf,
+    HitWall,
+    HitObstacle,
+    HitSnake,
+    HeadOn,
+    // an external bot crashed or broke the protocol
+    BotError
+}
+
+// With more than one snake, whether the game ends with the first death or
+// plays on until every snake has died.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum GameOverRule {
+    #[default]
+    AnyPlayerDies,
+    AllPlayersDie
+}
+
+impl std::str::FromStr for GameOverRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "any" => Ok(GameOverRule::AnyPlayerDies),
+            "all" => Ok(GameOverRule::AllPlayersDie),
+            _ => Err(format!("unknown game over rule '{}', expected any or all", s))
+        }
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
 pub enum GameState {
+    MAIN_MENU,
+    COUNTDOWN,
     RUNNING,
+    PAUSED,
     DEAD
 }
 
@@ -43,24 +121,165 @@ impl Plugin for GameWindow {
     }
 }
 
+// A new game is set up on entering COUNTDOWN, which then hands over to RUNNING.
 pub struct GameStatePlugin{
     pub tick_time_sec: f32,
-    pub game_over_pause_sec: f32
+    pub game_over_pause_sec: f32,
+    pub countdown_sec: f32,
+    pub initial_state: GameState
 }
 
 impl Plugin for GameStatePlugin {
     fn build(&self, app: &mut App) {
         app
             .insert_resource(GameOverTimer(Timer::from_seconds(self.game_over_pause_sec, true)))
-            .add_loopless_state(GameState::RUNNING)
-            .add_enter_system(GameState::DEAD, start_game_over_timer)
-            .add_system(start_new_game.run_in_state(GameState::DEAD));
+            .insert_resource(CountdownTimer(Timer::from_seconds(self.countdown_sec, false)))
+            .add_loopless_state(self.initial_state.clone())
+            .add_enter_system(GameState::COUNTDOWN, start_countdown)
+            .add_system(tick_countdown.run_in_state(GameState::COUNTDOWN))
+            .init_resource::<Pause>()
+            .add_enter_system(GameState::DEAD, start_game_over_timer.run_if(not_resuming))
+            .add_system(start_new_game.run_in_state(GameState::DEAD))
+            .add_system_to_stage(CoreStage::PostUpdate, finish_resuming);
+    }
+}
+
+// PAUSED can be entered from RUNNING or DEAD and returns to whichever it came
+// from. Enter systems of those states should run_if(not_resuming) so coming back
+// from a pause doesn't start them over. Timers carry on as normal, frames keep
+// running while paused so the resume frame's delta is just one frame.
+#[derive(Default)]
+pub struct Pause {
+    paused_from: Option<GameState>,
+    resuming: bool
+}
+
+impl Pause {
+    // The state to switch to for pausing or resuming from `current`, if it can
+    pub fn toggle(&mut self, current: &GameState) -> Option<GameState> {
+        match current {
+            GameState::RUNNING | GameState::DEAD => {
+                self.paused_from = Some(current.clone());
+                Some(GameState::PAUSED)
+            }
+            GameState::PAUSED => {
+                self.resuming = true;
+                self.paused_from.take()
+            }
+            _ => None
+        }
+    }
+
+    pub fn is_resuming(&self) -> bool {
+        self.resuming
+    }
+}
+
+pub fn not_resuming(pause: Option<Res<Pause>>) -> bool {
+    !pause.is_some_and(|pause| pause.is_resuming())
+}
+
+fn finish_resuming(state: Res<CurrentState<GameState>>, mut pause: ResMut<Pause>) {
+    if pause.resuming && state.0 != GameState::PAUSED {
+        pause.resuming = false;
+    }
+}
+
+
+pub struct GameRngPlugin {
+    pub seed: u64
+}
+
+impl Plugin for GameRngPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(GameRng::new(self.seed))
+            .add_enter_system(GameState::COUNTDOWN, start_game_rng);
+    }
+}
+
+// Every random decision in the game goes through this so a seed reproduces a run.
+// Each game is reseeded from the base seed so a single game can be replayed alone.
+pub struct GameRng {
+    seed: u64,
+    game_seed: u64,
+    games_started: u64,
+    rng: StdRng
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            game_seed: seed,
+            games_started: 0,
+            rng: StdRng::seed_from_u64(seed)
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn game_seed(&self) -> u64 {
+        self.game_seed
+    }
+
+    pub fn start_game(&mut self) {
+        self.game_seed = self.seed.wrapping_add(self.games_started);
+        self.games_started += 1;
+        self.rng = StdRng::seed_from_u64(self.game_seed);
     }
 }
 
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+fn start_game_rng(mut rng: ResMut<GameRng>) {
+    rng.start_game();
+    info!("game seed {}", rng.game_seed());
+}
 
 struct GameOverTimer(Timer);
 
+pub struct CountdownTimer(Timer);
+
+impl CountdownTimer {
+    // Whole seconds left, rounded up, for a 3-2-1 display
+    pub fn seconds_left(&self) -> u32 {
+        (self.0.duration().as_secs_f32() - self.0.elapsed_secs()).max(0.).ceil() as u32
+    }
+}
+
+fn start_countdown(mut timer: ResMut<CountdownTimer>) {
+    timer.0.reset();
+}
+
+fn tick_countdown(
+    time: Res<Time>,
+    mut timer: ResMut<CountdownTimer>,
+    mut commands: Commands
+) {
+    if timer.0.tick(time.delta()).finished() {
+        commands.insert_resource(NextState(GameState::RUNNING));
+    }
+}
+
 fn start_game_over_timer(mut timer: ResMut<GameOverTimer>) {
     timer.0.reset();
 }
@@ -71,7 +290,7 @@ fn start_new_game(
     mut commands: Commands
 ) {
     if timer.0.tick(time.delta()).just_finished() {
-        commands.insert_resource(NextState(GameState::RUNNING));
+        commands.insert_resource(NextState(GameState::COUNTDOWN));
     }
 }
 
@@ -81,12 +300,22 @@ mod tests {
     use super::*;
     use std::{thread, time::Duration};
 
-    #[test]
-    fn switch_from_dead_to_running_after_time() {
+    fn init_states(game_over_pause_sec: f32, countdown_sec: f32, initial_state: GameState) -> App {
         let mut app = App::default();
         app.add_plugins(MinimalPlugins);
-        app.add_plugin( GameStatePlugin{ tick_time_sec: 1.0, game_over_pause_sec: 1.0 });
+        app.add_plugin(GameStatePlugin{
+            tick_time_sec: 1.0,
+            game_over_pause_sec,
+            countdown_sec,
+            initial_state
+        });
         app.update(); // setup initial state
+        app
+    }
+
+    #[test]
+    fn switch_from_dead_to_countdown_after_time() {
+        let mut app = init_states(1.0, 100.0, GameState::RUNNING);
         assert_eq!(app.world.resource::<CurrentState<GameState>>().0, GameState::RUNNING);
         app.world.insert_resource(NextState(GameState::DEAD));
         app.update(); // process state change
@@ -97,9 +326,129 @@ mod tests {
         thread::sleep(Duration::from_millis(500));
         app.update(); // tick + complete timer
         app.update(); // process state change
+        assert_eq!(app.world.resource::<CurrentState<GameState>>().0, GameState::COUNTDOWN);
+    }
+
+    #[test]
+    fn switch_from_countdown_to_running_after_time() {
+        let mut app = init_states(100.0, 0.5, GameState::COUNTDOWN);
+        assert_eq!(app.world.resource::<CurrentState<GameState>>().0, GameState::COUNTDOWN);
+        assert_eq!(app.world.resource::<CountdownTimer>().seconds_left(), 1);
+        thread::sleep(Duration::from_millis(500));
+        app.update(); // tick + complete timer
+        assert_eq!(app.world.resource::<CountdownTimer>().seconds_left(), 0);
+        app.update(); // process state change
+        assert_eq!(app.world.resource::<CurrentState<GameState>>().0, GameState::RUNNING);
+    }
+
+    #[test]
+    fn pause_returns_to_paused_state() {
+        let mut pause = Pause::default();
+        assert_eq!(pause.toggle(&GameState::COUNTDOWN), None);
+        assert_eq!(pause.toggle(&GameState::DEAD), Some(GameState::PAUSED));
+        assert!(!pause.is_resuming());
+        assert_eq!(pause.toggle(&GameState::PAUSED), Some(GameState::DEAD));
+        assert!(pause.is_resuming());
+        assert_eq!(pause.toggle(&GameState::RUNNING), Some(GameState::PAUSED));
+        assert_eq!(pause.toggle(&GameState::PAUSED), Some(GameState::RUNNING));
+    }
+
+    #[test]
+    fn pause_freezes_game_over_timer() {
+        let mut app = init_states(0.6, 100.0, GameState::RUNNING);
+        app.world.insert_resource(NextState(GameState::DEAD));
+        app.update();
+        thread::sleep(Duration::from_millis(400));
+        app.update();
+
+        let paused = app.world.resource_mut::<Pause>().toggle(&GameState::DEAD).unwrap();
+        app.world.insert_resource(NextState(paused));
+        app.update();
+        thread::sleep(Duration::from_millis(400));
+        app.update();
+        assert_eq!(app.world.resource::<CurrentState<GameState>>().0, GameState::PAUSED);
+
+        let resumed = app.world.resource_mut::<Pause>().toggle(&GameState::PAUSED).unwrap();
+        app.world.insert_resource(NextState(resumed));
+        app.update(); // resume without restarting the timer
+        assert_eq!(app.world.resource::<CurrentState<GameState>>().0, GameState::DEAD);
+        assert!(!app.world.resource::<Pause>().is_resuming());
+        thread::sleep(Duration::from_millis(300));
+        app.update(); // tick + complete timer
+        app.update(); // process state change
+        assert_eq!(app.world.resource::<CurrentState<GameState>>().0, GameState::COUNTDOWN);
+    }
+
+    #[test]
+    fn countdown_seconds_left_rounds_up() {
+        let mut timer = CountdownTimer(Timer::from_seconds(3.0, false));
+        assert_eq!(timer.seconds_left(), 3);
+        timer.0.tick(Duration::from_millis(1));
+        assert_eq!(timer.seconds_left(), 3);
+        timer.0.tick(Duration::from_millis(1000));
+        assert_eq!(timer.seconds_left(), 2);
+        timer.0.tick(Duration::from_millis(2000));
+        assert_eq!(timer.seconds_left(), 0);
+    }
+
+    #[test]
+    fn zero_countdown_starts_running_immediately() {
+        let mut app = init_states(100.0, 0.0, GameState::COUNTDOWN);
+        app.update();
         assert_eq!(app.world.resource::<CurrentState<GameState>>().0, GameState::RUNNING);
     }
 
+    #[test]
+    fn game_rng_same_seed_same_sequence() {
+        let mut a = GameRng::new(1234);
+        let mut b = GameRng::new(1234);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(GameRng::new(1).next_u64(), GameRng::new(2).next_u64());
+    }
+
+    #[test]
+    fn game_rng_reseeds_each_game() {
+        let mut rng = GameRng::new(50);
+        rng.start_game();
+        assert_eq!(rng.game_seed(), 50);
+        let first_game = rng.next_u64();
+        rng.start_game();
+        assert_eq!(rng.game_seed(), 51);
+        assert_eq!(rng.seed(), 50);
+        assert_ne!(rng.next_u64(), first_game);
+
+        let mut replayed = GameRng::new(51);
+        replayed.start_game();
+        assert_eq!(replayed.next_u64(), GameRng::new(51).next_u64());
+    }
+
+    #[test]
+    fn game_rng_plugin_starts_game_on_running() {
+        let mut app = App::default();
+        app.add_plugins(MinimalPlugins);
+        app.add_plugin(GameStatePlugin{
+            tick_time_sec: 1.0,
+            game_over_pause_sec: 1.0,
+            countdown_sec: 1.0,
+            initial_state: GameState::COUNTDOWN
+        });
+        app.add_plugin(GameRngPlugin{ seed: 7 });
+        app.update();
+        let mut expected = GameRng::new(7);
+        expected.start_game();
+        assert_eq!(app.world.resource_mut::<GameRng>().next_u64(), expected.next_u64());
+    }
+
+    #[test]
+    fn direction_opposite_and_perpendicular() {
+        assert_eq!(Direction::Up.opposite(), Direction::Down);
+        assert_eq!(Direction::Left.opposite(), Direction::Right);
+        assert_eq!(Direction::Down.perpendicular(), [Direction::Left, Direction::Right]);
+        assert_eq!(Direction::Right.perpendicular(), [Direction::Up, Direction::Down]);
+    }
+
     #[test]
     fn grid_pos_set_sets_all_fields() {
         let mut grid_pos = GridPosition{x: 111, y: 222};
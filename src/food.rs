@@ -1,19 +1,45 @@
 use rand::prelude::*;
 use bevy::prelude::*;
+use bevy::utils::HashSet;
 use bevy::sprite::Anchor;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
 use crate::game_board::board;
-use crate::core::GridPosition;
+use crate::core::{GameRng, GameState, GridPosition, PlayerId};
+use crate::tick::AppTickExt;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct InitParams{
-    pub start_position: GridPosition
+    pub start_position: GridPosition,
+    #[serde(default)]
+    pub spawn_points: Vec<GridPosition>
+}
+
+impl InitParams {
+    // Food respawns on a free spawn point when the level has any, anywhere otherwise
+    pub fn next_position(
+        &self,
+        game_board: &board::Desc,
+        disallowed_positions: &[GridPosition],
+        rng: &mut impl Rng
+    ) -> Result<GridPosition, String> {
+        let free_spawn_points = self.spawn_points
+            .iter()
+            .filter(|pos| !disallowed_positions.contains(pos) && !game_board.is_obstacle(pos))
+            .collect::<Vec<&GridPosition>>();
+        match free_spawn_points.choose(rng) {
+            Some(pos) => Ok(**pos),
+            None => find_next_position(game_board, disallowed_positions, rng)
+        }
+    }
 }
 
 #[derive(Component)]
 pub struct FoodComponent;
 
 pub struct ConsumeEvent{
-    pub(crate) target: Entity
+    pub(crate) target: Entity,
+    pub(crate) eater: PlayerId
 }
 
 pub struct FoodPlugin{
@@ -26,16 +52,20 @@ impl Plugin for FoodPlugin {
         app
             .insert_resource(self.init_params.clone())
             .add_event::<ConsumeEvent>()
-            .add_startup_system(init_food)
-            .add_system(consume_food);
+            .add_enter_system(GameState::COUNTDOWN, init_food)
+            .add_tick_system(consume_food.after("follow"));
     }
 }
 
 fn init_food(
     init_data: Res<InitParams>,
     game_board: Res<board::Desc>,
+    existing_food: Query<Entity, With<FoodComponent>>,
     mut commands: Commands
 ) {
+    for food in existing_food.iter() {
+        commands.entity(food).despawn();
+    }
     spawn_food(
         init_data.start_position.clone(),
         &game_board,
@@ -55,6 +85,7 @@ fn spawn_food(
     commands
         .spawn()
         .insert(FoodComponent)
+        .insert(grid_position)
         .insert_bundle( SpriteBundle {
             transform: Transform { translation, ..default() },
             sprite: Sprite {
@@ -70,26 +101,31 @@ fn spawn_food(
 }
 
 fn consume_food(
+    init_data: Res<InitParams>,
     game_board: Res<board::Desc>,
+    mut rng: ResMut<GameRng>,
     query: Query<Entity, With<FoodComponent>>,
-    all_transforms: Query<&Transform>,
+    all_grid_positions: Query<&GridPosition>,
     mut consume_events: EventReader<ConsumeEvent>,
     mut commands: Commands
 ) {
-    consume_events.iter().enumerate().for_each(|(_, event)|{
+    // heads that reach the same food together only eat it once
+    let mut eaten = HashSet::new();
+    consume_events.iter().filter(|event| eaten.insert(event.target)).for_each(|event|{
         match query.iter().find(|food|{*food == event.target}) {
             None => {}
             Some(food) => {
-                println!("food consumed id:{:?}", food);
+                debug!("food consumed id:{:?}", food);
                 commands.entity(food).despawn();
-                if let Ok(next_position) = find_next_position(
+                if let Ok(next_position) = init_data.next_position(
                     &game_board,
-                    &all_transforms
+                    &all_grid_positions
                         .iter()
-                        .map(|t|{ game_board.world_pos_to_grid_pos(&t.translation) })
-                        .collect::<Vec<GridPosition>>()
+                        .copied()
+                        .collect::<Vec<GridPosition>>(),
+                    rng.as_mut()
                 ) {
-                    println!("spawn food at {:?}", next_position);
+                    debug!("spawn food at {:?}", next_position);
                     spawn_food(next_position, &game_board, &mut commands);
                 }
             }
@@ -98,21 +134,27 @@ fn consume_food(
 }
 
 
-fn find_next_position(
+pub fn find_next_position(
     game_board: &board::Desc,
-    disallowed_positions: &[GridPosition]
+    disallowed_positions: &[GridPosition],
+    rng: &mut impl Rng
 ) -> Result<GridPosition, String> {
-    let max_positions = game_board.grid_size.0 * game_board.grid_size.1;
-    if disallowed_positions.len() >= max_positions as usize {
+    let is_allowed = |pos: &GridPosition| {
+        !disallowed_positions.contains(pos) && !game_board.is_obstacle(pos)
+    };
+    let any_allowed = (0..game_board.grid_size.1)
+        .flat_map(|y| (0..game_board.grid_size.0).map(move |x| GridPosition{x, y}))
+        .any(|pos| is_allowed(&pos));
+    if !any_allowed {
         return Err(String::from("All positions disallowed"));
     }
 
     loop {
         let pos = GridPosition{
-            x: random::<i32>().abs() % game_board.grid_size.0,
-            y: random::<i32>().abs() % game_board.grid_size.1,
+            x: rng.gen_range(0..game_board.grid_size.0),
+            y: rng.gen_range(0..game_board.grid_size.1),
         };
-        if !disallowed_positions.contains(&pos) {
+        if is_allowed(&pos) {
             return Ok(pos);
         }
     }
@@ -122,18 +164,26 @@ fn find_next_position(
 #[cfg(test)]
 mod tests {
     use bevy::ecs::event::Events;
+    use crate::tick::TickClock;
     use super::*;
 
     fn init_plugin() -> App {
         let mut app = App::default();
+        app.add_loopless_state(GameState::COUNTDOWN);
+        app.insert_resource(NextState(GameState::RUNNING));
+        app.insert_resource(TickClock::unpaced());
         let board = board::Desc {
             grid_size: (5, 5),
-            cell_size: 10
+            cell_size: 10,
+            boundary: board::BoundaryMode::Wrap,
+            obstacles: Vec::new()
         };
         app.world.insert_resource(board);
+        app.world.insert_resource(GameRng::new(0));
         let food_plugin = FoodPlugin{
             init_params: InitParams{
-                start_position: GridPosition {x:0, y:0}
+                start_position: GridPosition {x:0, y:0},
+                spawn_points: Vec::new()
             }
         };
         food_plugin.build(&mut app);
@@ -154,19 +204,22 @@ mod tests {
         let food_entity = get_food_entity(app);
         app.world.resource_mut::<Events<ConsumeEvent>>().send(
             ConsumeEvent{
-                target: food_entity
+                target: food_entity,
+                eater: PlayerId(0)
             });
         app.update();
     }
 
     #[test]
     fn find_next_position_fills_all() {
-        let board = board::Desc{ grid_size: (2, 2), cell_size: 1 };
+        let board = board::Desc{ grid_size: (2, 2), cell_size: 1, boundary: board::BoundaryMode::Wrap, obstacles: Vec::new() };
+        let mut rng = GameRng::new(random());
         let mut picked_positions = Vec::new();
         for _ in std::iter::repeat(()).take(4) {
             picked_positions.push(find_next_position(
                 &board,
-                &picked_positions
+                &picked_positions,
+                &mut rng
             ).unwrap());
         }
         assert_eq!(picked_positions.len(), 4);
@@ -178,27 +231,71 @@ mod tests {
 
     #[test]
     fn find_next_position_fails_eventually() {
-        let board = board::Desc{ grid_size: (2, 2), cell_size: 1 };
+        let board = board::Desc{ grid_size: (2, 2), cell_size: 1, boundary: board::BoundaryMode::Wrap, obstacles: Vec::new() };
+        let mut rng = GameRng::new(random());
         let mut picked_positions = Vec::new();
         for _ in std::iter::repeat(()).take(4) {
             picked_positions.push(find_next_position(
                 &board,
-                &picked_positions
+                &picked_positions,
+                &mut rng
             ).unwrap());
         }
 
         assert_eq!(
-            find_next_position(&board, &picked_positions),
+            find_next_position(&board, &picked_positions, &mut rng),
             Err(String::from("All positions disallowed"))
         );
     }
 
+    #[test]
+    fn find_next_position_avoids_obstacles() {
+        let board = board::Desc{
+            grid_size: (2, 2),
+            cell_size: 1,
+            boundary: board::BoundaryMode::Wrap,
+            obstacles: vec![GridPosition{x:0, y:0}, GridPosition{x:1, y:1}]
+        };
+        let mut rng = GameRng::new(random());
+        for _ in 0..20 {
+            assert_eq!(
+                find_next_position(&board, &[GridPosition{x:1, y:0}], &mut rng),
+                Ok(GridPosition{x:0, y:1})
+            );
+        }
+        assert!(find_next_position(
+            &board,
+            &[GridPosition{x:1, y:0}, GridPosition{x:0, y:1}],
+            &mut rng
+        ).is_err());
+    }
+
+    #[test]
+    fn next_position_prefers_free_spawn_points() {
+        let board = board::Desc{ grid_size: (5, 5), cell_size: 1, boundary: board::BoundaryMode::Wrap, obstacles: Vec::new() };
+        let params = InitParams {
+            start_position: GridPosition{x:0, y:0},
+            spawn_points: vec![GridPosition{x:0, y:0}, GridPosition{x:4, y:4}]
+        };
+        let mut rng = GameRng::new(random());
+        for _ in 0..20 {
+            assert_eq!(
+                params.next_position(&board, &[GridPosition{x:0, y:0}], &mut rng),
+                Ok(GridPosition{x:4, y:4})
+            );
+        }
+        let taken = params.spawn_points.clone();
+        let next_position = params.next_position(&board, &taken, &mut rng).unwrap();
+        assert!(!taken.contains(&next_position));
+    }
+
     #[test]
     fn find_next_position_always_within_board() {
-        let board = board::Desc{ grid_size: (30, 20), cell_size: 1 };
+        let board = board::Desc{ grid_size: (30, 20), cell_size: 1, boundary: board::BoundaryMode::Wrap, obstacles: Vec::new() };
+        let mut rng = GameRng::new(random());
         let mut picked_positions = Vec::new();
         while let Ok(next_position) =
-            find_next_position(&board, &picked_positions) {
+            find_next_position(&board, &picked_positions, &mut rng) {
             assert!(next_position.x >= 0);
             assert!(next_position.x < board.grid_size.0);
             assert!(next_position.y >= 0);
@@ -208,6 +305,20 @@ mod tests {
         assert_eq!(picked_positions.len(), (board.grid_size.0 * board.grid_size.1) as usize);
     }
 
+    #[test]
+    fn find_next_position_same_seed_same_positions() {
+        let board = board::Desc{ grid_size: (15, 15), cell_size: 1, boundary: board::BoundaryMode::Wrap, obstacles: Vec::new() };
+        let seed = random();
+        let mut first_rng = GameRng::new(seed);
+        let mut second_rng = GameRng::new(seed);
+        for _ in 0..50 {
+            assert_eq!(
+                find_next_position(&board, &[], &mut first_rng),
+                find_next_position(&board, &[], &mut second_rng)
+            );
+        }
+    }
+
     #[test]
     fn spawn_food_on_start() {
         let mut app = init_plugin();
@@ -218,13 +329,40 @@ mod tests {
         assert_eq!(food_count, 1);
     }
 
+    #[test]
+    fn food_has_grid_position() {
+        let mut app = init_plugin();
+        let grid_pos = app.world
+            .query_filtered::<&GridPosition, With<FoodComponent>>()
+            .iter(&app.world)
+            .next()
+            .cloned();
+        assert_eq!(grid_pos, Some(GridPosition{x: 0, y: 0}));
+    }
+
+    #[test]
+    fn food_reset_on_new_game() {
+        let mut app = init_plugin();
+        consume_food(&mut app);
+        app.world.insert_resource(NextState(GameState::DEAD));
+        app.update();
+        app.world.insert_resource(NextState(GameState::COUNTDOWN));
+        app.update();
+        let grid_positions = app.world
+            .query_filtered::<&GridPosition, With<FoodComponent>>()
+            .iter(&app.world)
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(grid_positions, vec![GridPosition{x: 0, y: 0}]);
+    }
+
     #[test]
     fn spawn_food_on_consume() {
         init_plugin();
         let mut app = init_plugin();
         let food_before = get_food_entity(&mut app);
         app.world.resource_mut::<Events<ConsumeEvent>>().send(
-            ConsumeEvent{ target: food_before }
+            ConsumeEvent{ target: food_before, eater: PlayerId(0) }
         );
         app.update();
         let food_after = get_food_entity(&mut app);
@@ -247,13 +385,31 @@ mod tests {
         assert_eq!(food_count, 1);
     }
 
+    #[test]
+    fn food_eaten_twice_in_a_tick_spawns_once() {
+        let mut app = init_plugin();
+        let food_before = get_food_entity(&mut app);
+        for player in 0..2 {
+            app.world.resource_mut::<Events<ConsumeEvent>>().send(
+                ConsumeEvent{ target: food_before, eater: PlayerId(player) }
+            );
+        }
+        app.update();
+        let food = app.world
+            .query_filtered::<Entity, With<FoodComponent>>()
+            .iter(&app.world)
+            .collect::<Vec<_>>();
+        assert_eq!(food.len(), 1);
+        assert_ne!(food[0], food_before);
+    }
+
     #[test]
     fn consume_food_checks_correct_target() {
         let mut app = init_plugin();
         let food_before = get_food_entity(&mut app);
         let not_a_real_entity = Entity::from_raw(random::<u32>());
         app.world.resource_mut::<Events<ConsumeEvent>>().send(
-            ConsumeEvent{ target: not_a_real_entity }
+            ConsumeEvent{ target: not_a_real_entity, eater: PlayerId(0) }
         );
         app.update();
         let food_after = get_food_entity(&mut app);
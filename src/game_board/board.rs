@@ -1,20 +1,82 @@
 use bevy::prelude::*;
+use bevy::sprite::Anchor;
 use bevy_prototype_lyon::prelude::*;
-use crate::GridPosition;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use crate::core::{checksum, Direction, GridPosition};
+use super::helpers::{move_grid_position, offset_grid_position};
 
-#[derive(Clone)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum BoundaryMode {
+    #[default]
+    Wrap,
+    Solid,
+    Reflect
+}
+
+impl FromStr for BoundaryMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "wrap" => Ok(BoundaryMode::Wrap),
+            "solid" => Ok(BoundaryMode::Solid),
+            "reflect" => Ok(BoundaryMode::Reflect),
+            _ => Err(format!("unknown boundary mode '{}', expected wrap, solid or reflect", s))
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Desc {
     pub grid_size: (i32, i32),
     pub cell_size: i32,
+    #[serde(default)]
+    pub boundary: BoundaryMode,
+    #[serde(default)]
+    pub obstacles: Vec<GridPosition>,
 }
 
+#[derive(Component)]
+pub struct Obstacle;
+
 impl Desc {
+    pub fn is_inside(&self, grid_pos: &GridPosition) -> bool {
+        grid_pos.x >= 0 && grid_pos.x < self.grid_size.0 &&
+            grid_pos.y >= 0 && grid_pos.y < self.grid_size.1
+    }
+
+    // None when the step would leave a board that doesn't wrap
+    pub fn step(&self, grid_pos: GridPosition, direction: Direction) -> Option<GridPosition> {
+        match self.boundary {
+            BoundaryMode::Wrap => Some(move_grid_position(grid_pos, direction, self.grid_size)),
+            BoundaryMode::Solid | BoundaryMode::Reflect => {
+                Some(offset_grid_position(grid_pos, direction))
+                    .filter(|moved| self.is_inside(moved))
+            }
+        }
+    }
+
+    pub fn is_obstacle(&self, grid_pos: &GridPosition) -> bool {
+        self.obstacles.contains(grid_pos)
+    }
+
+    // Identifies the obstacle layout whatever order the obstacles are listed in.
+    pub fn obstacle_hash(&self) -> u64 {
+        let mut obstacles = self.obstacles.iter().map(|grid_pos| (grid_pos.x, grid_pos.y)).collect::<Vec<_>>();
+        obstacles.sort();
+        obstacles.dedup();
+        checksum(obstacles.iter().flat_map(|&(x, y)| [x as i64, y as i64]))
+    }
+
     pub fn grid_pos_to_world_pos(&self, grid_pos: &GridPosition) -> Vec3 {
-        Vec3::new(
-            (grid_pos.x * self.cell_size) as f32,
-            -(grid_pos.y * self.cell_size) as f32,
-            0.
-        )
+        self.grid_coords_to_world_pos(grid_pos.x as f32, grid_pos.y as f32)
+    }
+
+    // Same as grid_pos_to_world_pos, for points between cell centres.
+    pub fn grid_coords_to_world_pos(&self, x: f32, y: f32) -> Vec3 {
+        let cell_size = self.cell_size as f32;
+        Vec3::new(x * cell_size, -y * cell_size, 0.)
     }
     pub fn world_pos_to_grid_pos(&self, translation: &Vec3) -> GridPosition {
         GridPosition {
@@ -41,6 +103,30 @@ pub fn spawn_camera(game_board: Res<Desc>, mut commands: Commands) {
     commands.spawn_bundle(camera);
 }
 
+pub fn spawn_obstacles(game_board: Res<Desc>, mut commands: Commands) {
+    for grid_pos in game_board.obstacles.iter() {
+        commands
+            .spawn()
+            .insert(Obstacle)
+            .insert(*grid_pos)
+            .insert_bundle(SpriteBundle {
+                transform: Transform {
+                    translation: game_board.grid_pos_to_world_pos(grid_pos),
+                    ..default()
+                },
+                sprite: Sprite {
+                    color: Color::GRAY,
+                    custom_size: Some(Vec2::new(
+                        game_board.cell_size as f32,
+                        game_board.cell_size as f32)),
+                    anchor: Anchor::TopLeft,
+                    ..default()
+                },
+                ..default()
+            });
+    }
+}
+
 pub fn draw_origin(game_board: Res<Desc>, mut commands: Commands) {
     let width = (game_board.grid_size.0 * game_board.cell_size) as f32;
     for i in 0..game_board.grid_size.0 + 1 {
@@ -81,15 +167,17 @@ mod tests {
 
     #[test]
     fn world_dimensions() {
-        assert_eq!(Desc{ grid_size: (5, 5), cell_size: 10 }.world_dimensions(), (50.0, 50.0));
-        assert_eq!(Desc{ grid_size: (15, 8), cell_size: 18 }.world_dimensions(), (270.0, 144.0));
+        assert_eq!(Desc{ grid_size: (5, 5), cell_size: 10, boundary: BoundaryMode::Wrap, obstacles: Vec::new() }.world_dimensions(), (50.0, 50.0));
+        assert_eq!(Desc{ grid_size: (15, 8), cell_size: 18, boundary: BoundaryMode::Wrap, obstacles: Vec::new() }.world_dimensions(), (270.0, 144.0));
     }
 
     #[test]
     fn grid_pos_to_world_pos() {
         let board = Desc{
             grid_size: (5, 5),
-            cell_size: 10
+            cell_size: 10,
+            boundary: BoundaryMode::Wrap,
+            obstacles: Vec::new()
         };
 
         assert_eq!(board.grid_pos_to_world_pos(&GridPosition{x: 5, y: 2}), Vec3::new(50., -20., 0.));
@@ -97,7 +185,9 @@ mod tests {
 
         let board = Desc{
             grid_size: (5, 5),
-            cell_size: 8
+            cell_size: 8,
+            boundary: BoundaryMode::Wrap,
+            obstacles: Vec::new()
         };
 
         assert_eq!(board.grid_pos_to_world_pos(&GridPosition{x: 5, y: 2}), Vec3::new(40., -16., 0.));
@@ -108,7 +198,9 @@ mod tests {
     fn world_pos_to_grid_pos() {
         let board = Desc {
             grid_size: (5, 5),
-            cell_size: 10
+            cell_size: 10,
+            boundary: BoundaryMode::Wrap,
+            obstacles: Vec::new()
         };
 
         assert_eq!(board.world_pos_to_grid_pos(&Vec3::new(50., -20., 0.)), GridPosition{x: 5, y: 2});
@@ -116,19 +208,55 @@ mod tests {
 
         let board = Desc{
             grid_size: (5, 5),
-            cell_size: 8
+            cell_size: 8,
+            boundary: BoundaryMode::Wrap,
+            obstacles: Vec::new()
         };
 
         assert_eq!(board.world_pos_to_grid_pos(&Vec3::new(40., -16., 0.)), GridPosition{x: 5, y: 2});
         assert_eq!(board.world_pos_to_grid_pos(&Vec3::new(56., 16., 0.)), GridPosition{x: 7, y: 2});
     }
 
+    #[test]
+    fn is_inside() {
+        let board = Desc { grid_size: (5, 3), cell_size: 10, boundary: BoundaryMode::Solid, obstacles: Vec::new() };
+        assert!(board.is_inside(&GridPosition::new(0, 0)));
+        assert!(board.is_inside(&GridPosition::new(4, 2)));
+        assert!(!board.is_inside(&GridPosition::new(5, 2)));
+        assert!(!board.is_inside(&GridPosition::new(4, 3)));
+        assert!(!board.is_inside(&GridPosition::new(-1, -1)));
+    }
+
+    #[test]
+    fn step_wraps_only_in_wrap_mode() {
+        let mut board = Desc { grid_size: (5, 5), cell_size: 10, boundary: BoundaryMode::Wrap, obstacles: Vec::new() };
+        assert_eq!(board.step(GridPosition::new(4, 2), Direction::Right), Some(GridPosition::new(0, 2)));
+        assert_eq!(board.step(GridPosition::new(3, 2), Direction::Right), Some(GridPosition::new(4, 2)));
+
+        for boundary in [BoundaryMode::Solid, BoundaryMode::Reflect] {
+            board.boundary = boundary;
+            assert_eq!(board.step(GridPosition::new(4, 2), Direction::Right), None);
+            assert_eq!(board.step(GridPosition::new(2, 0), Direction::Up), None);
+            assert_eq!(board.step(GridPosition::new(3, 2), Direction::Right), Some(GridPosition::new(4, 2)));
+        }
+    }
+
+    #[test]
+    fn boundary_mode_from_str() {
+        assert_eq!("wrap".parse(), Ok(BoundaryMode::Wrap));
+        assert_eq!("Solid".parse(), Ok(BoundaryMode::Solid));
+        assert_eq!("REFLECT".parse(), Ok(BoundaryMode::Reflect));
+        assert!("bouncy".parse::<BoundaryMode>().is_err());
+    }
+
     #[test]
     fn spawn_camera() {
         let mut app = App::default();
         let board = Desc {
             grid_size: (5, 5),
-            cell_size: 10
+            cell_size: 10,
+            boundary: BoundaryMode::Wrap,
+            obstacles: Vec::new()
         };
         app.world.insert_resource(board);
         app.add_system(super::spawn_camera);
@@ -141,12 +269,37 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn spawn_obstacles() {
+        let mut app = App::default();
+        app.world.insert_resource(Desc {
+            grid_size: (5, 5),
+            cell_size: 10,
+            boundary: BoundaryMode::Wrap,
+            obstacles: vec![GridPosition::new(1, 1), GridPosition::new(3, 2)]
+        });
+        app.add_system(super::spawn_obstacles);
+        app.update();
+        let mut obstacles = app.world
+            .query_filtered::<(&GridPosition, &Transform), With<Obstacle>>()
+            .iter(&app.world)
+            .map(|(grid_pos, transform)| (*grid_pos, transform.translation))
+            .collect::<Vec<_>>();
+        obstacles.sort_by_key(|(grid_pos, _)| grid_pos.x);
+        assert_eq!(obstacles, vec![
+            (GridPosition::new(1, 1), Vec3::new(10., -10., 0.)),
+            (GridPosition::new(3, 2), Vec3::new(30., -20., 0.)),
+        ]);
+    }
+
     #[test]
     fn set_camera_pos() {
         let mut camera_transform = Transform::default();
         let board = Desc {
             grid_size: (5, 5),
-            cell_size: 10
+            cell_size: 10,
+            boundary: BoundaryMode::Wrap,
+            obstacles: Vec::new()
         };
         super::set_camera_pos(&mut camera_transform, &board);
         assert_eq!(
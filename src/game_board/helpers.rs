@@ -1,10 +1,9 @@
 use crate::core::Direction;
-use crate::GridPosition;
+use crate::core::GridPosition;
 
-pub fn move_grid_position(
+pub fn offset_grid_position(
     mut grid_pos: GridPosition,
-    direction: Direction,
-    grid_size: (i32, i32)) -> GridPosition {
+    direction: Direction) -> GridPosition {
 
     match direction {
         Direction::Up => grid_pos.y -= 1,
@@ -12,6 +11,15 @@ pub fn move_grid_position(
         Direction::Left => grid_pos.x -= 1,
         Direction::Right => grid_pos.x += 1
     }
+    grid_pos
+}
+
+pub fn move_grid_position(
+    grid_pos: GridPosition,
+    direction: Direction,
+    grid_size: (i32, i32)) -> GridPosition {
+
+    let mut grid_pos = offset_grid_position(grid_pos, direction);
 
     // Wrap Around
     grid_pos.x = (grid_pos.x + grid_size.0) % grid_size.0;
@@ -19,6 +27,17 @@ pub fn move_grid_position(
     grid_pos
 }
 
+// Direction of a single unwrapped step from one cell to a neighbouring one
+pub fn direction_between(from: &GridPosition, to: &GridPosition) -> Option<Direction> {
+    match (to.x - from.x, to.y - from.y) {
+        (0, -1) => Some(Direction::Up),
+        (0, 1) => Some(Direction::Down),
+        (-1, 0) => Some(Direction::Left),
+        (1, 0) => Some(Direction::Right),
+        _ => None
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -52,6 +71,25 @@ mod tests {
 
     }
 
+    #[test]
+    fn offset_grid_position_does_not_wrap() {
+        assert_eq!(offset_grid_position(GridPosition{x: 0, y: 0}, Direction::Up), GridPosition{x: 0, y: -1});
+        assert_eq!(offset_grid_position(GridPosition{x: 0, y: 0}, Direction::Left), GridPosition{x: -1, y: 0});
+        assert_eq!(offset_grid_position(GridPosition{x: 4, y: 4}, Direction::Down), GridPosition{x: 4, y: 5});
+        assert_eq!(offset_grid_position(GridPosition{x: 4, y: 4}, Direction::Right), GridPosition{x: 5, y: 4});
+    }
+
+    #[test]
+    fn direction_between_neighbours() {
+        let from = GridPosition{x: 2, y: 2};
+        assert_eq!(direction_between(&from, &GridPosition{x: 2, y: 1}), Some(Direction::Up));
+        assert_eq!(direction_between(&from, &GridPosition{x: 2, y: 3}), Some(Direction::Down));
+        assert_eq!(direction_between(&from, &GridPosition{x: 1, y: 2}), Some(Direction::Left));
+        assert_eq!(direction_between(&from, &GridPosition{x: 3, y: 2}), Some(Direction::Right));
+        assert_eq!(direction_between(&from, &GridPosition{x: 3, y: 3}), None);
+        assert_eq!(direction_between(&from, &from), None);
+    }
+
     #[test]
     fn move_grid_position_wrap() {
         assert_eq!(move_grid_position(
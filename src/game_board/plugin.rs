@@ -12,6 +12,7 @@ impl Plugin for GameBoardPlugin {
             .add_plugin(ShapePlugin)
             .add_startup_system(board::spawn_camera)
             .insert_resource(self.desc.clone())
-            .add_startup_system(board::draw_origin);
+            .add_startup_system(board::draw_origin)
+            .add_startup_system(board::spawn_obstacles);
     }
 }
\ No newline at end of file
@@ -0,0 +1,372 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::core::{not_resuming, GameState, GridPosition};
+use crate::game_board::board;
+use crate::score::Score;
+use crate::snake;
+use crate::speed::SpeedCurve;
+
+pub const DEFAULT_MAX_ENTRIES: usize = 10;
+
+// Everything about a game that makes scoring easier or harder.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Configuration {
+    pub grid_size: (i32, i32),
+    // the starting tick rate, the speed curve says how it changes
+    pub tick_ms: u64,
+    pub speed_curve: SpeedCurve,
+    pub boundary: board::BoundaryMode,
+    pub obstacles: u64
+}
+
+impl Configuration {
+    pub fn new(game_board: &board::Desc, snake_params: &snake::helpers::InitParams) -> Self {
+        Configuration {
+            grid_size: game_board.grid_size,
+            tick_ms: snake_params.movement_time_step.as_millis() as u64,
+            speed_curve: snake_params.speed_curve,
+            boundary: game_board.boundary,
+            obstacles: game_board.obstacle_hash()
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct HighScoreEntry {
+    pub score: u32,
+    pub length: usize,
+    pub date: String,
+    #[serde(flatten)]
+    pub configuration: Configuration,
+}
+
+// Entries for every configuration live in one file, but each configuration
+// keeps its own top N so scores are only ranked against like.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    pub entries: Vec<HighScoreEntry>,
+}
+
+impl HighScores {
+    // A missing file is an empty table. An unreadable one is set aside as .bak so
+    // the next save doesn't silently destroy it.
+    pub fn load(path: &Path) -> HighScores {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(_) => return HighScores::default()
+        };
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            let backup = path.with_extension("json.bak");
+            warn!("high score file {} is corrupt ({}), moving it to {}", path.display(), e, backup.display());
+            if let Err(e) = fs::rename(path, &backup) {
+                warn!("failed to move corrupt high score file {}", e);
+            }
+            HighScores::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Best first, only entries recorded with the same configuration.
+    pub fn table(&self, configuration: &Configuration) -> Vec<&HighScoreEntry> {
+        let mut table = self.entries
+            .iter()
+            .filter(|entry| &entry.configuration == configuration)
+            .collect::<Vec<&HighScoreEntry>>();
+        table.sort_by(|a, b| b.score.cmp(&a.score).then(b.length.cmp(&a.length)));
+        table
+    }
+
+    // Returns the entry's rank, counting from 0, if it made its table's top `max_entries`.
+    pub fn insert(&mut self, entry: HighScoreEntry, max_entries: usize) -> Option<usize> {
+        let configuration = entry.configuration;
+        let rank = self
+            .table(&configuration)
+            .iter()
+            .position(|other| entry.score > other.score)
+            .unwrap_or_else(|| self.table(&configuration).len());
+        if rank >= max_entries {
+            return None;
+        }
+
+        self.entries.push(entry);
+        let kept = self.table(&configuration)
+            .into_iter()
+            .take(max_entries)
+            .cloned()
+            .collect::<Vec<HighScoreEntry>>();
+        self.entries.retain(|entry| entry.configuration != configuration);
+        self.entries.extend(kept);
+        Some(rank)
+    }
+}
+
+// $XDG_DATA_HOME/bevy_snake, falling back to ~/.local/share/bevy_snake, or
+// %APPDATA%\bevy_snake on Windows.
+pub fn default_path() -> Option<PathBuf> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(data_dir.join("bevy_snake").join("high_scores.json"))
+}
+
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs() / 86_400)
+        .unwrap_or_default();
+    date_from_unix_days(days as i64)
+}
+
+// Proleptic Gregorian calendar date as YYYY-MM-DD.
+fn date_from_unix_days(days: i64) -> String {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub struct HighScorePlugin {
+    pub path: PathBuf,
+    pub max_entries: usize
+}
+
+pub struct HighScoreFile {
+    pub path: PathBuf,
+    pub max_entries: usize
+}
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(HighScores::load(&self.path))
+            .insert_resource(HighScoreFile {
+                path: self.path.clone(),
+                max_entries: self.max_entries
+            })
+            .add_enter_system(GameState::DEAD, record_high_score.run_if(not_resuming));
+    }
+}
+
+fn record_high_score(
+    file: Res<HighScoreFile>,
+    mut high_scores: ResMut<HighScores>,
+    score: Res<Score>,
+    game_board: Res<board::Desc>,
+    snake_params: Res<snake::helpers::InitParams>,
+    snake_query: Query<&GridPosition, snake::helpers::WithAnySnakeType>
+) {
+    // the score is shared between players, so it doesn't belong in the table,
+    // and the table is for people rather than bots
+    if snake_params.player_count() > 1 || !snake_params.bots.is_empty() {
+        return;
+    }
+    let entry = HighScoreEntry {
+        score: score.value,
+        // segments still growing in wait off the board
+        length: snake_query.iter().filter(|grid_pos| game_board.is_inside(grid_pos)).count(),
+        date: today(),
+        configuration: Configuration::new(&game_board, &snake_params),
+    };
+    if let Some(rank) = high_scores.insert(entry, file.max_entries) {
+        info!("new high score #{}: {}", rank + 1, score.value);
+        if let Err(e) = high_scores.save(&file.path) {
+            warn!("failed to save high scores {}", e);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::core::{Direction, PlayerId};
+    use crate::snake::brain::BotKind;
+    use crate::snake::head::SnakeHead;
+    use crate::snake::tail::SnakeTail;
+    use super::*;
+
+    fn configuration(grid_size: (i32, i32)) -> Configuration {
+        Configuration { grid_size, tick_ms: 100, ..Default::default() }
+    }
+
+    fn entry(score: u32, configuration: Configuration) -> HighScoreEntry {
+        HighScoreEntry {
+            score,
+            length: score as usize + 4,
+            date: String::from("2024-01-01"),
+            configuration
+        }
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("bevy_snake_scores_{}", rand::random::<u32>()))
+            .join("high_scores.json")
+    }
+
+    #[test]
+    fn insert_keeps_top_entries_per_configuration() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.insert(entry(5, configuration((15, 15))), 3), Some(0));
+        assert_eq!(high_scores.insert(entry(9, configuration((15, 15))), 3), Some(0));
+        assert_eq!(high_scores.insert(entry(1, configuration((15, 15))), 3), Some(2));
+        assert_eq!(high_scores.insert(entry(0, configuration((15, 15))), 3), None);
+        assert_eq!(high_scores.insert(entry(7, configuration((15, 15))), 3), Some(1));
+        assert_eq!(high_scores.insert(entry(0, configuration((20, 20))), 3), Some(0));
+
+        let scores = high_scores.table(&configuration((15, 15)))
+            .iter()
+            .map(|entry| entry.score)
+            .collect::<Vec<u32>>();
+        assert_eq!(scores, vec![9, 7, 5]);
+        assert_eq!(high_scores.table(&configuration((20, 20))).len(), 1);
+        assert!(high_scores.table(&Configuration { tick_ms: 50, ..configuration((15, 15)) }).is_empty());
+    }
+
+    #[test]
+    fn boards_that_play_differently_keep_separate_tables() {
+        let wrap = configuration((15, 15));
+        let solid = Configuration { boundary: board::BoundaryMode::Solid, ..wrap };
+        let walled = Configuration {
+            obstacles: board::Desc {
+                grid_size: (15, 15),
+                cell_size: 10,
+                boundary: board::BoundaryMode::Wrap,
+                obstacles: vec![GridPosition::new(3, 3)]
+            }.obstacle_hash(),
+            ..wrap
+        };
+        let speeding_up = Configuration { speed_curve: SpeedCurve::Linear { step_ms: 2, min_ms: 50 }, ..wrap };
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.insert(entry(5, wrap), 1), Some(0));
+        assert_eq!(high_scores.insert(entry(1, solid), 1), Some(0));
+        assert_eq!(high_scores.insert(entry(1, walled), 1), Some(0));
+        assert_eq!(high_scores.insert(entry(1, speeding_up), 1), Some(0));
+        assert_eq!(high_scores.entries.len(), 4);
+        assert_eq!(high_scores.table(&wrap)[0].score, 5);
+        assert_eq!(high_scores.table(&solid)[0].score, 1);
+    }
+
+    #[test]
+    fn entries_from_before_configurations_still_load() {
+        let json = r#"{"entries":[{"score":3,"length":7,"date":"2024-01-01","grid_size":[15,15],"tick_ms":100}]}"#;
+        let high_scores: HighScores = serde_json::from_str(json).unwrap();
+        assert_eq!(high_scores.entries[0].configuration.grid_size, (15, 15));
+        assert_eq!(high_scores.entries[0].configuration.boundary, board::BoundaryMode::Wrap);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = temp_path();
+        let mut high_scores = HighScores::default();
+        high_scores.insert(entry(3, configuration((15, 15))), 10);
+        high_scores.save(&path).unwrap();
+        assert_eq!(HighScores::load(&path), high_scores);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn load_missing_file_is_empty() {
+        assert_eq!(HighScores::load(Path::new("does/not/exist.json")), HighScores::default());
+    }
+
+    #[test]
+    fn load_corrupt_file_is_empty_and_kept() {
+        let path = temp_path();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "{ not json").unwrap();
+        assert_eq!(HighScores::load(&path), HighScores::default());
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(path.with_extension("json.bak")).unwrap(), "{ not json");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn dates_from_unix_days() {
+        assert_eq!(date_from_unix_days(0), "1970-01-01");
+        assert_eq!(date_from_unix_days(59), "1970-03-01");
+        assert_eq!(date_from_unix_days(11_016), "2000-02-29");
+        assert_eq!(date_from_unix_days(19_723), "2024-01-01");
+        assert_eq!(date_from_unix_days(-1), "1969-12-31");
+    }
+
+    #[test]
+    fn game_over_records_high_score() {
+        let path = temp_path();
+        let mut app = App::default();
+        app.add_loopless_state(GameState::RUNNING);
+        app.insert_resource(Score { value: 4 });
+        app.insert_resource(board::Desc {
+            grid_size: (5, 5),
+            cell_size: 10,
+            boundary: board::BoundaryMode::Wrap,
+            obstacles: Vec::new()
+        });
+        app.insert_resource(snake::helpers::InitParams {
+            movement_time_step: Duration::from_millis(150),
+            ..snake::helpers::InitParams::single_player(GridPosition::new(0, 0), Direction::Right, 0)
+        });
+        app.add_plugin(HighScorePlugin { path: path.clone(), max_entries: DEFAULT_MAX_ENTRIES });
+        app.world.spawn().insert(SnakeHead{}).insert(GridPosition::new(2, 2));
+        app.world.spawn().insert(SnakeTail{ index: 0, follow_target: Entity::from_raw(0), next_position: GridPosition::new(2, 2) })
+            .insert(GridPosition::new(-1, -1));
+        app.update();
+        assert!(app.world.resource::<HighScores>().entries.is_empty());
+
+        app.world.insert_resource(NextState(GameState::DEAD));
+        app.update();
+        let saved = HighScores::load(&path);
+        assert_eq!(saved.entries.len(), 1);
+        assert_eq!(saved.entries[0].score, 4);
+        assert_eq!(saved.entries[0].length, 1);
+        assert_eq!(saved.entries[0].configuration.grid_size, (5, 5));
+        assert_eq!(saved.entries[0].configuration.tick_ms, 150);
+        assert_eq!(saved.entries[0].configuration.obstacles, board::Desc {
+            grid_size: (9, 9),
+            cell_size: 1,
+            boundary: board::BoundaryMode::Solid,
+            obstacles: Vec::new()
+        }.obstacle_hash());
+        assert_eq!(&saved, app.world.resource::<HighScores>());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn bot_games_are_not_recorded() {
+        let path = temp_path();
+        let mut app = App::default();
+        app.add_loopless_state(GameState::RUNNING);
+        app.insert_resource(Score { value: 4 });
+        app.insert_resource(board::Desc { grid_size: (5, 5), cell_size: 10, boundary: board::BoundaryMode::Wrap, obstacles: Vec::new() });
+        app.insert_resource(snake::helpers::InitParams {
+            bots: vec![(PlayerId(0), BotKind::Greedy)],
+            ..snake::helpers::InitParams::single_player(GridPosition::new(0, 0), Direction::Right, 0)
+        });
+        app.add_plugin(HighScorePlugin { path: path.clone(), max_entries: DEFAULT_MAX_ENTRIES });
+        app.update();
+        app.world.insert_resource(NextState(GameState::DEAD));
+        app.update();
+        assert!(app.world.resource::<HighScores>().entries.is_empty());
+        assert!(!path.exists());
+    }
+}
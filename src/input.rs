@@ -1,33 +1,349 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use bevy::input::gamepad::{Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, Gamepads};
 use bevy::prelude::*;
-use bevy::input::system::exit_on_esc_system;
-use crate::core::Direction;
+use bevy::utils::HashMap;
+use crate::core::{Direction, DirectionEvent, PlayerId};
 
-pub struct GameInputPlugin;
+// Actions that aren't steering, handled by whichever plugin owns them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MetaAction {
+    Select,
+    Pause,
+    Restart,
+    Quit
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Binding {
+    Turn(PlayerId, Direction),
+    Meta(MetaAction)
+}
+
+// Turns are named for player 1, `p2_up` and so on for the other players.
+const DIRECTION_NAMES: [(&str, Direction); 4] = [
+    ("up", Direction::Up),
+    ("down", Direction::Down),
+    ("left", Direction::Left),
+    ("right", Direction::Right),
+];
+
+const META_NAMES: [(&str, MetaAction); 4] = [
+    ("select", MetaAction::Select),
+    ("pause", MetaAction::Pause),
+    ("restart", MetaAction::Restart),
+    ("quit", MetaAction::Quit),
+];
+
+const LETTER_KEYS: [KeyCode; 26] = [
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+    KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+    KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+    KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z
+];
+
+const DIGIT_KEYS: [KeyCode; 10] = [
+    KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+    KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9
+];
+
+fn binding_name(binding: &Binding) -> String {
+    match binding {
+        Binding::Turn(player, direction) => {
+            let name = DIRECTION_NAMES.iter().find(|(_, named)| named == direction).map(|(name, _)| *name).unwrap_or_default();
+            match player.0 {
+                0 => String::from(name),
+                index => format!("p{}_{}", index + 1, name)
+            }
+        }
+        Binding::Meta(action) => META_NAMES
+            .iter()
+            .find(|(_, named)| named == action)
+            .map(|(name, _)| String::from(*name))
+            .unwrap_or_default()
+    }
+}
+
+fn parse_binding(name: &str) -> Option<Binding> {
+    let name = name.to_lowercase();
+    if let Some((_, action)) = META_NAMES.iter().find(|(named, _)| *named == name) {
+        return Some(Binding::Meta(*action));
+    }
+    let (player, direction_name) = match name.strip_prefix('p').and_then(|rest| rest.split_once('_')) {
+        Some((number, direction_name)) => (number.parse::<usize>().ok().filter(|number| *number >= 1)? - 1, direction_name),
+        None => (0, name.as_str())
+    };
+    DIRECTION_NAMES
+        .iter()
+        .find(|(named, _)| *named == direction_name)
+        .map(|(_, direction)| Binding::Turn(PlayerId(player), direction.clone()))
+}
+
+fn parse_key(name: &str) -> Result<KeyCode, String> {
+    let lower = name.to_lowercase();
+    let mut chars = lower.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_lowercase() {
+            return Ok(LETTER_KEYS[(c as u8 - b'a') as usize]);
+        }
+        if c.is_ascii_digit() {
+            return Ok(DIGIT_KEYS[(c as u8 - b'0') as usize]);
+        }
+    }
+    match lower.as_str() {
+        "up" => Ok(KeyCode::Up),
+        "down" => Ok(KeyCode::Down),
+        "left" => Ok(KeyCode::Left),
+        "right" => Ok(KeyCode::Right),
+        "space" => Ok(KeyCode::Space),
+        "return" | "enter" => Ok(KeyCode::Return),
+        "escape" | "esc" => Ok(KeyCode::Escape),
+        "tab" => Ok(KeyCode::Tab),
+        "backspace" => Ok(KeyCode::Back),
+        _ => Err(format!("unknown key '{}'", name))
+    }
+}
+
+// Keys can be listed against more than one binding; the first one listed wins
+// and the rest are reported by `conflicts`. Player 1 steers with the arrows and
+// player 2 with WASD.
+#[derive(Clone, PartialEq, Debug)]
+pub struct KeyBindings {
+    bindings: Vec<(KeyCode, Binding)>
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            bindings: vec![
+                (KeyCode::Left, Binding::Turn(PlayerId(0), Direction::Left)),
+                (KeyCode::Right, Binding::Turn(PlayerId(0), Direction::Right)),
+                (KeyCode::Up, Binding::Turn(PlayerId(0), Direction::Up)),
+                (KeyCode::Down, Binding::Turn(PlayerId(0), Direction::Down)),
+                (KeyCode::A, Binding::Turn(PlayerId(1), Direction::Left)),
+                (KeyCode::D, Binding::Turn(PlayerId(1), Direction::Right)),
+                (KeyCode::W, Binding::Turn(PlayerId(1), Direction::Up)),
+                (KeyCode::S, Binding::Turn(PlayerId(1), Direction::Down)),
+                (KeyCode::Return, Binding::Meta(MetaAction::Select)),
+                (KeyCode::Space, Binding::Meta(MetaAction::Select)),
+                (KeyCode::Escape, Binding::Meta(MetaAction::Pause)),
+            ]
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn load(path: &Path) -> Result<KeyBindings, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        KeyBindings::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // One `action = key, key` line per action, `;` starts a comment. Actions
+    // that aren't listed keep their default keys.
+    //
+    //   up = Up, W, K
+    //   restart = R
+    pub fn parse(text: &str) -> Result<KeyBindings, String> {
+        let mut key_bindings = KeyBindings::default();
+        let mut listed: Vec<Binding> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", index + 1, message);
+            let (name, keys) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected 'action = keys', found '{}'", line)))?;
+            let binding = parse_binding(name.trim())
+                .ok_or_else(|| error(format!("unknown action '{}'", name.trim())))?;
+            if !listed.contains(&binding) {
+                key_bindings.unbind(&binding);
+                listed.push(binding.clone());
+            }
+            for key in keys.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+                let key = parse_key(key).map_err(error)?;
+                // a key listed in the file takes over from any default it had
+                key_bindings.bindings.retain(|(bound_key, bound)| *bound_key != key || listed.contains(bound));
+                key_bindings.bind(key, binding.clone());
+            }
+        }
+        Ok(key_bindings)
+    }
+
+    pub fn bind(&mut self, key: KeyCode, binding: Binding) {
+        self.bindings.push((key, binding));
+    }
+
+    pub fn unbind(&mut self, binding: &Binding) {
+        self.bindings.retain(|(_, bound)| bound != binding);
+    }
+
+    pub fn binding(&self, key: KeyCode) -> Option<&Binding> {
+        self.bindings
+            .iter()
+            .find(|(bound_key, _)| *bound_key == key)
+            .map(|(_, binding)| binding)
+    }
+
+    pub fn conflicts(&self) -> Vec<String> {
+        self.bindings
+            .iter()
+            .filter_map(|(key, binding)| {
+                let first = self.binding(*key)?;
+                (first != binding).then(|| format!(
+                    "{:?} is bound to both {} and {}, using {}",
+                    key, binding_name(first), binding_name(binding), binding_name(first)
+                ))
+            })
+            .collect()
+    }
+
+    // Bound keys in the order they were bound, each only once.
+    fn active(&self) -> impl Iterator<Item = (KeyCode, &Binding)> {
+        self.bindings
+            .iter()
+            .enumerate()
+            .filter(|(index, (key, _))| !self.bindings[..*index].iter().any(|(earlier, _)| earlier == key))
+            .map(|(_, (key, binding))| (*key, binding))
+    }
+}
+
+// The same bindings for any connected gamepad, except that gamepad N always
+// steers player N+1. The left stick turns once it's pushed past
+// `stick_deadzone` towards a new direction.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GamepadBindings {
+    pub buttons: Vec<(GamepadButtonType, Binding)>,
+    pub stick_deadzone: f32
+}
+
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        GamepadBindings {
+            buttons: vec![
+                (GamepadButtonType::DPadUp, Binding::Turn(PlayerId(0), Direction::Up)),
+                (GamepadButtonType::DPadDown, Binding::Turn(PlayerId(0), Direction::Down)),
+                (GamepadButtonType::DPadLeft, Binding::Turn(PlayerId(0), Direction::Left)),
+                (GamepadButtonType::DPadRight, Binding::Turn(PlayerId(0), Direction::Right)),
+                // menu and pause actions sit on the face buttons, with Start
+                // pausing too as it does in most games
+                (GamepadButtonType::South, Binding::Meta(MetaAction::Select)),
+                (GamepadButtonType::East, Binding::Meta(MetaAction::Pause)),
+                (GamepadButtonType::North, Binding::Meta(MetaAction::Restart)),
+                (GamepadButtonType::Start, Binding::Meta(MetaAction::Pause)),
+            ],
+            stick_deadzone: 0.5
+        }
+    }
+}
+
+// The stick's direction is whichever axis it's pushed furthest along, y up.
+fn stick_direction(x: f32, y: f32, deadzone: f32) -> Option<Direction> {
+    if x.abs().max(y.abs()) < deadzone {
+        None
+    } else if x.abs() > y.abs() {
+        Some(if x > 0. { Direction::Right } else { Direction::Left })
+    } else {
+        Some(if y > 0. { Direction::Up } else { Direction::Down })
+    }
+}
+
+// $XDG_CONFIG_HOME/bevy_snake/keys.txt, falling back to ~/.config/bevy_snake, or
+// %APPDATA%\bevy_snake on Windows.
+pub fn default_bindings_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(config_dir.join("bevy_snake").join("keys.txt"))
+}
+
+pub struct GameInputPlugin {
+    pub key_bindings: KeyBindings,
+    pub gamepad_bindings: GamepadBindings
+}
 
 impl Plugin for GameInputPlugin {
     fn build(&self, app: &mut App) {
+        for conflict in self.key_bindings.conflicts() {
+            warn!("key binding conflict: {}", conflict);
+        }
         app
-            .add_event::<Direction>()
-            .add_system(exit_on_esc_system)
-            .add_system(handle_keyboard_input);
+            .insert_resource(self.key_bindings.clone())
+            .insert_resource(self.gamepad_bindings.clone())
+            .init_resource::<Gamepads>()
+            .init_resource::<Input<GamepadButton>>()
+            .init_resource::<Axis<GamepadAxis>>()
+            .add_event::<DirectionEvent>()
+            .add_event::<MetaAction>()
+            .add_system(handle_keyboard_input.label("input"))
+            .add_system(handle_gamepad_input.label("input"));
+    }
+}
+
+fn send_binding(
+    binding: &Binding,
+    direction_events: &mut EventWriter<DirectionEvent>,
+    meta_events: &mut EventWriter<MetaAction>) {
+
+    match binding {
+        Binding::Turn(player, direction) => direction_events.send(DirectionEvent { player: *player, direction: direction.clone() }),
+        Binding::Meta(action) => meta_events.send(*action)
     }
 }
 
 fn handle_keyboard_input(
     keyboard_input: Res<Input<KeyCode>>,
-    mut direction_events: EventWriter<Direction>) {
+    key_bindings: Res<KeyBindings>,
+    mut direction_events: EventWriter<DirectionEvent>,
+    mut meta_events: EventWriter<MetaAction>) {
 
-    if keyboard_input.just_pressed(KeyCode::Left) {
-        direction_events.send(Direction::Left)
-    }
-    if keyboard_input.just_pressed(KeyCode::Right) {
-        direction_events.send(Direction::Right)
+    for (key, binding) in key_bindings.active() {
+        if keyboard_input.just_pressed(key) {
+            send_binding(binding, &mut direction_events, &mut meta_events);
+        }
     }
-    if keyboard_input.just_pressed(KeyCode::Up) {
-        direction_events.send(Direction::Up)
-    }
-    if keyboard_input.just_pressed(KeyCode::Down){
-        direction_events.send(Direction::Down)
+}
+
+fn handle_gamepad_input(
+    gamepads: Res<Gamepads>,
+    button_input: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepad_bindings: Res<GamepadBindings>,
+    mut stick_directions: Local<HashMap<Gamepad, Direction>>,
+    mut direction_events: EventWriter<DirectionEvent>,
+    mut meta_events: EventWriter<MetaAction>) {
+
+    for gamepad in gamepads.iter() {
+        let player = PlayerId(gamepad.0);
+        for (button_type, binding) in gamepad_bindings.buttons.iter() {
+            if button_input.just_pressed(GamepadButton(*gamepad, *button_type)) {
+                let binding = match binding {
+                    Binding::Turn(_, direction) => Binding::Turn(player, direction.clone()),
+                    meta => meta.clone()
+                };
+                send_binding(&binding, &mut direction_events, &mut meta_events);
+            }
+        }
+
+        let axis = |axis_type| axes.get(GamepadAxis(*gamepad, axis_type)).unwrap_or_default();
+        let direction = stick_direction(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+            gamepad_bindings.stick_deadzone
+        );
+        match direction {
+            Some(direction) if stick_directions.get(gamepad) != Some(&direction) => {
+                stick_directions.insert(*gamepad, direction.clone());
+                direction_events.send(DirectionEvent { player, direction });
+            }
+            Some(_) => {}
+            None => {
+                stick_directions.remove(gamepad);
+            }
+        }
     }
 }
 
@@ -35,24 +351,33 @@ fn handle_keyboard_input(
 #[cfg(test)]
 mod tests {
     use bevy::ecs::event::Events;
+    use bevy::input::gamepad::{gamepad_connection_system, GamepadEvent, GamepadEventType};
     use super::*;
 
     fn init_system() -> App {
+        init_system_with(KeyBindings::default())
+    }
+
+    fn init_system_with(key_bindings: KeyBindings) -> App {
         let mut app = App::default();
         app.add_event::<bevy::input::keyboard::KeyboardInput>();
-        app.add_plugin(GameInputPlugin);
+        app.add_plugin(GameInputPlugin { key_bindings, gamepad_bindings: GamepadBindings::default() });
         app.world.insert_resource(Input::<KeyCode>::default());
         app
     }
 
-    fn get_direction_events(app: &App) -> Vec<Direction> {
+    fn get_player_direction_events(app: &App) -> Vec<DirectionEvent> {
         app.world
-            .resource::<Events<Direction>>()
+            .resource::<Events<DirectionEvent>>()
             .iter_current_update_events()
             .cloned()
             .collect()
     }
 
+    fn get_direction_events(app: &App) -> Vec<Direction> {
+        get_player_direction_events(app).into_iter().map(|event| event.direction).collect()
+    }
+
     #[test]
     fn up_key() {
         let mut app = init_system();
@@ -101,4 +426,165 @@ mod tests {
         app.update();
         assert_eq!(get_direction_events(&app), vec![]);
     }
-}
\ No newline at end of file
+
+    fn get_meta_events(app: &App) -> Vec<MetaAction> {
+        app.world
+            .resource::<Events<MetaAction>>()
+            .iter_current_update_events()
+            .cloned()
+            .collect()
+    }
+
+    fn press(app: &mut App, keys: &[KeyCode]) {
+        for key in keys {
+            app.world.resource_mut::<Input<KeyCode>>().press(*key);
+        }
+        app.update();
+    }
+
+    const WASD_VIM: &str = "\
+; WASD and vim keys alongside the arrows
+up = Up, W, K
+down = Down, S, J
+left = Left, A, H
+right = Right, D, L
+restart = r
+quit = Q
+";
+
+    #[test]
+    fn custom_map_keys() {
+        let mut app = init_system_with(KeyBindings::parse(WASD_VIM).unwrap());
+        press(&mut app, &[KeyCode::W]);
+        assert_eq!(get_direction_events(&app), vec![Direction::Up]);
+        let mut app = init_system_with(KeyBindings::parse(WASD_VIM).unwrap());
+        press(&mut app, &[KeyCode::H]);
+        assert_eq!(get_direction_events(&app), vec![Direction::Left]);
+        let mut app = init_system_with(KeyBindings::parse(WASD_VIM).unwrap());
+        press(&mut app, &[KeyCode::Right]);
+        assert_eq!(get_direction_events(&app), vec![Direction::Right]);
+    }
+
+    #[test]
+    fn custom_map_meta_actions() {
+        let mut app = init_system_with(KeyBindings::parse(WASD_VIM).unwrap());
+        press(&mut app, &[KeyCode::R, KeyCode::Q]);
+        assert_eq!(get_meta_events(&app), vec![MetaAction::Restart, MetaAction::Quit]);
+        assert_eq!(get_direction_events(&app), vec![]);
+    }
+
+    #[test]
+    fn default_map_pauses_on_escape() {
+        let mut app = init_system();
+        press(&mut app, &[KeyCode::Escape]);
+        assert_eq!(get_meta_events(&app), vec![MetaAction::Pause]);
+    }
+
+    #[test]
+    fn unlisted_actions_keep_defaults() {
+        let key_bindings = KeyBindings::parse("up = W").unwrap();
+        assert_eq!(key_bindings.binding(KeyCode::W), Some(&Binding::Turn(PlayerId(0), Direction::Up)));
+        assert_eq!(key_bindings.binding(KeyCode::Up), None);
+        assert_eq!(key_bindings.binding(KeyCode::Down), Some(&Binding::Turn(PlayerId(0), Direction::Down)));
+        assert_eq!(key_bindings.binding(KeyCode::Escape), Some(&Binding::Meta(MetaAction::Pause)));
+    }
+
+    #[test]
+    fn second_player_steers_with_wasd() {
+        let mut app = init_system();
+        press(&mut app, &[KeyCode::W, KeyCode::Left]);
+        assert_eq!(get_player_direction_events(&app), vec![
+            DirectionEvent { player: PlayerId(0), direction: Direction::Left },
+            DirectionEvent { player: PlayerId(1), direction: Direction::Up },
+        ]);
+
+        let key_bindings = KeyBindings::parse("p2_up = I
+p3_left = J").unwrap();
+        assert_eq!(key_bindings.binding(KeyCode::W), None);
+        assert_eq!(key_bindings.binding(KeyCode::I), Some(&Binding::Turn(PlayerId(1), Direction::Up)));
+        assert_eq!(key_bindings.binding(KeyCode::J), Some(&Binding::Turn(PlayerId(2), Direction::Left)));
+        assert!(KeyBindings::parse("p0_up = I").is_err());
+    }
+
+    #[test]
+    fn conflicting_keys_use_first_binding() {
+        let key_bindings = KeyBindings::parse("up = W\nquit = w").unwrap();
+        assert_eq!(key_bindings.conflicts(), vec![String::from("W is bound to both up and quit, using up")]);
+        let mut app = init_system_with(key_bindings);
+        press(&mut app, &[KeyCode::W]);
+        assert_eq!(get_direction_events(&app), vec![Direction::Up]);
+        assert_eq!(get_meta_events(&app), vec![]);
+        assert!(KeyBindings::default().conflicts().is_empty());
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let cases = [
+            ("up = W\njump = Space", "line 2: unknown action 'jump'"),
+            ("up = W, Ctrl", "line 1: unknown key 'Ctrl'"),
+            ("up W", "line 1: expected 'action = keys', found 'up W'"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(KeyBindings::parse(text), Err(String::from(expected)), "{:?}", text);
+        }
+    }
+
+    fn init_gamepad_system() -> App {
+        let mut app = init_system();
+        app.add_event::<GamepadEvent>();
+        app.add_system_to_stage(CoreStage::PreUpdate, gamepad_connection_system);
+        app.world.resource_mut::<Events<GamepadEvent>>().send(GamepadEvent(Gamepad(0), GamepadEventType::Connected));
+        app.update();
+        app
+    }
+
+    fn set_stick(app: &mut App, x: f32, y: f32) {
+        let mut axes = app.world.resource_mut::<Axis<GamepadAxis>>();
+        axes.set(GamepadAxis(Gamepad(0), GamepadAxisType::LeftStickX), x);
+        axes.set(GamepadAxis(Gamepad(0), GamepadAxisType::LeftStickY), y);
+        app.update();
+    }
+
+    #[test]
+    fn gamepad_buttons() {
+        let mut app = init_gamepad_system();
+        let mut button_input = app.world.resource_mut::<Input<GamepadButton>>();
+        button_input.press(GamepadButton(Gamepad(0), GamepadButtonType::DPadLeft));
+        button_input.press(GamepadButton(Gamepad(0), GamepadButtonType::South));
+        button_input.press(GamepadButton(Gamepad(0), GamepadButtonType::East));
+        button_input.press(GamepadButton(Gamepad(0), GamepadButtonType::North));
+        button_input.press(GamepadButton(Gamepad(1), GamepadButtonType::DPadUp));
+        app.update();
+        assert_eq!(get_player_direction_events(&app), vec![
+            DirectionEvent { player: PlayerId(0), direction: Direction::Left }
+        ]);
+        assert_eq!(get_meta_events(&app), vec![MetaAction::Select, MetaAction::Pause, MetaAction::Restart]);
+
+        let mut button_input = app.world.resource_mut::<Input<GamepadButton>>();
+        for button_type in [GamepadButtonType::DPadLeft, GamepadButtonType::South, GamepadButtonType::East, GamepadButtonType::North] {
+            button_input.release(GamepadButton(Gamepad(0), button_type));
+        }
+        button_input.clear();
+        button_input.press(GamepadButton(Gamepad(0), GamepadButtonType::Start));
+        button_input.press(GamepadButton(Gamepad(0), GamepadButtonType::Select));
+        app.update();
+        assert_eq!(get_meta_events(&app), vec![MetaAction::Pause]);
+    }
+
+    #[test]
+    fn gamepad_stick_turns_on_direction_change() {
+        let mut app = init_gamepad_system();
+        set_stick(&mut app, 0.3, 0.);
+        assert_eq!(get_direction_events(&app), vec![]);
+        set_stick(&mut app, 0.9, 0.1);
+        assert_eq!(get_direction_events(&app), vec![Direction::Right]);
+        set_stick(&mut app, 0.95, -0.2);
+        assert_eq!(get_direction_events(&app), vec![]);
+        set_stick(&mut app, 0.2, -0.8);
+        assert_eq!(get_direction_events(&app), vec![Direction::Down]);
+        set_stick(&mut app, 0., 0.);
+        assert_eq!(get_direction_events(&app), vec![]);
+        set_stick(&mut app, 0., -1.);
+        assert_eq!(get_direction_events(&app), vec![Direction::Down]);
+    }
+}
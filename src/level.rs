@@ -0,0 +1,338 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use crate::core::{Direction, GameOverRule, GridPosition, PlayerId};
+use crate::food;
+use crate::game_board::board;
+use crate::snake;
+use crate::snake::brain::BotKind;
+use crate::speed::SpeedCurve;
+
+// Levels are plain text: `key = value` settings, then a `map:` line followed by
+// one row of cells per line.
+//
+//   . empty   # wall   S snake start   F food spawn point (the first is where food starts)
+//   2-9 the starts of any more players, who head `pN_direction` or else `direction`
+//
+// `speed` takes a speed curve such as `linear` or `stepped:5:10:50`, see SpeedCurve.
+// `game_over` is `any` to end the game at the first death or `all` at the last.
+// `bot` and `pN_bot` hand a player over to the computer: `greedy`, `bfs`,
+// `hamiltonian`, `exec:COMMAND` for an external bot, see snake::external, or
+// `http://HOST:PORT` for a Battlesnake server, see snake::battlesnake.
+//
+// Lines starting with `;` are comments. Settings that are left out take the
+// defaults below.
+const DEFAULT_CELL_SIZE: i32 = 15;
+const DEFAULT_TICK_SECONDS: f32 = 0.1;
+const MAX_TICK_SECONDS: f32 = 60.;
+const DEFAULT_TAIL_LENGTH: usize = 3;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Level {
+    pub board: board::Desc,
+    pub snake: snake::helpers::InitParams,
+    pub food: food::InitParams,
+}
+
+impl Level {
+    pub fn load(path: &Path) -> Result<Level, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Level::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(text: &str) -> Result<Level, String> {
+        let mut cell_size = DEFAULT_CELL_SIZE;
+        let mut tick_time = Duration::from_secs_f32(DEFAULT_TICK_SECONDS);
+        let mut tail_length = DEFAULT_TAIL_LENGTH;
+        let mut direction = Direction::Right;
+        let mut boundary = board::BoundaryMode::Wrap;
+        let mut speed_curve = SpeedCurve::Constant;
+        let mut game_over = GameOverRule::AnyPlayerDies;
+        let mut player_directions: Vec<(usize, Direction)> = Vec::new();
+        let mut bots: Vec<(PlayerId, BotKind)> = Vec::new();
+
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim_end()))
+            .filter(|(_, line)| !line.trim_start().starts_with(';'));
+
+        let mut map_line = None;
+        for (line_number, line) in lines.by_ref() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line == "map:" {
+                map_line = Some(line_number);
+                break;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| error(line_number, format!("expected 'key = value' or 'map:', found '{}'", line)))?;
+            match key {
+                "cell_size" => cell_size = parse_value(line_number, key, value)?,
+                "tick_seconds" => tick_time = parse_tick_seconds(line_number, key, value)?,
+                "tail_length" => tail_length = parse_value(line_number, key, value)?,
+                "direction" => direction = parse_direction(value).map_err(|e| error(line_number, e))?,
+                "boundary" => boundary = value.parse().map_err(|e| error(line_number, e))?,
+                "speed" => speed_curve = value.parse().map_err(|e| error(line_number, e))?,
+                "game_over" => game_over = value.parse().map_err(|e| error(line_number, e))?,
+                "bot" => bots.push((PlayerId(0), value.parse().map_err(|e| error(line_number, e))?)),
+                _ => match (player_setting(key, "direction"), player_setting(key, "bot")) {
+                    (Some(player), _) => player_directions.push(
+                        (player, parse_direction(value).map_err(|e| error(line_number, e))?)
+                    ),
+                    (_, Some(player)) => bots.push(
+                        (PlayerId(player - 1), value.parse().map_err(|e| error(line_number, e))?)
+                    ),
+                    _ => return Err(error(line_number, format!("unknown setting '{}'", key)))
+                }
+            }
+        }
+        let map_line = map_line.ok_or_else(|| String::from("missing 'map:' section"))?;
+        if cell_size <= 0 {
+            return Err(String::from("cell_size must be greater than zero"));
+        }
+
+        let mut width = None;
+        let mut height = 0;
+        let mut obstacles = Vec::new();
+        let mut snake_start = None;
+        let mut food_spawns = Vec::new();
+        let mut other_starts: Vec<(usize, GridPosition)> = Vec::new();
+        for (line_number, row) in lines {
+            if row.is_empty() {
+                continue;
+            }
+            let row_width = row.chars().count();
+            match width {
+                None => width = Some(row_width),
+                Some(width) if width != row_width => {
+                    return Err(error(line_number, format!("row is {} cells wide, expected {}", row_width, width)));
+                }
+                _ => {}
+            }
+            for (x, cell) in row.chars().enumerate() {
+                let grid_pos = GridPosition::new(x as i32, height);
+                match cell {
+                    '.' => {}
+                    '#' => obstacles.push(grid_pos),
+                    'F' => food_spawns.push(grid_pos),
+                    'S' if snake_start.is_none() => snake_start = Some(grid_pos),
+                    'S' => return Err(error(line_number, String::from("more than one snake start 'S'"))),
+                    '2'..='9' => {
+                        let player = cell as usize - '0' as usize;
+                        if other_starts.iter().any(|(other, _)| *other == player) {
+                            return Err(error(line_number, format!("more than one start for player {}", player)));
+                        }
+                        other_starts.push((player, grid_pos));
+                    }
+                    _ => return Err(error(line_number, format!("unknown cell '{}'", cell)))
+                }
+            }
+            height += 1;
+        }
+
+        let width = width.ok_or_else(|| error(map_line, String::from("map has no rows")))?;
+        let start_position = snake_start.ok_or_else(|| String::from("map has no snake start 'S'"))?;
+        let food_start = *food_spawns.first().ok_or_else(|| String::from("map has no food spawn 'F'"))?;
+        other_starts.sort_by_key(|(player, _)| *player);
+        let mut other_players = Vec::new();
+        for (index, (player, position)) in other_starts.into_iter().enumerate() {
+            if player != index + 2 {
+                return Err(format!("map has a start for player {} but not player {}", player, index + 2));
+            }
+            let direction = player_directions
+                .iter()
+                .find(|(directed, _)| *directed == player)
+                .map(|(_, direction)| direction.clone())
+                .unwrap_or_else(|| direction.clone());
+            other_players.push(snake::helpers::PlayerStart { position, direction });
+        }
+        if let Some((player, _)) = bots.iter().find(|(player, _)| player.0 > other_players.len()) {
+            return Err(format!("p{}_bot is set but the map has no start for player {}", player.0 + 1, player.0 + 1));
+        }
+
+        Ok(Level {
+            board: board::Desc {
+                grid_size: (width as i32, height),
+                cell_size,
+                boundary,
+                obstacles
+            },
+            snake: snake::helpers::InitParams {
+                movement_time_step: tick_time,
+                start_position,
+                start_direction: direction,
+                initial_tail_length: tail_length,
+                speed_curve,
+                other_players,
+                game_over,
+                bots
+            },
+            food: food::InitParams {
+                start_position: food_start,
+                spawn_points: food_spawns
+            }
+        })
+    }
+}
+
+fn error(line_number: usize, message: String) -> String {
+    format!("line {}: {}", line_number, message)
+}
+
+fn parse_value<T: std::str::FromStr>(line_number: usize, key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| error(line_number, format!("invalid value '{}' for {}", value, key)))
+}
+
+fn parse_tick_seconds(line_number: usize, key: &str, value: &str) -> Result<Duration, String> {
+    let seconds: f32 = parse_value(line_number, key, value)?;
+    match Duration::try_from_secs_f32(seconds) {
+        Ok(tick_time) if !tick_time.is_zero() && seconds <= MAX_TICK_SECONDS => Ok(tick_time),
+        _ => Err(error(line_number, format!("{} must be greater than zero and at most {}", key, MAX_TICK_SECONDS)))
+    }
+}
+
+// The player number from a per-player setting such as `p2_direction`.
+fn player_setting(key: &str, setting: &str) -> Option<usize> {
+    key.strip_prefix('p')?
+        .strip_suffix(setting)?
+        .strip_suffix('_')?
+        .parse()
+        .ok()
+        .filter(|player| (2..=9).contains(player))
+}
+
+fn parse_direction(value: &str) -> Result<Direction, String> {
+    match value.to_lowercase().as_str() {
+        "up" => Ok(Direction::Up),
+        "down" => Ok(Direction::Down),
+        "left" => Ok(Direction::Left),
+        "right" => Ok(Direction::Right),
+        _ => Err(format!("unknown direction '{}', expected up, down, left or right", value))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALLED: &str = "\
+; a small test level
+cell_size = 10
+tail_length = 2
+direction = up
+boundary = solid
+speed = linear:4:60
+
+map:
+.....
+.#F#.
+.....
+..S.F
+";
+
+    #[test]
+    fn parse_level() {
+        let level = Level::parse(WALLED).unwrap();
+        assert_eq!(level.board, board::Desc {
+            grid_size: (5, 4),
+            cell_size: 10,
+            boundary: board::BoundaryMode::Solid,
+            obstacles: vec![GridPosition::new(1, 1), GridPosition::new(3, 1)]
+        });
+        assert_eq!(level.snake, snake::helpers::InitParams {
+            movement_time_step: Duration::from_secs_f32(DEFAULT_TICK_SECONDS),
+            speed_curve: SpeedCurve::Linear { step_ms: 4, min_ms: 60 },
+            ..snake::helpers::InitParams::single_player(GridPosition::new(2, 3), Direction::Up, 2)
+        });
+        assert_eq!(level.food, food::InitParams {
+            start_position: GridPosition::new(2, 1),
+            spawn_points: vec![GridPosition::new(2, 1), GridPosition::new(4, 3)]
+        });
+    }
+
+    #[test]
+    fn parse_uses_defaults() {
+        let level = Level::parse("map:\nS.F\n").unwrap();
+        assert_eq!(level.board.grid_size, (3, 1));
+        assert_eq!(level.board.cell_size, DEFAULT_CELL_SIZE);
+        assert_eq!(level.board.boundary, board::BoundaryMode::Wrap);
+        assert_eq!(level.snake.start_direction, Direction::Right);
+        assert_eq!(level.snake.initial_tail_length, DEFAULT_TAIL_LENGTH);
+        assert_eq!(level.snake.speed_curve, SpeedCurve::Constant);
+        assert_eq!(level.snake.player_count(), 1);
+        assert_eq!(level.snake.game_over, GameOverRule::AnyPlayerDies);
+    }
+
+    #[test]
+    fn parse_more_players() {
+        let level = Level::parse("direction = up\np3_direction = left\ngame_over = all\np2_bot = bfs\nmap:\nS.3\n2.F\n").unwrap();
+        assert_eq!(level.snake.other_players, vec![
+            snake::helpers::PlayerStart { position: GridPosition::new(0, 1), direction: Direction::Up },
+            snake::helpers::PlayerStart { position: GridPosition::new(2, 0), direction: Direction::Left },
+        ]);
+        assert_eq!(level.snake.game_over, GameOverRule::AllPlayersDie);
+        assert_eq!(level.snake.bot(PlayerId(0)), None);
+        assert_eq!(level.snake.bot(PlayerId(1)), Some(BotKind::Bfs));
+    }
+
+    #[test]
+    fn default_level_parses() {
+        let level = Level::parse(include_str!("../levels/classic.txt")).unwrap();
+        assert_eq!(level.board.grid_size, (15, 15));
+        assert_eq!(level.snake.start_position, GridPosition::new(0, 7));
+        assert_eq!(level.food.start_position, GridPosition::new(7, 7));
+        // the default game keeps one pace, speeding up is a level of its own
+        assert_eq!(level.snake.speed_curve, SpeedCurve::Constant);
+        let speedup = Level::parse(include_str!("../levels/speedup.txt")).unwrap();
+        assert_eq!(speedup.board, level.board);
+        assert_ne!(speedup.snake.speed_curve, SpeedCurve::Constant);
+        assert!(Level::parse(include_str!("../levels/walled.txt")).is_ok());
+        assert_eq!(Level::parse(include_str!("../levels/duel.txt")).unwrap().snake.player_count(), 2);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let cases = [
+            ("tail_length = lots\nmap:\nS.F", "line 1: invalid value 'lots' for tail_length"),
+            ("colour = red\nmap:\nS.F", "line 1: unknown setting 'colour'"),
+            ("speed = warp\nmap:\nS.F", "line 1: unknown speed curve 'warp', expected constant, linear or stepped"),
+            ("direction = north\nmap:\nS.F", "line 1: unknown direction 'north', expected up, down, left or right"),
+            ("just some text\nmap:\nS.F", "line 1: expected 'key = value' or 'map:', found 'just some text'"),
+            ("map:\nS.F\n..", "line 3: row is 2 cells wide, expected 3"),
+            ("map:\nS.F\nS..", "line 3: more than one snake start 'S'"),
+            ("map:\nS?F", "line 2: unknown cell '?'"),
+            ("map:\n", "line 1: map has no rows"),
+            ("S.F", "line 1: expected 'key = value' or 'map:', found 'S.F'"),
+            ("cell_size = 10", "missing 'map:' section"),
+            ("map:\n..F", "map has no snake start 'S'"),
+            ("map:\nS..", "map has no food spawn 'F'"),
+            ("map:\nS.F3", "map has a start for player 3 but not player 2"),
+            ("map:\nS2F2", "line 2: more than one start for player 2"),
+            ("p2_direction = sideways\nmap:\nS2F", "line 1: unknown direction 'sideways', expected up, down, left or right"),
+            ("game_over = never\nmap:\nS2F", "line 1: unknown game over rule 'never', expected any or all"),
+            ("bot = clever\nmap:\nS2F", "line 1: unknown bot 'clever', expected greedy, bfs, hamiltonian, exec:COMMAND or http://HOST:PORT"),
+            ("p3_bot = bfs\nmap:\nS2F", "p3_bot is set but the map has no start for player 3"),
+            ("tick_seconds = 0\nmap:\nS.F", "line 1: tick_seconds must be greater than zero and at most 60"),
+            ("tick_seconds = -1\nmap:\nS.F", "line 1: tick_seconds must be greater than zero and at most 60"),
+            ("\ntick_seconds = NaN\nmap:\nS.F", "line 2: tick_seconds must be greater than zero and at most 60"),
+            ("tick_seconds = inf\nmap:\nS.F", "line 1: tick_seconds must be greater than zero and at most 60"),
+            ("tick_seconds = 1e30\nmap:\nS.F", "line 1: tick_seconds must be greater than zero and at most 60"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(Level::parse(text), Err(String::from(expected)), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn load_missing_file_fails() {
+        assert!(Level::load(Path::new("does/not/exist.txt")).is_err());
+    }
+}
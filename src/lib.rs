@@ -0,0 +1,18 @@
+pub mod batch;
+pub mod core;
+pub mod game_board;
+pub mod high_scores;
+pub mod input;
+pub mod snake;
+pub mod food;
+pub mod level;
+pub mod menu;
+pub mod netplay;
+pub mod pixel_text;
+pub mod replay;
+pub mod score;
+pub mod sim;
+pub mod spectate;
+pub mod terminal;
+pub mod speed;
+pub mod tick;
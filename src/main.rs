@@ -1,67 +1,196 @@
-pub mod core;
-mod game_board;
-mod input;
-mod snake;
-mod food;
-
+use std::path::PathBuf;
 use std::time::Duration;
+use bevy::app::ScheduleRunnerSettings;
 use bevy::prelude::*;
-use crate::core::{GameStatePlugin, GameWindow, GridPosition};
-use crate::food::FoodPlugin;
-use crate::game_board::board;
-use crate::game_board::plugin::GameBoardPlugin;
-use crate::input::GameInputPlugin;
+use bevy_snake::core::{DirectionEvent, GameRngPlugin, GameState, GameStatePlugin, GameWindow, PlayerId};
+use bevy_snake::food::FoodPlugin;
+use bevy_snake::game_board::board;
+use bevy_snake::game_board::plugin::GameBoardPlugin;
+use bevy_snake::high_scores::{self, HighScorePlugin};
+use bevy_snake::input::{self, GameInputPlugin, GamepadBindings, KeyBindings};
+use bevy_snake::level::Level;
+use bevy_snake::menu::MenuPlugin;
+use bevy_snake::netplay::{self, LockstepPlugin};
+use bevy_snake::replay::{Replay, ReplayPlaybackPlugin, ReplayRecorderPlugin};
+use bevy_snake::score::{ScorePlugin, HUD_HEIGHT};
+use bevy_snake::snake::{self, interpolation::InterpolationPlugin};
+use bevy_snake::spectate::SpectatorPlugin;
+use bevy_snake::speed::SpeedPlugin;
+use bevy_snake::terminal::{TerminalInputPlugin, TerminalPlugin};
+
+const DEFAULT_LEVEL: &str = include_str!("../levels/classic.txt");
+
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+        .cloned()
+}
 
-const TICK_TIME_SECONDS: f32 = 0.1;
-const GRID_SIZE: (i32, i32) = (15, 15);
-const CELL_SIZE: i32 = 15;
-const SNAKE_START_POS: GridPosition = GridPosition{x: 0, y: 7};
-const FOOD_START_POS: GridPosition = GridPosition{x: 7, y: 7};
-const START_TAIL_LENGTH: usize = 3;
+// `--net ADDRESS,ADDRESS,... --player N` plays a networked game as player N,
+// listening on the Nth address. Every instance needs the same addresses, level
+// and `--seed`.
+fn lockstep_plugin(snake_params: &snake::helpers::InitParams) -> Option<LockstepPlugin> {
+    let addresses = arg_value("--net")?
+        .split(',')
+        .map(|address| address.trim().parse().unwrap_or_else(|e| panic!("invalid --net address '{}': {}", address, e)))
+        .collect::<Vec<_>>();
+    let player = arg_value("--player")
+        .and_then(|player| player.parse::<usize>().ok())
+        .filter(|player| (1..=addresses.len()).contains(player))
+        .unwrap_or_else(|| panic!("--net needs --player between 1 and {}", addresses.len()));
+    if addresses.len() > snake_params.player_count() {
+        panic!("{} players joined but the level only has starts for {}", addresses.len(), snake_params.player_count());
+    }
+    if arg_value("--seed").is_none() {
+        panic!("--net needs the same --seed on every instance");
+    }
+    netplay::check_bots(&snake_params.bots).unwrap_or_else(|e| panic!("{}", e));
+    Some(
+        LockstepPlugin::bind(addresses, PlayerId(player - 1), netplay::DEFAULT_INPUT_DELAY)
+            .unwrap_or_else(|e| panic!("{}", e))
+    )
+}
 
 fn main() {
-    println!("Hello, Snake!");
-    let game_board_desc = board::Desc{
-        grid_size: GRID_SIZE,
-        cell_size: CELL_SIZE,
+    let playback = arg_value("--replay").map(|path| {
+        Replay::load(&PathBuf::from(path)).unwrap_or_else(|e| panic!("failed to load replay {}", e))
+    });
+
+    let replay = match playback.clone() {
+        Some(mut replay) => {
+            // the recorded turns already hold whatever the bots chose
+            replay.snake.bots.clear();
+            replay
+        }
+        None => {
+            let mut level = match arg_value("--level") {
+                Some(path) => Level::load(&PathBuf::from(path)),
+                None => Level::parse(DEFAULT_LEVEL)
+            }.unwrap_or_else(|e| panic!("failed to load level {}", e));
+            if let Some(mode) = arg_value("--boundary") {
+                level.board.boundary = mode.parse().unwrap_or_else(|e| panic!("{}", e));
+            }
+            if let Some(rule) = arg_value("--game-over") {
+                level.snake.game_over = rule.parse().unwrap_or_else(|e| panic!("{}", e));
+            }
+            if let Some(kind) = arg_value("--bot") {
+                level.snake.bots.retain(|(player, _)| *player != PlayerId(0));
+                level.snake.bots.push((PlayerId(0), kind.parse().unwrap_or_else(|e| panic!("{}", e))));
+            }
+            if let Some(curve) = arg_value("--speed") {
+                level.snake.speed_curve = curve.parse().unwrap_or_else(|e| panic!("{}", e));
+            }
+            Replay {
+                board: level.board,
+                snake: level.snake,
+                food: level.food,
+                seed: arg_value("--seed")
+                    .map(|seed| seed.parse::<u64>().expect("--seed must be a positive integer"))
+                    .unwrap_or_else(rand::random),
+                turns: Vec::new(),
+                other_turns: Vec::new()
+            }
+        }
     };
-
-    App::new()
-        .add_plugin( GameWindow {
-            title: "Bevy Snake".to_string(),
-            width: game_board_desc.world_dimensions().0,
-            height: game_board_desc.world_dimensions().1,
-        })
+    let watching_replay = playback.is_some();
+    let lockstep = match playback {
+        Some(_) => None,
+        None => lockstep_plugin(&replay.snake)
+    };
+    let game_board_desc = replay.board.clone();
+    // `--terminal` plays in the terminal instead of a window
+    let terminal = std::env::args().any(|arg| arg == "--terminal");
+
+    let mut app = App::new();
+    if terminal {
+        app
+            .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(1. / 60.)))
+            .add_plugins(MinimalPlugins)
+            .insert_resource(game_board_desc)
+            .add_startup_system(board::spawn_obstacles)
+            .add_plugin(TerminalPlugin);
+    } else {
+        app
+            .add_plugin( GameWindow {
+                title: "Bevy Snake".to_string(),
+                width: game_board_desc.world_dimensions().0,
+                height: game_board_desc.world_dimensions().1 + HUD_HEIGHT,
+            })
+            .add_plugins(DefaultPlugins)
+            .add_plugin(GameBoardPlugin { desc: game_board_desc });
+    }
+    app
         .add_plugin(GameStatePlugin{
-            tick_time_sec: TICK_TIME_SECONDS,
-            game_over_pause_sec: 2.0
+            tick_time_sec: replay.snake.movement_time_step.as_secs_f32(),
+            game_over_pause_sec: 2.0,
+            countdown_sec: 3.0,
+            // playback, networked and terminal games go straight into the game
+            initial_state: match (&playback, &lockstep, terminal) {
+                (None, None, false) => GameState::MAIN_MENU,
+                _ => GameState::COUNTDOWN
+            }
         })
-        .add_plugins(DefaultPlugins)
-        .add_plugin(GameBoardPlugin { desc: game_board_desc })
-        .add_plugin(GameInputPlugin)
+        .add_plugin(GameRngPlugin { seed: replay.seed })
         .add_plugin(FoodPlugin {
-            init_params: food::InitParams {
-                start_position: FOOD_START_POS
-            }
+            init_params: replay.food.clone()
         })
         .add_plugin(snake::plugin::SnakePlugin {
-            init_params: snake::helpers::InitParams{
-                movement_time_step: Duration::from_secs_f32(TICK_TIME_SECONDS),
-                start_position: SNAKE_START_POS,
-                initial_tail_length: START_TAIL_LENGTH
-            }
+            init_params: replay.snake.clone()
         })
-        .run();
+        .add_plugin(ScorePlugin { hud: !terminal })
+        .add_plugin(SpeedPlugin);
+
+    match playback {
+        Some(replay) => {
+            app
+                .add_event::<DirectionEvent>()
+                .add_plugin(ReplayPlaybackPlugin { replay });
+        }
+        None if terminal => {
+            app.add_plugin(TerminalInputPlugin);
+        }
+        None => {
+            // an explicit --keys file has to load, the default one is optional
+            let key_bindings = match arg_value("--keys") {
+                Some(path) => KeyBindings::load(&PathBuf::from(path)).unwrap_or_else(|e| panic!("failed to load key bindings {}", e)),
+                None => input::default_bindings_path()
+                    .filter(|path| path.exists())
+                    .map(|path| KeyBindings::load(&path).unwrap_or_else(|e| {
+                        warn!("ignoring key bindings {}", e);
+                        KeyBindings::default()
+                    }))
+                    .unwrap_or_default()
+            };
+            app
+                .add_plugin(GameInputPlugin { key_bindings, gamepad_bindings: GamepadBindings::default() })
+                .add_plugin(MenuPlugin);
+        }
+    }
+    // a replay's score was recorded when it was played
+    match high_scores::default_path() {
+        Some(path) if !watching_replay => {
+            app.add_plugin(HighScorePlugin { path, max_entries: high_scores::DEFAULT_MAX_ENTRIES });
+        }
+        Some(_) => {}
+        None => println!("no data directory found, high scores won't be saved")
+    }
+    // `--smooth` tweens snakes between cells rather than stepping them
+    if std::env::args().any(|arg| arg == "--smooth") {
+        app.add_plugin(InterpolationPlugin);
+    }
+    if let Some(lockstep) = lockstep {
+        app.add_plugin(lockstep);
+    }
+    if let Some(address) = arg_value("--spectate") {
+        let address = address.parse().unwrap_or_else(|e| panic!("invalid --spectate address '{}': {}", address, e));
+        app.add_plugin(SpectatorPlugin { address });
+    }
+    if let Some(directory) = arg_value("--record") {
+        app.add_plugin(ReplayRecorderPlugin { directory: PathBuf::from(directory) });
+    }
+    // after the plugins, so the log is there to show it
+    info!("seed {}", replay.seed);
+    app.run();
 }
-
-
-
-
-
-
-
-
-
-
-
-
@@ -0,0 +1,521 @@
+use std::time::Duration;
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use iyes_loopless::prelude::*;
+use crate::core::{CountdownTimer, Direction, DirectionEvent, GameState, Pause};
+use crate::game_board::board::{self, BoundaryMode};
+use crate::input::MetaAction;
+use crate::pixel_text;
+use crate::snake;
+use crate::tick::TickClock;
+
+const MENU_PIXEL_SIZE: f32 = 3.;
+const TITLE_PIXEL_SIZE: f32 = 6.;
+const COUNTDOWN_PIXEL_SIZE: f32 = 10.;
+const LINE_GAP: f32 = 3. * MENU_PIXEL_SIZE;
+const TEXT_Z: f32 = 1.;
+// name and percentage of the level's own tick time
+const SPEEDS: [(&str, u32); 3] = [("SLOW", 150), ("NORMAL", 100), ("FAST", 60)];
+const NORMAL_SPEED: usize = 1;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MenuEntry {
+    Start,
+    Walls,
+    Speed,
+    Quit
+}
+
+const ENTRIES: [MenuEntry; 4] = [MenuEntry::Start, MenuEntry::Walls, MenuEntry::Speed, MenuEntry::Quit];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PauseEntry {
+    Resume,
+    Quit
+}
+
+const PAUSE_ENTRIES: [PauseEntry; 2] = [PauseEntry::Resume, PauseEntry::Quit];
+
+pub struct MainMenu {
+    selected: usize,
+    speed: usize,
+    base_time_step: Duration
+}
+
+impl MainMenu {
+    pub fn selected(&self) -> MenuEntry {
+        ENTRIES[self.selected]
+    }
+}
+
+#[derive(Default)]
+pub struct PauseMenu {
+    selected: usize
+}
+
+impl PauseMenu {
+    pub fn selected(&self) -> PauseEntry {
+        PAUSE_ENTRIES[self.selected]
+    }
+}
+
+#[derive(Component, Clone)]
+struct MenuText;
+
+#[derive(Component, Clone)]
+struct PauseText;
+
+#[derive(Component, Clone)]
+struct CountdownText(u32);
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_startup_system(init_menu)
+            .add_enter_system(GameState::MAIN_MENU, open_menu)
+            .add_exit_system(GameState::MAIN_MENU, despawn_with::<MenuText>)
+            .add_system(menu_input.run_in_state(GameState::MAIN_MENU).label("menu_input").after("input"))
+            .add_system(draw_menu.run_in_state(GameState::MAIN_MENU).after("menu_input"))
+            .add_system(draw_countdown.run_in_state(GameState::COUNTDOWN))
+            .add_exit_system(GameState::COUNTDOWN, despawn_with::<CountdownText>)
+            .init_resource::<PauseMenu>()
+            .add_enter_system(GameState::PAUSED, open_pause_menu)
+            .add_exit_system(GameState::PAUSED, despawn_with::<PauseText>)
+            .add_system(pause_menu_input.run_in_state(GameState::PAUSED).label("pause_input").after("input"))
+            .add_system(draw_pause_menu.run_in_state(GameState::PAUSED).after("pause_input"))
+            .add_system(meta_action_input.after("input").after("pause_input"));
+    }
+}
+
+fn init_menu(snake_params: Res<snake::helpers::InitParams>, mut commands: Commands) {
+    commands.insert_resource(MainMenu {
+        selected: 0,
+        speed: NORMAL_SPEED,
+        base_time_step: snake_params.movement_time_step
+    });
+}
+
+fn open_menu(menu: Option<ResMut<MainMenu>>) {
+    if let Some(mut menu) = menu {
+        menu.selected = 0;
+    }
+}
+
+fn next_boundary(boundary: BoundaryMode) -> BoundaryMode {
+    match boundary {
+        BoundaryMode::Wrap => BoundaryMode::Solid,
+        BoundaryMode::Solid => BoundaryMode::Reflect,
+        BoundaryMode::Reflect => BoundaryMode::Wrap
+    }
+}
+
+fn menu_input(
+    mut direction_events: EventReader<DirectionEvent>,
+    mut meta_events: EventReader<MetaAction>,
+    mut menu: ResMut<MainMenu>,
+    // the settings the menu changes
+    (mut game_board, mut snake_params, mut clock): (
+        ResMut<board::Desc>,
+        ResMut<snake::helpers::InitParams>,
+        ResMut<TickClock>
+    ),
+    mut exit_events: EventWriter<AppExit>,
+    mut commands: Commands
+) {
+    for event in direction_events.iter() {
+        match event.direction {
+            Direction::Up => menu.selected = (menu.selected + ENTRIES.len() - 1) % ENTRIES.len(),
+            Direction::Down => menu.selected = (menu.selected + 1) % ENTRIES.len(),
+            _ => {}
+        }
+    }
+    if !meta_events.iter().any(|action| *action == MetaAction::Select) {
+        return;
+    }
+    match menu.selected() {
+        MenuEntry::Start => commands.insert_resource(NextState(GameState::COUNTDOWN)),
+        MenuEntry::Walls => game_board.boundary = next_boundary(game_board.boundary),
+        MenuEntry::Speed => {
+            menu.speed = (menu.speed + 1) % SPEEDS.len();
+            snake_params.movement_time_step = menu.base_time_step * SPEEDS[menu.speed].1 / 100;
+            clock.set_step(snake_params.movement_time_step);
+        }
+        MenuEntry::Quit => exit_events.send(AppExit)
+    }
+}
+
+fn entry_label(entry: MenuEntry, menu: &MainMenu, game_board: &board::Desc) -> String {
+    match entry {
+        MenuEntry::Start => String::from("START"),
+        MenuEntry::Walls => format!("WALLS {:?}", game_board.boundary).to_uppercase(),
+        MenuEntry::Speed => format!("SPEED {}", SPEEDS[menu.speed].0),
+        MenuEntry::Quit => String::from("QUIT")
+    }
+}
+
+fn board_centre(game_board: &board::Desc) -> Vec2 {
+    let (width, height) = game_board.world_dimensions();
+    Vec2::new(width * 0.5, -height * 0.5)
+}
+
+fn spawn_centred_text(
+    commands: &mut Commands,
+    text: &str,
+    centre_x: f32,
+    top: f32,
+    pixel_size: f32,
+    color: Color,
+    marker: impl Component + Clone
+) {
+    let size = pixel_text::text_size(text, pixel_size);
+    pixel_text::spawn_text(
+        commands,
+        text,
+        Vec3::new(centre_x - size.x * 0.5, top, TEXT_Z),
+        pixel_size,
+        color,
+        marker
+    );
+}
+
+// A title over a list of entries, centred on the board, with the selected one highlighted
+fn spawn_entries(
+    commands: &mut Commands,
+    game_board: &board::Desc,
+    title: &str,
+    labels: &[String],
+    selected: usize,
+    marker: impl Component + Clone
+) {
+    let centre = board_centre(game_board);
+    let line_height = pixel_text::text_size("", MENU_PIXEL_SIZE).y + LINE_GAP;
+    let title_height = pixel_text::text_size("", TITLE_PIXEL_SIZE).y + LINE_GAP;
+    let mut top = centre.y + (title_height + line_height * labels.len() as f32) * 0.5;
+    spawn_centred_text(commands, title, centre.x, top, TITLE_PIXEL_SIZE, Color::GREEN, marker.clone());
+    top -= title_height;
+    for (index, label) in labels.iter().enumerate() {
+        let color = if index == selected { Color::YELLOW } else { Color::WHITE };
+        spawn_centred_text(commands, label, centre.x, top, MENU_PIXEL_SIZE, color, marker.clone());
+        top -= line_height;
+    }
+}
+
+fn draw_menu(
+    menu: Res<MainMenu>,
+    game_board: Res<board::Desc>,
+    existing_text: Query<Entity, With<MenuText>>,
+    mut commands: Commands
+) {
+    if !menu.is_changed() && !game_board.is_changed() {
+        return;
+    }
+    for entity in existing_text.iter() {
+        commands.entity(entity).despawn();
+    }
+    let labels = ENTRIES
+        .iter()
+        .map(|entry| entry_label(*entry, &menu, &game_board))
+        .collect::<Vec<String>>();
+    spawn_entries(&mut commands, &game_board, "SNAKE", &labels, menu.selected, MenuText);
+}
+
+fn draw_countdown(
+    timer: Res<CountdownTimer>,
+    game_board: Res<board::Desc>,
+    existing_text: Query<(Entity, &CountdownText)>,
+    mut commands: Commands
+) {
+    let seconds_left = timer.seconds_left();
+    if existing_text.iter().any(|(_, text)| text.0 == seconds_left) {
+        return;
+    }
+    for (entity, _) in existing_text.iter() {
+        commands.entity(entity).despawn();
+    }
+    if seconds_left == 0 {
+        return;
+    }
+    let text = seconds_left.to_string();
+    let centre = board_centre(&game_board);
+    let size = pixel_text::text_size(&text, COUNTDOWN_PIXEL_SIZE);
+    spawn_centred_text(
+        &mut commands,
+        &text,
+        centre.x,
+        centre.y + size.y * 0.5,
+        COUNTDOWN_PIXEL_SIZE,
+        Color::WHITE,
+        CountdownText(seconds_left)
+    );
+}
+
+// Pause quits from the main menu and otherwise opens or closes the pause menu.
+// Restart abandons the game in play for a fresh one.
+pub fn meta_action_input(
+    mut meta_events: EventReader<MetaAction>,
+    state: Res<CurrentState<GameState>>,
+    mut pause: ResMut<Pause>,
+    mut exit_events: EventWriter<AppExit>,
+    mut commands: Commands
+) {
+    for action in meta_events.iter() {
+        match action {
+            MetaAction::Quit => exit_events.send(AppExit),
+            MetaAction::Pause if state.0 == GameState::MAIN_MENU => exit_events.send(AppExit),
+            MetaAction::Pause => {
+                if let Some(next_state) = pause.toggle(&state.0) {
+                    commands.insert_resource(NextState(next_state));
+                }
+            }
+            MetaAction::Select => {}
+            MetaAction::Restart => {
+                if matches!(state.0, GameState::RUNNING | GameState::PAUSED | GameState::DEAD) {
+                    *pause = Pause::default();
+                    commands.insert_resource(NextState(GameState::COUNTDOWN));
+                }
+            }
+        }
+    }
+}
+
+fn open_pause_menu(mut pause_menu: ResMut<PauseMenu>) {
+    pause_menu.selected = 0;
+}
+
+fn pause_menu_input(
+    mut direction_events: EventReader<DirectionEvent>,
+    mut meta_events: EventReader<MetaAction>,
+    mut pause_menu: ResMut<PauseMenu>,
+    mut pause: ResMut<Pause>,
+    mut exit_events: EventWriter<AppExit>,
+    mut commands: Commands
+) {
+    for event in direction_events.iter() {
+        if matches!(event.direction, Direction::Up | Direction::Down) {
+            pause_menu.selected = (pause_menu.selected + 1) % PAUSE_ENTRIES.len();
+        }
+    }
+    if !meta_events.iter().any(|action| *action == MetaAction::Select) {
+        return;
+    }
+    match pause_menu.selected() {
+        PauseEntry::Resume => {
+            if let Some(next_state) = pause.toggle(&GameState::PAUSED) {
+                commands.insert_resource(NextState(next_state));
+            }
+        }
+        PauseEntry::Quit => exit_events.send(AppExit)
+    }
+}
+
+fn draw_pause_menu(
+    pause_menu: Res<PauseMenu>,
+    game_board: Res<board::Desc>,
+    existing_text: Query<Entity, With<PauseText>>,
+    mut commands: Commands
+) {
+    if !pause_menu.is_changed() {
+        return;
+    }
+    for entity in existing_text.iter() {
+        commands.entity(entity).despawn();
+    }
+    let (width, height) = game_board.world_dimensions();
+    commands
+        .spawn()
+        .insert(PauseText)
+        .insert_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::rgba(0., 0., 0., 0.7),
+                custom_size: Some(Vec2::new(width, height)),
+                anchor: Anchor::TopLeft,
+                ..default()
+            },
+            transform: Transform::from_xyz(0., 0., TEXT_Z - 0.5),
+            ..default()
+        });
+    let labels = [String::from("RESUME"), String::from("QUIT")];
+    spawn_entries(&mut commands, &game_board, "PAUSED", &labels, pause_menu.selected, PauseText);
+}
+
+fn despawn_with<T: Component>(query: Query<Entity, With<T>>, mut commands: Commands) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::Events;
+    use crate::core::{GameStatePlugin, GridPosition};
+    use crate::input::{GameInputPlugin, GamepadBindings, KeyBindings};
+    use super::*;
+
+    fn init_menu_app(initial_state: GameState) -> App {
+        let mut app = App::default();
+        app.add_plugins(MinimalPlugins);
+        app.add_plugin(GameStatePlugin{
+            tick_time_sec: 1.0,
+            game_over_pause_sec: 100.0,
+            countdown_sec: 3.0,
+            initial_state
+        });
+        app.insert_resource(Input::<KeyCode>::default());
+        app.insert_resource(board::Desc {
+            grid_size: (15, 15),
+            cell_size: 15,
+            boundary: BoundaryMode::Wrap,
+            obstacles: Vec::new()
+        });
+        app.insert_resource(snake::helpers::InitParams::single_player(GridPosition::new(0, 0), Direction::Right, 0));
+        app.insert_resource(TickClock::new(Duration::from_millis(100)));
+        app.add_plugin(GameInputPlugin {
+            key_bindings: KeyBindings::parse("restart = R\nquit = Q").unwrap(),
+            gamepad_bindings: GamepadBindings::default()
+        });
+        app.add_plugin(MenuPlugin);
+        app.update();
+        app
+    }
+
+    fn tap(app: &mut App, key: KeyCode) {
+        app.world.resource_mut::<Input<KeyCode>>().press(key);
+        app.update();
+        let mut keyboard_input = app.world.resource_mut::<Input<KeyCode>>();
+        keyboard_input.release(key);
+        keyboard_input.clear();
+        app.update();
+    }
+
+    fn current_state(app: &App) -> GameState {
+        app.world.resource::<CurrentState<GameState>>().0.clone()
+    }
+
+    fn text_count<T: Component>(app: &mut App) -> usize {
+        app.world
+            .query_filtered::<Entity, With<T>>()
+            .iter(&app.world)
+            .count()
+    }
+
+    #[test]
+    fn start_entry_starts_countdown() {
+        let mut app = init_menu_app(GameState::MAIN_MENU);
+        assert!(text_count::<MenuText>(&mut app) > 0);
+        tap(&mut app, KeyCode::Return);
+        assert_eq!(current_state(&app), GameState::COUNTDOWN);
+        assert_eq!(text_count::<MenuText>(&mut app), 0);
+        assert_eq!(text_count::<CountdownText>(&mut app), pixel_text::pixels("3").len());
+    }
+
+    #[test]
+    fn selection_wraps_around() {
+        let mut app = init_menu_app(GameState::MAIN_MENU);
+        tap(&mut app, KeyCode::Up);
+        assert_eq!(app.world.resource::<MainMenu>().selected(), MenuEntry::Quit);
+        tap(&mut app, KeyCode::Down);
+        tap(&mut app, KeyCode::Down);
+        assert_eq!(app.world.resource::<MainMenu>().selected(), MenuEntry::Walls);
+    }
+
+    #[test]
+    fn settings_entries_change_settings() {
+        let mut app = init_menu_app(GameState::MAIN_MENU);
+        tap(&mut app, KeyCode::Down);
+        tap(&mut app, KeyCode::Space);
+        assert_eq!(app.world.resource::<board::Desc>().boundary, BoundaryMode::Solid);
+        tap(&mut app, KeyCode::Down);
+        tap(&mut app, KeyCode::Space);
+        assert_eq!(
+            app.world.resource::<snake::helpers::InitParams>().movement_time_step,
+            Duration::from_millis(60)
+        );
+        assert_eq!(app.world.resource::<TickClock>().step(), Duration::from_millis(60));
+        tap(&mut app, KeyCode::Space);
+        assert_eq!(
+            app.world.resource::<snake::helpers::InitParams>().movement_time_step,
+            Duration::from_millis(150)
+        );
+        assert_eq!(current_state(&app), GameState::MAIN_MENU);
+    }
+
+    #[test]
+    fn quit_entry_exits() {
+        let mut app = init_menu_app(GameState::MAIN_MENU);
+        tap(&mut app, KeyCode::Up);
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::Return);
+        app.update();
+        assert_eq!(app.world.resource::<Events<AppExit>>().iter_current_update_events().count(), 1);
+    }
+
+    #[test]
+    fn escape_toggles_pause() {
+        let mut app = init_menu_app(GameState::RUNNING);
+        tap(&mut app, KeyCode::Escape);
+        assert_eq!(current_state(&app), GameState::PAUSED);
+        assert!(text_count::<PauseText>(&mut app) > 0);
+        tap(&mut app, KeyCode::Escape);
+        assert_eq!(current_state(&app), GameState::RUNNING);
+        assert_eq!(text_count::<PauseText>(&mut app), 0);
+    }
+
+    #[test]
+    fn resume_entry_returns_to_game_over() {
+        let mut app = init_menu_app(GameState::DEAD);
+        tap(&mut app, KeyCode::Escape);
+        assert_eq!(current_state(&app), GameState::PAUSED);
+        tap(&mut app, KeyCode::Return);
+        assert_eq!(current_state(&app), GameState::DEAD);
+    }
+
+    #[test]
+    fn quit_from_pause_menu() {
+        let mut app = init_menu_app(GameState::RUNNING);
+        tap(&mut app, KeyCode::Escape);
+        tap(&mut app, KeyCode::Down);
+        assert_eq!(app.world.resource::<PauseMenu>().selected(), PauseEntry::Quit);
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::Return);
+        app.update();
+        assert_eq!(app.world.resource::<Events<AppExit>>().iter_current_update_events().count(), 1);
+        assert_eq!(current_state(&app), GameState::PAUSED);
+    }
+
+    #[test]
+    fn escape_quits_from_main_menu() {
+        let mut app = init_menu_app(GameState::MAIN_MENU);
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::Escape);
+        app.update();
+        assert_eq!(current_state(&app), GameState::MAIN_MENU);
+        assert_eq!(app.world.resource::<Events<AppExit>>().iter_current_update_events().count(), 1);
+    }
+
+    #[test]
+    fn escape_ignored_during_countdown() {
+        let mut app = init_menu_app(GameState::COUNTDOWN);
+        tap(&mut app, KeyCode::Escape);
+        assert_eq!(current_state(&app), GameState::COUNTDOWN);
+    }
+
+    #[test]
+    fn restart_key_starts_new_game_from_pause() {
+        let mut app = init_menu_app(GameState::RUNNING);
+        tap(&mut app, KeyCode::Escape);
+        assert_eq!(current_state(&app), GameState::PAUSED);
+        tap(&mut app, KeyCode::R);
+        assert_eq!(current_state(&app), GameState::COUNTDOWN);
+        assert_eq!(text_count::<PauseText>(&mut app), 0);
+        assert!(!app.world.resource::<Pause>().is_resuming());
+    }
+
+    #[test]
+    fn quit_key_exits_mid_game() {
+        let mut app = init_menu_app(GameState::RUNNING);
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::Q);
+        app.update();
+        assert_eq!(app.world.resource::<Events<AppExit>>().iter_current_update_events().count(), 1);
+    }
+}
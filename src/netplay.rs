@@ -0,0 +1,466 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::core::{checksum, Direction, DirectionEvent, GameState, GridPosition, PlayerId};
+use crate::food::FoodComponent;
+use crate::snake::brain::BotKind;
+use crate::snake::controller::{Dead, LiveHead, MovementController};
+use crate::snake::head::SnakeHead;
+use crate::snake::tail::SnakeTail;
+use crate::tick::{AppTickExt, TickClock};
+
+// Turns pressed now are played this many ticks later, which gives them time to
+// reach the other players before anyone needs them.
+pub const DEFAULT_INPUT_DELAY: u64 = 2;
+
+// How many ticks of our own checksums are kept to compare with late ones.
+const CHECKSUM_HISTORY: usize = 256;
+
+// Sent to every other player each frame over UDP: our turns for every tick from
+// `first_tick` on, starting from the oldest one someone might still be missing,
+// and the checksum of the last tick we played.
+#[derive(Serialize, Deserialize)]
+struct Packet {
+    player: PlayerId,
+    next_tick: u64,
+    first_tick: u64,
+    inputs: Vec<Vec<Direction>>,
+    checksum: Option<(u64, u64)>
+}
+
+// Plays one board across several instances, each steering one player. Only the
+// turns for each tick are exchanged, and every instance plays the same ticks
+// from them, so all of them need the same level and seed. A tick waits until
+// every player's turns for it are in. After each tick the state is checksummed,
+// and once another player's checksum doesn't match, the game stops.
+//
+// Player N is at the Nth address in `addresses`. Bots set in the level play on
+// every instance, see check_bots.
+pub struct LockstepPlugin {
+    pub addresses: Vec<SocketAddr>,
+    pub local_player: PlayerId,
+    pub input_delay: u64,
+    socket: UdpSocket
+}
+
+impl LockstepPlugin {
+    // Listens on the local player's address, so a port that's taken is
+    // reported before the game starts.
+    pub fn bind(addresses: Vec<SocketAddr>, local_player: PlayerId, input_delay: u64) -> Result<Self, String> {
+        let address = addresses[local_player.0];
+        let socket = UdpSocket::bind(address)
+            .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+            .map_err(|e| format!("failed to listen on {}: {}", address, e))?;
+        Ok(LockstepPlugin { addresses, local_player, input_delay, socket })
+    }
+}
+
+// Only the built-in bots play the same on every instance. An external bot or
+// Battlesnake server is asked separately by each one and can answer differently.
+pub fn check_bots(bots: &[(PlayerId, BotKind)]) -> Result<(), String> {
+    match bots.iter().find(|(_, kind)| matches!(kind, BotKind::External { .. } | BotKind::Battlesnake { .. })) {
+        Some((player, _)) => Err(format!("player {} has a bot that can't play over the network, only built-in ones can", player.0 + 1)),
+        None => Ok(())
+    }
+}
+
+impl Plugin for LockstepPlugin {
+    fn build(&self, app: &mut App) {
+        let socket = self.socket.try_clone().expect("failed to share the lockstep socket");
+        let players = self.addresses.len();
+        app
+            .insert_resource(Lockstep {
+                socket,
+                addresses: self.addresses.clone(),
+                local_player: self.local_player,
+                input_delay: self.input_delay,
+                pending: Vec::new(),
+                inputs: vec![BTreeMap::new(); players],
+                received: vec![0; players],
+                next_ticks: vec![1; players],
+                checksums: BTreeMap::new(),
+                remote_checksums: Vec::new(),
+                desync: None
+            })
+            .add_system_to_stage(CoreStage::PreUpdate, exchange_inputs)
+            .add_tick_system(apply_inputs
+                .run_in_state(GameState::RUNNING)
+                .before("move"))
+            .add_tick_system(record_checksum
+                .run_in_state(GameState::RUNNING)
+                .after("grow"));
+    }
+}
+
+pub struct Lockstep {
+    socket: UdpSocket,
+    addresses: Vec<SocketAddr>,
+    local_player: PlayerId,
+    input_delay: u64,
+    // turns pressed since the last tick we scheduled
+    pending: Vec<Direction>,
+    // indexed by player, then by tick
+    inputs: Vec<BTreeMap<u64, Vec<Direction>>>,
+    // the last tick each player's turns are in for, with none missing before it
+    received: Vec<u64>,
+    // the tick each player is waiting to play, as they last told us
+    next_ticks: Vec<u64>,
+    checksums: BTreeMap<u64, u64>,
+    remote_checksums: Vec<(PlayerId, u64, u64)>,
+    desync: Option<u64>
+}
+
+impl Lockstep {
+    // The first tick that played out differently for another player.
+    pub fn desync(&self) -> Option<u64> {
+        self.desync
+    }
+
+    fn players(&self) -> usize {
+        self.addresses.len()
+    }
+
+    fn schedule_local_inputs(&mut self, tick: u64) {
+        let local = self.local_player.0;
+        while self.received[local] < tick + self.input_delay {
+            self.received[local] += 1;
+            let turns = std::mem::take(&mut self.pending);
+            let scheduled = self.received[local];
+            self.inputs[local].insert(scheduled, turns);
+        }
+    }
+
+    fn send(&mut self, tick: u64) {
+        let local = self.local_player.0;
+        self.next_ticks[local] = tick + 1;
+        let first_tick = self.next_ticks.iter().copied().min().unwrap_or(tick + 1);
+        self.inputs[local].retain(|scheduled, _| *scheduled >= first_tick);
+        let packet = Packet {
+            player: self.local_player,
+            next_tick: tick + 1,
+            first_tick,
+            inputs: self.inputs[local].values().cloned().collect(),
+            checksum: self.checksums.iter().next_back().map(|(tick, checksum)| (*tick, *checksum))
+        };
+        let json = match serde_json::to_vec(&packet) {
+            Ok(json) => json,
+            Err(e) => return warn!("failed to encode inputs: {}", e)
+        };
+        for (player, address) in self.addresses.iter().enumerate() {
+            // players that aren't listening yet get the same inputs next frame
+            if player != local {
+                let _ = self.socket.send_to(&json, address);
+            }
+        }
+    }
+
+    fn receive(&mut self, tick: u64) {
+        let mut buffer = [0; 65536];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((length, _)) => match serde_json::from_slice::<Packet>(&buffer[..length]) {
+                    Ok(packet) => self.accept(packet),
+                    Err(e) => warn!("ignoring bad packet: {}", e)
+                },
+                // a player that isn't listening yet can bounce back an error
+                Err(e) if matches!(e.kind(), ErrorKind::ConnectionReset | ErrorKind::ConnectionRefused) => {}
+                Err(_) => break
+            }
+        }
+        for (player, inputs) in self.inputs.iter_mut().enumerate() {
+            if player != self.local_player.0 {
+                inputs.retain(|scheduled, _| *scheduled > tick);
+            }
+        }
+        self.check_checksums();
+    }
+
+    fn accept(&mut self, packet: Packet) {
+        let player = packet.player.0;
+        if player >= self.players() || packet.player == self.local_player {
+            return;
+        }
+        self.next_ticks[player] = self.next_ticks[player].max(packet.next_tick);
+        // ticks we already have are skipped, and after a gap nothing is taken
+        // until the missing ticks are sent again
+        for (offset, turns) in packet.inputs.into_iter().enumerate() {
+            let scheduled = packet.first_tick + offset as u64;
+            if scheduled == self.received[player] + 1 {
+                self.inputs[player].insert(scheduled, turns);
+                self.received[player] = scheduled;
+            }
+        }
+        if let Some((tick, checksum)) = packet.checksum {
+            let remote_checksum = (packet.player, tick, checksum);
+            if !self.remote_checksums.contains(&remote_checksum) {
+                self.remote_checksums.push(remote_checksum);
+            }
+        }
+    }
+
+    fn check_checksums(&mut self) {
+        let latest = self.checksums.keys().next_back().copied().unwrap_or(0);
+        let mut mismatches = Vec::new();
+        let checksums = &self.checksums;
+        // checksums for ticks we haven't played yet wait, ones too old to check are dropped
+        self.remote_checksums.retain(|(player, tick, checksum)| {
+            if *tick > latest {
+                return true;
+            }
+            if checksums.get(tick).is_some_and(|own| own != checksum) {
+                mismatches.push((*player, *tick));
+            }
+            false
+        });
+        for (player, tick) in mismatches {
+            if self.desync.is_none_or(|desync| tick < desync) {
+                warn!("out of step with player {} after tick {}", player.0 + 1, tick);
+                self.desync = Some(tick);
+            }
+        }
+    }
+
+    // The last tick that can be played with everyone's turns.
+    fn ready_tick(&self) -> u64 {
+        let ready = self.received.iter().copied().min().unwrap_or(0);
+        self.desync.map_or(ready, |desync| ready.min(desync))
+    }
+}
+
+pub fn not_networked(lockstep: Option<Res<Lockstep>>) -> bool {
+    lockstep.is_none()
+}
+
+// Runs before the tick stage: every key and gamepad on this instance steers its
+// player, then the tick clock is held at the last tick everyone's turns are in for.
+fn exchange_inputs(
+    mut lockstep: ResMut<Lockstep>,
+    state: Res<CurrentState<GameState>>,
+    mut direction_events: EventReader<DirectionEvent>,
+    mut clock: ResMut<TickClock>
+) {
+    for event in direction_events.iter() {
+        if state.0 == GameState::RUNNING {
+            lockstep.pending.push(event.direction.clone());
+        }
+    }
+    let tick = clock.tick();
+    lockstep.schedule_local_inputs(tick);
+    lockstep.send(tick);
+    lockstep.receive(tick);
+    clock.set_limit(Some(lockstep.ready_tick()));
+}
+
+fn apply_inputs(
+    lockstep: Res<Lockstep>,
+    clock: Res<TickClock>,
+    mut query: Query<(&PlayerId, &mut MovementController), LiveHead>
+) {
+    for (player, mut movement) in query.iter_mut() {
+        if let Some(turns) = lockstep.inputs.get(player.0).and_then(|inputs| inputs.get(&clock.tick())) {
+            for turn in turns {
+                movement.turns.push(turn.clone());
+            }
+        }
+    }
+}
+
+fn record_checksum(
+    mut lockstep: ResMut<Lockstep>,
+    clock: Res<TickClock>,
+    head_query: Query<(&PlayerId, &GridPosition, &MovementController, Option<&Dead>), With<SnakeHead>>,
+    tail_query: Query<(&PlayerId, &GridPosition, &SnakeTail)>,
+    food_query: Query<&GridPosition, With<FoodComponent>>
+) {
+    let mut heads = head_query
+        .iter()
+        .map(|(player, grid_pos, movement, dead)| {
+            [player.0 as i64, grid_pos.x as i64, grid_pos.y as i64, movement.direction.clone() as i64, dead.is_some() as i64]
+        })
+        .collect::<Vec<_>>();
+    heads.sort();
+    let mut tails = tail_query
+        .iter()
+        .map(|(player, grid_pos, tail)| [player.0 as i64, tail.index as i64, grid_pos.x as i64, grid_pos.y as i64])
+        .collect::<Vec<_>>();
+    tails.sort();
+    let mut food = food_query.iter().map(|grid_pos| [grid_pos.x as i64, grid_pos.y as i64]).collect::<Vec<_>>();
+    food.sort();
+
+    let values = heads.iter().flatten().chain(tails.iter().flatten()).chain(food.iter().flatten());
+    lockstep.checksums.insert(clock.tick(), checksum(values.copied()));
+    while lockstep.checksums.len() > CHECKSUM_HISTORY {
+        lockstep.checksums.pop_first();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use bevy::ecs::event::Events;
+    use crate::batch;
+    use crate::snake;
+    use crate::snake::helpers::PlayerStart;
+    use super::*;
+
+    fn free_addresses(count: usize) -> Vec<SocketAddr> {
+        let sockets = (0..count).map(|_| UdpSocket::bind("127.0.0.1:0").unwrap()).collect::<Vec<_>>();
+        sockets.iter().map(|socket| socket.local_addr().unwrap()).collect()
+    }
+
+    fn init_peer(addresses: &[SocketAddr], local_player: usize, second_start: GridPosition) -> App {
+        let mut level = batch::open_level((10, 10));
+        level.snake = snake::helpers::InitParams {
+            other_players: vec![PlayerStart { position: second_start, direction: Direction::Right }],
+            ..snake::helpers::InitParams::single_player(GridPosition::new(0, 2), Direction::Right, 2)
+        };
+        level.food.start_position = GridPosition::new(5, 2);
+        let mut app = App::default();
+        app.add_plugins(MinimalPlugins);
+        batch::add_headless_game(&mut app, &level, 5);
+        app.add_plugin(LockstepPlugin::bind(addresses.to_vec(), PlayerId(local_player), DEFAULT_INPUT_DELAY).unwrap());
+        app
+    }
+
+    fn press(app: &mut App, direction: Direction) {
+        // whichever player the key is bound to, it steers this instance's player
+        app.world.resource_mut::<Events<DirectionEvent>>().send(DirectionEvent { player: PlayerId(0), direction });
+    }
+
+    fn tick(app: &App) -> u64 {
+        app.world.resource::<TickClock>().tick()
+    }
+
+    fn snakes(app: &mut App) -> Vec<(usize, i32, GridPosition)> {
+        let heads = app.world
+            .query_filtered::<(&PlayerId, &GridPosition), With<SnakeHead>>()
+            .iter(&app.world)
+            .map(|(player, grid_pos)| (player.0, -1, *grid_pos))
+            .collect::<Vec<_>>();
+        let mut snakes = app.world
+            .query::<(&PlayerId, &GridPosition, &SnakeTail)>()
+            .iter(&app.world)
+            .map(|(player, grid_pos, tail)| (player.0, tail.index as i32, *grid_pos))
+            .chain(heads)
+            .collect::<Vec<_>>();
+        snakes.sort_by_key(|(player, index, _)| (*player, *index));
+        snakes
+    }
+
+    fn wait_for_step(app: &mut App, other: &App) {
+        // waits on the UDP round trip through loopback
+        for _ in 0..200 {
+            if tick(app) >= tick(other) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+            app.update();
+        }
+        panic!("never caught up");
+    }
+
+    #[test]
+    fn peers_play_the_same_game() {
+        let addresses = free_addresses(2);
+        let mut first = init_peer(&addresses, 0, GridPosition::new(0, 7));
+        let mut second = init_peer(&addresses, 1, GridPosition::new(0, 7));
+        for frame in 0..40 {
+            match frame {
+                10 => press(&mut first, Direction::Down),
+                15 => press(&mut second, Direction::Up),
+                _ => {}
+            }
+            first.update();
+            second.update();
+        }
+        assert!(tick(&first) > 20);
+        wait_for_step(&mut second, &first);
+        wait_for_step(&mut first, &second);
+
+        assert_eq!(tick(&first), tick(&second));
+        let first_snakes = snakes(&mut first);
+        assert_eq!(first_snakes, snakes(&mut second));
+        // both turns were played, on both instances
+        let head = |player: usize| first_snakes.iter().find(|(owner, index, _)| *owner == player && *index == -1).unwrap().2;
+        assert_ne!(head(0).y, 2);
+        assert_ne!(head(1).y, 7);
+        assert_eq!(first.world.resource::<Lockstep>().desync(), None);
+        assert_eq!(second.world.resource::<Lockstep>().desync(), None);
+    }
+
+    #[test]
+    fn game_waits_for_missing_inputs() {
+        let addresses = free_addresses(2);
+        let mut alone = init_peer(&addresses, 0, GridPosition::new(0, 7));
+        for _ in 0..20 {
+            alone.update();
+        }
+        assert_eq!(alone.world.resource::<CurrentState<GameState>>().0, GameState::RUNNING);
+        assert_eq!(tick(&alone), 0);
+    }
+
+    #[test]
+    fn different_games_stop_out_of_step() {
+        let addresses = free_addresses(2);
+        let mut first = init_peer(&addresses, 0, GridPosition::new(0, 7));
+        let mut second = init_peer(&addresses, 1, GridPosition::new(0, 6));
+        for _ in 0..40 {
+            first.update();
+            second.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(first.world.resource::<Lockstep>().desync(), Some(1));
+        assert_eq!(second.world.resource::<Lockstep>().desync(), Some(1));
+
+        let stopped_at = (tick(&first), tick(&second));
+        for _ in 0..10 {
+            first.update();
+            second.update();
+        }
+        assert_eq!((tick(&first), tick(&second)), stopped_at);
+    }
+
+    #[test]
+    fn inputs_are_taken_in_order() {
+        let addresses = free_addresses(2);
+        let mut app = init_peer(&addresses, 0, GridPosition::new(0, 7));
+        let mut lockstep = app.world.resource_mut::<Lockstep>();
+        let packet = |first_tick: u64, count: usize| Packet {
+            player: PlayerId(1),
+            next_tick: 1,
+            first_tick,
+            inputs: vec![vec![Direction::Up]; count],
+            checksum: None
+        };
+        lockstep.accept(packet(3, 2));
+        assert_eq!(lockstep.received[1], 0);
+        lockstep.accept(packet(1, 3));
+        lockstep.accept(packet(2, 3));
+        assert_eq!(lockstep.received[1], 4);
+        assert_eq!(lockstep.inputs[1].len(), 4);
+        // the local player's turns aren't ready yet
+        assert_eq!(lockstep.ready_tick(), 0);
+    }
+
+    #[test]
+    fn taken_address_is_an_error() {
+        let taken = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = taken.local_addr().unwrap();
+        let error = LockstepPlugin::bind(vec![address], PlayerId(0), DEFAULT_INPUT_DELAY).err().unwrap();
+        assert!(error.starts_with(&format!("failed to listen on {}: ", address)), "{}", error);
+    }
+
+    #[test]
+    fn only_built_in_bots_play_over_the_network() {
+        assert_eq!(check_bots(&[(PlayerId(0), BotKind::Bfs), (PlayerId(1), BotKind::Hamiltonian)]), Ok(()));
+        let external = BotKind::External { command: String::from("./bot"), timeout_ms: 100 };
+        assert_eq!(
+            check_bots(&[(PlayerId(0), BotKind::Greedy), (PlayerId(1), external)]),
+            Err(String::from("player 2 has a bot that can't play over the network, only built-in ones can"))
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+
+// Blocky text drawn from sprites so the game needs no font assets. Each glyph is
+// 3 pixels wide and 5 high, one row per entry with the high bit on the left.
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+
+#[derive(Component)]
+pub struct PixelText;
+
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b011, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        _ => [0; GLYPH_HEIGHT]
+    }
+}
+
+// Grid coordinates, (column, row) from the top left, of every lit pixel in the text.
+pub fn pixels(text: &str) -> Vec<(usize, usize)> {
+    let mut lit = Vec::new();
+    for (index, c) in text.chars().enumerate() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                    lit.push((index * (GLYPH_WIDTH + 1) + column, row));
+                }
+            }
+        }
+    }
+    lit
+}
+
+pub fn text_size(text: &str, pixel_size: f32) -> Vec2 {
+    let columns = (text.chars().count() * (GLYPH_WIDTH + 1)).saturating_sub(1);
+    Vec2::new(columns as f32 * pixel_size, GLYPH_HEIGHT as f32 * pixel_size)
+}
+
+// Spawns one sprite per lit pixel with `top_left` as the text's top left corner.
+// Every sprite gets a clone of `marker` so the caller can find and despawn them.
+pub fn spawn_text(
+    commands: &mut Commands,
+    text: &str,
+    top_left: Vec3,
+    pixel_size: f32,
+    color: Color,
+    marker: impl Component + Clone
+) {
+    for (column, row) in pixels(text) {
+        commands
+            .spawn()
+            .insert(PixelText)
+            .insert(marker.clone())
+            .insert_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(pixel_size, pixel_size)),
+                    anchor: Anchor::TopLeft,
+                    ..default()
+                },
+                transform: Transform::from_translation(
+                    top_left + Vec3::new(column as f32 * pixel_size, -(row as f32) * pixel_size, 0.)
+                ),
+                ..default()
+            });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_of_one() {
+        assert_eq!(pixels("1"), vec![(1, 0), (0, 1), (1, 1), (1, 2), (1, 3), (0, 4), (1, 4), (2, 4)]);
+    }
+
+    #[test]
+    fn pixels_advance_per_character() {
+        let one = pixels("1");
+        let shifted = pixels(" 1");
+        assert_eq!(shifted, one.iter().map(|(x, y)| (x + 4, *y)).collect::<Vec<_>>());
+        assert!(pixels("   ").is_empty());
+        assert_eq!(pixels("a"), pixels("A"));
+    }
+
+    #[test]
+    fn text_size_leaves_no_trailing_gap() {
+        assert_eq!(text_size("", 2.), Vec2::new(0., 10.));
+        assert_eq!(text_size("1", 2.), Vec2::new(6., 10.));
+        assert_eq!(text_size("12", 2.), Vec2::new(14., 10.));
+    }
+
+    #[derive(Component, Clone)]
+    struct Label;
+
+    #[test]
+    fn spawn_text_spawns_marked_sprites() {
+        let mut app = App::default();
+        app.add_system(|mut commands: Commands| {
+            spawn_text(&mut commands, "1", Vec3::new(10., 20., 0.), 2., Color::WHITE, Label);
+        });
+        app.update();
+        let mut translations = app.world
+            .query_filtered::<&Transform, (With<Label>, With<PixelText>, With<Sprite>)>()
+            .iter(&app.world)
+            .map(|transform| transform.translation)
+            .collect::<Vec<Vec3>>();
+        assert_eq!(translations.len(), 8);
+        translations.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap().then(b.y.partial_cmp(&a.y).unwrap()));
+        assert_eq!(translations[0], Vec3::new(10., 18., 0.));
+    }
+}
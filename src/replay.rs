@@ -0,0 +1,335 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::core::{not_resuming, Direction, DirectionEvent, GameRng, GameState, PlayerId};
+use crate::food;
+use crate::game_board::board;
+use crate::snake;
+use crate::snake::controller::{self, LiveHead, MovementController};
+use crate::tick::AppTickExt;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub board: board::Desc,
+    pub snake: snake::helpers::InitParams,
+    pub food: food::InitParams,
+    pub seed: u64,
+    // the turn player 1 took on each tick, if any
+    pub turns: Vec<Option<Direction>>,
+    // one list per player after the first
+    #[serde(default)]
+    pub other_turns: Vec<Vec<Option<Direction>>>,
+}
+
+impl Replay {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+pub struct ReplayRecorderPlugin {
+    pub directory: PathBuf
+}
+
+impl Plugin for ReplayRecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(ReplayRecorder {
+                directory: self.directory.clone(),
+                turns: Vec::new()
+            })
+            .add_enter_system(GameState::COUNTDOWN, start_recording)
+            .add_enter_system(GameState::DEAD, save_recording.run_if(not_resuming))
+            .add_tick_system(record_turns
+                .run_in_state(GameState::RUNNING)
+                .after("move"));
+    }
+}
+
+pub struct ReplayRecorder {
+    directory: PathBuf,
+    // indexed by player
+    turns: Vec<Vec<Option<Direction>>>
+}
+
+fn start_recording(mut recorder: ResMut<ReplayRecorder>, snake_params: Res<snake::helpers::InitParams>) {
+    recorder.turns = vec![Vec::new(); snake_params.player_count()];
+}
+
+// Only the turns taken are kept, not the direction moved in, since a reflect
+// changes direction without any turn and plays out the same again on its own.
+fn record_turns(
+    mut recorder: ResMut<ReplayRecorder>,
+    query: Query<(&PlayerId, &MovementController), LiveHead>
+) {
+    for (player, turns) in recorder.turns.iter_mut().enumerate() {
+        let turn = query
+            .iter()
+            .find(|(head_player, _)| head_player.0 == player)
+            .and_then(|(_, movement)| movement.turns.last_turn().cloned());
+        turns.push(turn);
+    }
+}
+
+fn save_recording(
+    recorder: Res<ReplayRecorder>,
+    game_board: Res<board::Desc>,
+    snake_params: Res<snake::helpers::InitParams>,
+    food_params: Res<food::InitParams>,
+    rng: Res<GameRng>
+) {
+    let replay = Replay {
+        board: game_board.clone(),
+        snake: snake_params.clone(),
+        food: food_params.clone(),
+        seed: rng.game_seed(),
+        turns: recorder.turns.first().cloned().unwrap_or_default(),
+        other_turns: recorder.turns.iter().skip(1).cloned().collect()
+    };
+    let path = recorder.directory.join(format!("snake-{}.json", replay.seed));
+    match replay.save(&path) {
+        Ok(()) => info!("replay saved to {}", path.display()),
+        Err(e) => warn!("failed to save replay {}", e)
+    }
+}
+
+// Stands in for GameInputPlugin, sending each recorded turn on the tick it was
+// taken through controller::handle_input, as if it had just been pressed.
+pub struct ReplayPlaybackPlugin {
+    pub replay: Replay
+}
+
+impl Plugin for ReplayPlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(ReplayPlayback {
+                replay: self.replay.clone(),
+                next_tick: 0,
+                finished: false
+            })
+            .add_enter_system(GameState::COUNTDOWN, start_playback)
+            .add_enter_system(GameState::DEAD, finish_playback.run_if(not_resuming))
+            .add_tick_system(play_turns
+                .run_in_state(GameState::RUNNING)
+                .label("replay")
+                .before("move"))
+            .add_tick_system(controller::handle_input
+                .run_in_state(GameState::RUNNING)
+                .after("replay")
+                .before("move"));
+    }
+}
+
+pub struct ReplayPlayback {
+    replay: Replay,
+    next_tick: usize,
+    finished: bool
+}
+
+pub fn not_playing_back(playback: Option<Res<ReplayPlayback>>) -> bool {
+    playback.is_none()
+}
+
+fn start_playback(
+    mut playback: ResMut<ReplayPlayback>,
+    mut exit_events: EventWriter<AppExit>
+) {
+    if playback.finished {
+        exit_events.send(AppExit);
+    }
+    playback.next_tick = 0;
+}
+
+fn finish_playback(mut playback: ResMut<ReplayPlayback>) {
+    info!("replay finished after {} ticks", playback.next_tick);
+    playback.finished = true;
+}
+
+fn play_turns(mut playback: ResMut<ReplayPlayback>, mut direction_events: EventWriter<DirectionEvent>) {
+    let next_tick = playback.next_tick;
+    if next_tick >= playback.replay.turns.len() {
+        return;
+    }
+    let players = std::iter::once(&playback.replay.turns).chain(playback.replay.other_turns.iter());
+    for (player, turns) in players.enumerate() {
+        if let Some(Some(direction)) = turns.get(next_tick) {
+            direction_events.send(DirectionEvent { player: PlayerId(player), direction: direction.clone() });
+        }
+    }
+    playback.next_tick += 1;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::Events;
+    use crate::core::{GameRngPlugin, GameStatePlugin, GridPosition};
+    use crate::snake::head::SnakeHead;
+    use crate::snake::tail::SnakeTail;
+    use crate::tick::TickClock;
+    use super::*;
+
+    fn test_replay() -> Replay {
+        Replay {
+            board: board::Desc { grid_size: (5, 5), cell_size: 10, boundary: board::BoundaryMode::Wrap, obstacles: Vec::new() },
+            snake: snake::helpers::InitParams::single_player(GridPosition::new(0, 2), Direction::Right, 4),
+            food: food::InitParams { start_position: GridPosition::new(2, 2), spawn_points: Vec::new() },
+            seed: 99,
+            turns: vec![None, Some(Direction::Down)],
+            other_turns: Vec::new()
+        }
+    }
+
+    fn init_game(replay: &Replay) -> App {
+        let mut app = App::default();
+        app.add_plugins(MinimalPlugins);
+        app.add_plugin(GameStatePlugin{
+            tick_time_sec: 1.0,
+            game_over_pause_sec: 100.0,
+            countdown_sec: 0.0,
+            initial_state: GameState::COUNTDOWN
+        });
+        app.add_plugin(GameRngPlugin{ seed: replay.seed });
+        app.add_event::<DirectionEvent>();
+        app.insert_resource(replay.board.clone());
+        app.add_plugin(food::FoodPlugin{ init_params: replay.food.clone() });
+        app.add_plugin(snake::plugin::SnakePlugin{ init_params: replay.snake.clone() });
+        app.insert_resource(TickClock::unpaced());
+        app
+    }
+
+    fn run_until_dead(app: &mut App, inputs: &[(usize, Direction)]) {
+        for frame in 0..100 {
+            if app.world.resource::<CurrentState<GameState>>().0 == GameState::DEAD {
+                return;
+            }
+            for (_, direction) in inputs.iter().filter(|(at_frame, _)| *at_frame == frame) {
+                app.world.resource_mut::<Events<DirectionEvent>>().send(DirectionEvent {
+                    player: PlayerId(0),
+                    direction: direction.clone()
+                });
+            }
+            app.update();
+        }
+        panic!("snake never died");
+    }
+
+    fn snake_positions(app: &mut App) -> Vec<GridPosition> {
+        let head = *app.world
+            .query_filtered::<&GridPosition, With<SnakeHead>>()
+            .iter(&app.world)
+            .next()
+            .unwrap();
+        let mut tail = app.world
+            .query::<(&GridPosition, &SnakeTail)>()
+            .iter(&app.world)
+            .map(|(grid_pos, tail)| (tail.index, *grid_pos))
+            .collect::<Vec<_>>();
+        tail.sort_by_key(|(index, _)| *index);
+        std::iter::once(head).chain(tail.into_iter().map(|(_, grid_pos)| grid_pos)).collect()
+    }
+
+    fn food_positions(app: &mut App) -> Vec<GridPosition> {
+        app.world
+            .query_filtered::<&GridPosition, With<food::FoodComponent>>()
+            .iter(&app.world)
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn save_and_load() {
+        let replay = test_replay();
+        let path = std::env::temp_dir().join(format!("bevy_snake_replay_{}.json", rand::random::<u32>()));
+        replay.save(&path).unwrap();
+        assert_eq!(Replay::load(&path), Ok(replay));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_missing_file_fails() {
+        assert!(Replay::load(Path::new("does/not/exist.json")).is_err());
+    }
+
+    #[test]
+    fn playback_reproduces_recorded_game() {
+        let directory = std::env::temp_dir().join(format!("bevy_snake_replays_{}", rand::random::<u32>()));
+        fs::create_dir_all(&directory).unwrap();
+        let settings = test_replay();
+
+        let mut recorded_app = init_game(&settings);
+        recorded_app.add_plugin(ReplayRecorderPlugin{ directory: directory.clone() });
+        run_until_dead(&mut recorded_app, &[
+            (5, Direction::Down),
+            (6, Direction::Left),
+            (7, Direction::Up),
+        ]);
+        let recorded_snake = snake_positions(&mut recorded_app);
+        let recorded_food = food_positions(&mut recorded_app);
+
+        let replay = Replay::load(&directory.join("snake-99.json")).unwrap();
+        let recorded_ticks = replay.turns.len();
+        assert!(recorded_ticks > 3);
+
+        let mut played_app = init_game(&replay);
+        played_app.add_plugin(ReplayPlaybackPlugin{ replay });
+        run_until_dead(&mut played_app, &[]);
+
+        assert_eq!(played_app.world.resource::<ReplayPlayback>().next_tick, recorded_ticks);
+        assert_eq!(snake_positions(&mut played_app), recorded_snake);
+        assert_eq!(food_positions(&mut played_app), recorded_food);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    fn head_position(app: &mut App) -> GridPosition {
+        snake_positions(app)[0]
+    }
+
+    #[test]
+    fn playback_follows_reflects_that_change_direction_without_a_turn() {
+        // the snake runs up into the top wall with its tail end against the
+        // bottom one, so it bounces off sideways
+        let settings = Replay {
+            board: board::Desc { grid_size: (5, 3), cell_size: 10, boundary: board::BoundaryMode::Reflect, obstacles: Vec::new() },
+            snake: snake::helpers::InitParams::single_player(GridPosition::new(1, 2), Direction::Up, 2),
+            food: food::InitParams { start_position: GridPosition::new(4, 0), spawn_points: Vec::new() },
+            seed: 5,
+            turns: Vec::new(),
+            other_turns: Vec::new()
+        };
+        let inputs = [(6, Direction::Up), (8, Direction::Right), (12, Direction::Down)];
+
+        let mut recorded_app = init_game(&settings);
+        recorded_app.add_plugin(ReplayRecorderPlugin{ directory: std::env::temp_dir() });
+        let mut recorded_heads = Vec::new();
+        for frame in 0..20 {
+            for (_, direction) in inputs.iter().filter(|(at_frame, _)| *at_frame == frame) {
+                recorded_app.world.resource_mut::<Events<DirectionEvent>>().send(DirectionEvent {
+                    player: PlayerId(0),
+                    direction: direction.clone()
+                });
+            }
+            recorded_app.update();
+            recorded_heads.push(head_position(&mut recorded_app));
+        }
+        assert_eq!(recorded_app.world.resource::<CurrentState<GameState>>().0, GameState::RUNNING);
+        let turns = recorded_app.world.resource::<ReplayRecorder>().turns[0].clone();
+        assert!(turns.iter().any(Option::is_some));
+
+        let mut played_app = init_game(&settings);
+        played_app.add_plugin(ReplayPlaybackPlugin{ replay: Replay { turns, ..settings.clone() } });
+        for recorded_head in recorded_heads {
+            played_app.update();
+            assert_eq!(head_position(&mut played_app), recorded_head);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+use bevy::render::camera::Camera2d;
+use iyes_loopless::prelude::*;
+use crate::core::GameState;
+use crate::food;
+use crate::pixel_text;
+use crate::tick::AppTickExt;
+
+// Height of the strip above the board the HUD is drawn in; the window should be
+// this much taller than the board.
+pub const HUD_HEIGHT: f32 = 25.;
+const HUD_PIXEL_SIZE: f32 = 3.;
+const HUD_MARGIN: f32 = 5.;
+
+#[derive(Default, Debug)]
+pub struct Score {
+    pub value: u32
+}
+
+#[derive(Component, Clone)]
+struct ScoreText;
+
+// Without `hud` the score is only kept, e.g. for a frontend that draws it itself.
+pub struct ScorePlugin {
+    pub hud: bool
+}
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Score>()
+            .add_enter_system(GameState::COUNTDOWN, reset_score)
+            .add_tick_system(count_score
+                .run_in_state(GameState::RUNNING)
+                .label("score")
+                .after("collide"));
+        if self.hud {
+            app
+                .add_startup_system_to_stage(StartupStage::PostStartup, make_room_for_hud)
+                .add_system(draw_score);
+        }
+    }
+}
+
+fn reset_score(mut score: ResMut<Score>) {
+    *score = Score::default();
+}
+
+fn count_score(mut score: ResMut<Score>, mut consume_events: EventReader<food::ConsumeEvent>) {
+    score.value += consume_events.iter().count() as u32;
+}
+
+// The board's top edge sits at y = 0, so moving the camera up by half the HUD
+// height leaves the HUD strip free above it.
+fn make_room_for_hud(mut query: Query<&mut Transform, With<Camera2d>>) {
+    for mut transform in query.iter_mut() {
+        transform.translation.y += HUD_HEIGHT * 0.5;
+    }
+}
+
+fn draw_score(
+    score: Res<Score>,
+    existing_text: Query<Entity, With<ScoreText>>,
+    mut commands: Commands
+) {
+    if !score.is_changed() {
+        return;
+    }
+    for entity in existing_text.iter() {
+        commands.entity(entity).despawn();
+    }
+    pixel_text::spawn_text(
+        &mut commands,
+        &format!("SCORE {}", score.value),
+        Vec3::new(HUD_MARGIN, HUD_HEIGHT - HUD_MARGIN, 0.),
+        HUD_PIXEL_SIZE,
+        Color::WHITE,
+        ScoreText
+    );
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::Events;
+    use crate::core::PlayerId;
+    use crate::tick::TickClock;
+    use super::*;
+
+    fn init_plugin() -> App {
+        let mut app = App::default();
+        app.add_loopless_state(GameState::RUNNING);
+        app.insert_resource(TickClock::unpaced());
+        app.add_event::<food::ConsumeEvent>();
+        app.add_plugin(ScorePlugin { hud: true });
+        app.update();
+        app
+    }
+
+    fn send_consume(app: &mut App) {
+        app.world.resource_mut::<Events<food::ConsumeEvent>>().send(
+            food::ConsumeEvent{ target: Entity::from_raw(0), eater: PlayerId(0) }
+        );
+        app.update();
+    }
+
+    fn score_text_count(app: &mut App) -> usize {
+        app.world
+            .query_filtered::<Entity, With<ScoreText>>()
+            .iter(&app.world)
+            .count()
+    }
+
+    #[test]
+    fn score_counts_consume_events() {
+        let mut app = init_plugin();
+        assert_eq!(app.world.resource::<Score>().value, 0);
+        send_consume(&mut app);
+        send_consume(&mut app);
+        assert_eq!(app.world.resource::<Score>().value, 2);
+        app.update();
+        assert_eq!(app.world.resource::<Score>().value, 2);
+    }
+
+    #[test]
+    fn score_resets_on_new_game() {
+        let mut app = init_plugin();
+        send_consume(&mut app);
+        app.world.insert_resource(NextState(GameState::DEAD));
+        app.update();
+        assert_eq!(app.world.resource::<Score>().value, 1);
+        app.world.insert_resource(NextState(GameState::COUNTDOWN));
+        app.update();
+        assert_eq!(app.world.resource::<Score>().value, 0);
+    }
+
+    #[test]
+    fn hud_redrawn_when_score_changes() {
+        let mut app = init_plugin();
+        assert_eq!(score_text_count(&mut app), pixel_text::pixels("SCORE 0").len());
+        send_consume(&mut app);
+        app.update();
+        assert_eq!(score_text_count(&mut app), pixel_text::pixels("SCORE 1").len());
+    }
+
+    #[test]
+    fn camera_moved_up_for_hud() {
+        let mut app = App::default();
+        app.world.spawn().insert_bundle(OrthographicCameraBundle::new_2d());
+        app.add_startup_system_to_stage(StartupStage::PostStartup, make_room_for_hud);
+        app.update();
+        let transform = app.world
+            .query_filtered::<&Transform, With<Camera2d>>()
+            .iter(&app.world)
+            .next()
+            .unwrap();
+        assert_eq!(transform.translation.y, HUD_HEIGHT * 0.5);
+    }
+}
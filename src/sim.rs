@@ -0,0 +1,606 @@
+use std::collections::VecDeque;
+use crate::core::{DeathCause, Direction, GameRng, GridPosition, PlayerId};
+use crate::food;
+use crate::game_board::board::{self, BoundaryMode};
+use crate::game_board::helpers::direction_between;
+use crate::snake;
+
+// Pure-Rust model of a single game. Shares its rules with the Bevy systems in
+// snake::controller so the two can't drift apart, but needs no App to run.
+pub struct SnakeSim {
+    board: board::Desc,
+    body: VecDeque<GridPosition>,
+    direction: Direction,
+    previous_position: GridPosition,
+    turns: TurnQueue,
+    pending_growth: usize,
+    food: Option<GridPosition>,
+    food_params: food::InitParams,
+    death_cause: Option<DeathCause>,
+    ticks: u64,
+    rng: GameRng,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum StepOutcome {
+    Moved,
+    Ate,
+    Died,
+}
+
+impl SnakeSim {
+    pub fn new(
+        board: board::Desc,
+        snake_params: &snake::helpers::InitParams,
+        food_params: &food::InitParams,
+        seed: u64) -> Self {
+
+        SnakeSim {
+            board,
+            body: VecDeque::from(vec![snake_params.start_position]),
+            direction: snake_params.start_direction.clone(),
+            previous_position: snake_params.start_position,
+            turns: TurnQueue::default(),
+            pending_growth: snake_params.initial_tail_length,
+            food: Some(food_params.start_position),
+            food_params: food_params.clone(),
+            death_cause: None,
+            ticks: 0,
+            rng: GameRng::new(seed),
+        }
+    }
+
+    pub fn step(&mut self, inputs: &[Direction]) -> StepOutcome {
+        if !self.is_alive() {
+            return StepOutcome::Died;
+        }
+        self.ticks += 1;
+
+        for input in inputs {
+            self.turns.push(input.clone());
+        }
+        if let Some(turn) = self.turns.next_turn(self.head(), self.previous_position, &self.direction, &self.board) {
+            self.direction = turn;
+        }
+
+        let new_head = match self.board.step(self.head(), self.direction.clone()) {
+            Some(new_head) => new_head,
+            None if self.board.boundary == BoundaryMode::Reflect => {
+                let body = self.body.iter().copied().collect::<Vec<GridPosition>>();
+                let (reflected, direction) = reflect(&body, self.direction.clone(), &self.board);
+                // boxed in at both ends, there's nowhere to bounce to
+                let Some(new_head) = self.board.step(reflected[0], direction.clone()) else {
+                    self.death_cause = Some(DeathCause::HitWall);
+                    return StepOutcome::Died;
+                };
+                self.body = reflected.into();
+                self.direction = direction;
+                new_head
+            }
+            None => {
+                self.death_cause = Some(DeathCause::HitWall);
+                return StepOutcome::Died;
+            }
+        };
+
+        if self.board.is_obstacle(&new_head) {
+            self.death_cause = Some(DeathCause::HitObstacle);
+            return StepOutcome::Died;
+        }
+
+        let head = self.head();
+        let bitten = bites_self(&new_head, self.body.iter().skip(1));
+
+        self.previous_position = head;
+        self.body.push_front(new_head);
+        if self.pending_growth > 0 {
+            self.pending_growth -= 1;
+        } else {
+            self.body.pop_back();
+        }
+
+        if bitten {
+            self.death_cause = Some(DeathCause::BitSelf);
+            return StepOutcome::Died;
+        }
+
+        if self.food == Some(new_head) {
+            self.pending_growth += 1;
+            self.food = self.food_params.next_position(
+                &self.board,
+                &self.body.iter().copied().collect::<Vec<GridPosition>>(),
+                &mut self.rng
+            ).ok();
+            return StepOutcome::Ate;
+        }
+        StepOutcome::Moved
+    }
+
+    pub fn board(&self) -> &board::Desc {
+        &self.board
+    }
+
+    pub fn head(&self) -> GridPosition {
+        self.body[0]
+    }
+
+    pub fn body(&self) -> impl Iterator<Item = &GridPosition> {
+        self.body.iter()
+    }
+
+    pub fn length(&self) -> usize {
+        self.body.len()
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction.clone()
+    }
+
+    pub fn food(&self) -> Option<GridPosition> {
+        self.food
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.death_cause.is_none()
+    }
+
+    pub fn death_cause(&self) -> Option<DeathCause> {
+        self.death_cause
+    }
+
+    // segments still to be added to the tail end
+    pub fn pending_growth(&self) -> usize {
+        self.pending_growth
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+}
+
+pub const MAX_QUEUED_TURNS: usize = 3;
+
+// Turns pressed faster than the snake moves wait here and are applied one per
+// tick, so a quick Up, Left makes a tight U-turn instead of losing the Up.
+#[derive(Clone, Default, Debug)]
+pub struct TurnQueue {
+    turns: VecDeque<Direction>,
+    // what next_turn came up with last time, for recording
+    last_turn: Option<Direction>
+}
+
+impl TurnQueue {
+    pub fn push(&mut self, direction: Direction) {
+        if self.turns.len() < MAX_QUEUED_TURNS && self.turns.back() != Some(&direction) {
+            self.turns.push_back(direction);
+        }
+    }
+
+    // Each turn is checked from where the head is when it comes up, so turns
+    // that would reverse or keep going the same way are dropped then.
+    pub fn next_turn(
+        &mut self,
+        position: GridPosition,
+        previous_position: GridPosition,
+        current: &Direction,
+        board: &board::Desc) -> Option<Direction> {
+
+        self.last_turn = None;
+        while let Some(turn) = self.turns.pop_front() {
+            if turn != *current && !is_reversal(position, previous_position, turn.clone(), board) {
+                self.last_turn = Some(turn.clone());
+                return Some(turn);
+            }
+        }
+        None
+    }
+
+    // The turn taken on the last tick, if there was one.
+    pub fn last_turn(&self) -> Option<&Direction> {
+        self.last_turn.as_ref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+}
+
+// A turn is a reversal if it would put the head straight back where it came from.
+pub fn is_reversal(
+    position: GridPosition,
+    previous_position: GridPosition,
+    direction: Direction,
+    board: &board::Desc) -> bool {
+
+    board.step(position, direction) == Some(previous_position)
+}
+
+// Turns the snake around when it hits a Reflect boundary: the tail end becomes the
+// head, heading away from the rest of the body. If that cell is up against a wall
+// too the snake turns along it instead.
+pub fn reflect(
+    body: &[GridPosition],
+    direction: Direction,
+    board: &board::Desc) -> (Vec<GridPosition>, Direction) {
+
+    let reflected = body.iter().rev().copied().collect::<Vec<GridPosition>>();
+    let heading = match reflected.get(1) {
+        Some(neck) => direction_between(neck, &reflected[0]).unwrap_or_else(|| direction.opposite()),
+        None => direction.opposite()
+    };
+    let [side_a, side_b] = heading.perpendicular();
+    let new_direction = [heading.clone(), side_a, side_b]
+        .into_iter()
+        .find(|candidate| {
+            board
+                .step(reflected[0], candidate.clone())
+                .is_some_and(|next| Some(&next) != reflected.get(1))
+        })
+        .unwrap_or(heading);
+    (reflected, new_direction)
+}
+
+pub fn bites_self<'a>(
+    head: &GridPosition,
+    mut tail: impl Iterator<Item = &'a GridPosition>) -> bool {
+
+    tail.any(|tail_grid_pos| { head == tail_grid_pos })
+}
+
+// One snake as it stands between moving its head and its tail following on.
+pub struct SnakeCells<'a> {
+    pub player: PlayerId,
+    pub alive: bool,
+    pub head: GridPosition,
+    // the cell the head left this tick, which the tail hasn't caught up with yet
+    pub previous_head: GridPosition,
+    pub tail: &'a [GridPosition],
+}
+
+impl SnakeCells<'_> {
+    pub fn length(&self) -> usize {
+        1 + self.tail.len()
+    }
+
+    fn occupies(&self, grid_pos: &GridPosition) -> bool {
+        self.tail.contains(grid_pos) || (self.alive && self.previous_head == *grid_pos) || (!self.alive && self.head == *grid_pos)
+    }
+
+    fn meets_head_on(&self, other: &SnakeCells) -> bool {
+        other.alive && (self.head == other.head || (self.head == other.previous_head && other.head == self.previous_head))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Collision {
+    pub player: PlayerId,
+    pub cause: DeathCause,
+    pub killer: Option<PlayerId>,
+}
+
+// Settles every collision between the live snakes at once, so the outcome doesn't
+// depend on the order they moved in. Heads meeting, in the same cell or by
+// swapping cells, kill the shorter snake, or both if they're the same length.
+// A head running into any other part of a snake, live or dead, kills it.
+pub fn resolve_collisions(snakes: &[SnakeCells]) -> Vec<Collision> {
+    let mut collisions = Vec::new();
+    for snake in snakes.iter().filter(|snake| snake.alive) {
+        let others = || snakes.iter().filter(|other| other.player != snake.player);
+        let collision = if bites_self(&snake.head, snake.tail.iter()) {
+            Some((DeathCause::BitSelf, None))
+        } else if let Some(killer) = others()
+            .filter(|other| snake.meets_head_on(other) && other.length() >= snake.length())
+            .max_by_key(|other| other.length()) {
+            Some((DeathCause::HeadOn, Some(killer.player)))
+        } else {
+            others()
+                .find(|other| other.occupies(&snake.head) && !snake.meets_head_on(other))
+                .map(|other| (DeathCause::HitSnake, Some(other.player)))
+        };
+        if let Some((cause, killer)) = collision {
+            collisions.push(Collision { player: snake.player, cause, killer });
+        }
+    }
+    collisions
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_sim(tail_length: usize, food_position: GridPosition) -> SnakeSim {
+        init_sim_with_boundary(tail_length, food_position, BoundaryMode::Wrap)
+    }
+
+    fn init_sim_with_boundary(
+        tail_length: usize,
+        food_position: GridPosition,
+        boundary: BoundaryMode) -> SnakeSim {
+
+        SnakeSim::new(
+            board::Desc { grid_size: (5, 5), cell_size: 10, boundary, obstacles: Vec::new() },
+            &snake::helpers::InitParams::single_player(GridPosition::new(0, 2), Direction::Right, tail_length),
+            &food::InitParams { start_position: food_position, spawn_points: Vec::new() },
+            0
+        )
+    }
+
+    #[test]
+    fn step_moves_and_wraps() {
+        let mut sim = init_sim(0, GridPosition::new(0, 0));
+        for x in 1..5 {
+            assert_eq!(sim.step(&[]), StepOutcome::Moved);
+            assert_eq!(sim.head(), GridPosition::new(x, 2));
+        }
+        sim.step(&[]);
+        assert_eq!(sim.head(), GridPosition::new(0, 2));
+        assert_eq!(sim.ticks(), 5);
+    }
+
+    #[test]
+    fn step_queues_inputs() {
+        let mut sim = init_sim(0, GridPosition::new(0, 0));
+        sim.step(&[Direction::Up, Direction::Left]);
+        assert_eq!(sim.head(), GridPosition::new(0, 1));
+        assert_eq!(sim.direction(), Direction::Up);
+        sim.step(&[]);
+        assert_eq!(sim.head(), GridPosition::new(4, 1));
+        assert_eq!(sim.direction(), Direction::Left);
+    }
+
+    #[test]
+    fn turn_queue_drops_turns_invalid_when_applied() {
+        let board = board::Desc { grid_size: (5, 5), cell_size: 10, boundary: BoundaryMode::Wrap, obstacles: Vec::new() };
+        let mut turns = TurnQueue::default();
+        for direction in [Direction::Right, Direction::Left, Direction::Left, Direction::Up, Direction::Down] {
+            turns.push(direction);
+        }
+        // the repeated Left is merged and Down overflows; moving right, Right and Left are dropped
+        let (position, previous_position) = (GridPosition::new(1, 0), GridPosition::new(0, 0));
+        assert_eq!(turns.next_turn(position, previous_position, &Direction::Right, &board), Some(Direction::Up));
+        assert!(turns.is_empty());
+    }
+
+    #[test]
+    fn step_prevents_reverse() {
+        let mut sim = init_sim(2, GridPosition::new(0, 0));
+        sim.step(&[]);
+        sim.step(&[Direction::Left]);
+        assert_eq!(sim.head(), GridPosition::new(2, 2));
+        assert_eq!(sim.direction(), Direction::Right);
+        assert!(sim.is_alive());
+    }
+
+    #[test]
+    fn initial_tail_grows_in() {
+        let mut sim = init_sim(3, GridPosition::new(0, 0));
+        assert_eq!(sim.length(), 1);
+        for expected_length in 2..=4 {
+            sim.step(&[]);
+            assert_eq!(sim.length(), expected_length);
+        }
+        sim.step(&[]);
+        assert_eq!(sim.length(), 4);
+    }
+
+    #[test]
+    fn eating_grows_and_respawns_food() {
+        let mut sim = init_sim(0, GridPosition::new(1, 2));
+        assert_eq!(sim.step(&[]), StepOutcome::Ate);
+        let next_food = sim.food().unwrap();
+        assert!(!sim.body().any(|pos| *pos == next_food));
+        sim.step(&[Direction::Up]);
+        assert_eq!(sim.length(), 2);
+    }
+
+    #[test]
+    fn bite_self_kills() {
+        let mut sim = init_sim(4, GridPosition::new(4, 4));
+        sim.step(&[]);
+        sim.step(&[]);
+        sim.step(&[]);
+        sim.step(&[Direction::Down]);
+        sim.step(&[Direction::Left]);
+        assert_eq!(sim.step(&[Direction::Up]), StepOutcome::Died);
+        assert!(!sim.is_alive());
+        let ticks = sim.ticks();
+        assert_eq!(sim.step(&[]), StepOutcome::Died);
+        assert_eq!(sim.ticks(), ticks);
+    }
+
+    #[test]
+    fn solid_walls_kill() {
+        let mut sim = init_sim_with_boundary(2, GridPosition::new(0, 0), BoundaryMode::Solid);
+        for _ in 0..4 {
+            assert_eq!(sim.step(&[]), StepOutcome::Moved);
+        }
+        assert_eq!(sim.head(), GridPosition::new(4, 2));
+        assert_eq!(sim.step(&[]), StepOutcome::Died);
+        assert_eq!(sim.death_cause(), Some(DeathCause::HitWall));
+        assert_eq!(sim.head(), GridPosition::new(4, 2));
+    }
+
+    #[test]
+    fn obstacles_kill() {
+        let mut sim = init_sim(2, GridPosition::new(0, 0));
+        sim.board.obstacles.push(GridPosition::new(2, 3));
+        sim.step(&[]);
+        assert_eq!(sim.step(&[Direction::Down]), StepOutcome::Moved);
+        assert_eq!(sim.step(&[Direction::Right]), StepOutcome::Died);
+        assert_eq!(sim.death_cause(), Some(DeathCause::HitObstacle));
+        assert_eq!(sim.head(), GridPosition::new(1, 3));
+    }
+
+    #[test]
+    fn reflect_turns_snake_around() {
+        let mut sim = init_sim_with_boundary(2, GridPosition::new(0, 0), BoundaryMode::Reflect);
+        for _ in 0..4 {
+            sim.step(&[]);
+        }
+        assert!(sim.body().eq([
+            GridPosition::new(4, 2),
+            GridPosition::new(3, 2),
+            GridPosition::new(2, 2)
+        ].iter()));
+        assert_eq!(sim.step(&[]), StepOutcome::Moved);
+        assert_eq!(sim.direction(), Direction::Left);
+        assert!(sim.body().eq([
+            GridPosition::new(1, 2),
+            GridPosition::new(2, 2),
+            GridPosition::new(3, 2)
+        ].iter()));
+        assert!(sim.is_alive());
+    }
+
+    #[test]
+    fn reflect_with_nowhere_to_turn_dies() {
+        let mut sim = SnakeSim::new(
+            board::Desc { grid_size: (3, 1), cell_size: 10, boundary: BoundaryMode::Reflect, obstacles: Vec::new() },
+            &snake::helpers::InitParams::single_player(GridPosition::new(0, 0), Direction::Right, 2),
+            &food::InitParams { start_position: GridPosition::new(9, 9), spawn_points: Vec::new() },
+            0
+        );
+        assert_eq!(sim.step(&[]), StepOutcome::Moved);
+        assert_eq!(sim.step(&[]), StepOutcome::Moved);
+        assert_eq!(sim.step(&[]), StepOutcome::Died);
+        assert_eq!(sim.death_cause(), Some(DeathCause::HitWall));
+        assert!(sim.body().eq([
+            GridPosition::new(2, 0),
+            GridPosition::new(1, 0),
+            GridPosition::new(0, 0)
+        ].iter()));
+    }
+
+    #[test]
+    fn reflect_turns_along_wall_when_tail_is_against_it() {
+        let board = board::Desc { grid_size: (5, 5), cell_size: 10, boundary: BoundaryMode::Reflect, obstacles: Vec::new() };
+        let body = [GridPosition::new(0, 1), GridPosition::new(0, 0)];
+        let (reflected, direction) = reflect(&body, Direction::Left, &board);
+        assert_eq!(reflected, vec![GridPosition::new(0, 0), GridPosition::new(0, 1)]);
+        assert_eq!(direction, Direction::Right);
+
+        let (reflected, direction) = reflect(&body[..1], Direction::Left, &board);
+        assert_eq!(reflected, vec![GridPosition::new(0, 1)]);
+        assert_eq!(direction, Direction::Right);
+    }
+
+    fn cells(player: usize, alive: bool, head: (i32, i32), previous_head: (i32, i32), tail: &[GridPosition]) -> SnakeCells<'_> {
+        SnakeCells {
+            player: PlayerId(player),
+            alive,
+            head: GridPosition::new(head.0, head.1),
+            previous_head: GridPosition::new(previous_head.0, previous_head.1),
+            tail
+        }
+    }
+
+    fn collision(player: usize, cause: DeathCause, killer: Option<usize>) -> Collision {
+        Collision { player: PlayerId(player), cause, killer: killer.map(PlayerId) }
+    }
+
+    #[test]
+    fn head_into_another_body_kills_only_that_snake() {
+        let long_tail = [GridPosition::new(1, 1), GridPosition::new(0, 1)];
+        let short_tail = [GridPosition::new(4, 2)];
+        // player 1 runs into player 0's tail, and player 2 into the cell player 0's head just left
+        let snakes = [
+            cells(0, true, (3, 1), (2, 1), &long_tail),
+            cells(1, true, (1, 1), (1, 2), &short_tail),
+            cells(2, true, (2, 1), (2, 0), &[]),
+        ];
+        assert_eq!(resolve_collisions(&snakes), vec![
+            collision(1, DeathCause::HitSnake, Some(0)),
+            collision(2, DeathCause::HitSnake, Some(0)),
+        ]);
+    }
+
+    #[test]
+    fn head_on_kills_shorter_snake_or_both() {
+        let long_tail = [GridPosition::new(0, 2), GridPosition::new(0, 3)];
+        let short_tail = [GridPosition::new(4, 2)];
+        let snakes = [
+            cells(0, true, (2, 2), (1, 2), &long_tail),
+            cells(1, true, (2, 2), (3, 2), &short_tail),
+        ];
+        assert_eq!(resolve_collisions(&snakes), vec![collision(1, DeathCause::HeadOn, Some(0))]);
+
+        let snakes = [
+            cells(0, true, (2, 2), (1, 2), &long_tail[..1]),
+            cells(1, true, (2, 2), (3, 2), &short_tail),
+        ];
+        assert_eq!(resolve_collisions(&snakes), vec![
+            collision(0, DeathCause::HeadOn, Some(1)),
+            collision(1, DeathCause::HeadOn, Some(0)),
+        ]);
+    }
+
+    #[test]
+    fn swapping_cells_is_head_on() {
+        let long_tail = [GridPosition::new(0, 2), GridPosition::new(0, 3)];
+        let snakes = [
+            cells(0, true, (2, 2), (1, 2), &long_tail),
+            cells(1, true, (1, 2), (2, 2), &[]),
+        ];
+        assert_eq!(resolve_collisions(&snakes), vec![collision(1, DeathCause::HeadOn, Some(0))]);
+
+        let snakes = [
+            cells(0, true, (2, 2), (1, 2), &[]),
+            cells(1, true, (1, 2), (2, 2), &[]),
+        ];
+        assert_eq!(resolve_collisions(&snakes), vec![
+            collision(0, DeathCause::HeadOn, Some(1)),
+            collision(1, DeathCause::HeadOn, Some(0)),
+        ]);
+    }
+
+    #[test]
+    fn dead_snakes_stay_in_the_way() {
+        let tail = [GridPosition::new(1, 1)];
+        let bitten_tail = [GridPosition::new(3, 3), GridPosition::new(4, 3)];
+        let snakes = [
+            // a dead snake's stale previous head doesn't count, its head does
+            cells(0, false, (2, 1), (3, 3), &tail),
+            cells(1, true, (2, 1), (2, 0), &[]),
+            cells(2, true, (3, 3), (3, 4), &bitten_tail),
+            cells(3, true, (4, 4), (3, 4), &[]),
+        ];
+        assert_eq!(resolve_collisions(&snakes), vec![
+            collision(1, DeathCause::HitSnake, Some(0)),
+            collision(2, DeathCause::BitSelf, None),
+        ]);
+    }
+
+    #[test]
+    fn reversal_respects_boundary() {
+        let mut board = board::Desc { grid_size: (2, 2), cell_size: 10, boundary: BoundaryMode::Wrap, obstacles: Vec::new() };
+        // on a two wide wrapping board moving on looks like reversing
+        assert!(is_reversal(GridPosition::new(1, 0), GridPosition::new(0, 0), Direction::Right, &board));
+        board.boundary = BoundaryMode::Solid;
+        assert!(!is_reversal(GridPosition::new(1, 0), GridPosition::new(0, 0), Direction::Right, &board));
+        assert!(is_reversal(GridPosition::new(1, 0), GridPosition::new(0, 0), Direction::Left, &board));
+    }
+
+    #[test]
+    fn same_seed_same_game() {
+        let mut first = init_sim(3, GridPosition::new(1, 2));
+        let mut second = init_sim(3, GridPosition::new(1, 2));
+        let inputs = [Direction::Down, Direction::Left, Direction::Up, Direction::Right];
+        for tick in 0..200 {
+            let input = [inputs[(tick / 3) % inputs.len()].clone()];
+            assert_eq!(first.step(&input), second.step(&input));
+            assert_eq!(first.food(), second.food());
+            assert!(first.body().eq(second.body()));
+        }
+    }
+
+    #[test]
+    fn runs_many_ticks_without_app() {
+        let mut sim = init_sim(3, GridPosition::new(0, 0));
+        for _ in 0..1_000_000 {
+            sim.step(&[]);
+        }
+        assert!(sim.is_alive());
+        assert_eq!(sim.ticks(), 1_000_000);
+    }
+}
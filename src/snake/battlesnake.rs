@@ -0,0 +1,489 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::core::{Direction, GridPosition};
+use crate::game_board::board::BoundaryMode;
+
+use super::brain::{BoardView, SnakeBrain, SnakeView};
+
+static GAMES: AtomicU64 = AtomicU64::new(0);
+
+// The request bodies of the Battlesnake API, https://docs.battlesnake.com/api.
+// Battlesnake counts y up from the bottom of the board, so rows are flipped on
+// the way out. Battlesnake snakes don't outlive their death, so the bodies of
+// dead snakes, which still block the board here, are sent as hazards along with
+// the obstacles, and hazards are set to do a full 100 damage.
+#[derive(Serialize)]
+struct GameRequest<'a> {
+    game: Game<'a>,
+    turn: u64,
+    board: Board,
+    you: Snake
+}
+
+#[derive(Serialize)]
+struct Game<'a> {
+    id: &'a str,
+    ruleset: Ruleset,
+    map: &'static str,
+    timeout: u64,
+    source: &'static str
+}
+
+#[derive(Serialize)]
+struct Ruleset {
+    name: &'static str,
+    version: &'static str,
+    settings: RulesetSettings
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RulesetSettings {
+    food_spawn_chance: u32,
+    minimum_food: u32,
+    hazard_damage_per_turn: u32
+}
+
+#[derive(Serialize)]
+struct Board {
+    height: i32,
+    width: i32,
+    food: Vec<Point>,
+    hazards: Vec<Point>,
+    snakes: Vec<Snake>
+}
+
+#[derive(Serialize, Clone)]
+struct Snake {
+    id: String,
+    name: String,
+    health: u32,
+    body: Vec<Point>,
+    latency: String,
+    head: Point,
+    length: usize,
+    shout: String,
+    squad: String
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+struct Point {
+    x: i32,
+    y: i32
+}
+
+#[derive(Deserialize)]
+struct MoveReply {
+    #[serde(rename = "move")]
+    direction: String
+}
+
+// A bot served over HTTP by a Battlesnake server, usually one on localhost. The
+// server is sent /start before the first move and /end when the game is over.
+// A move that doesn't come back within the timeout carries the snake straight
+// on, as a Battlesnake referee would. A server that can't be reached or answers
+// with an error fails the bot, and its snake dies.
+pub struct BattlesnakeBrain {
+    address: String,
+    path: String,
+    game_id: String,
+    timeout: Duration,
+    turn: u64,
+    last_request: Option<String>,
+    failure: Option<String>
+}
+
+impl BattlesnakeBrain {
+    // `url` is `http://HOST:PORT/PATH`, with the API endpoints under PATH.
+    pub fn new(url: &str, timeout: Duration) -> BattlesnakeBrain {
+        let mut brain = BattlesnakeBrain {
+            address: String::new(),
+            path: String::new(),
+            game_id: format!("bevy_snake-{}-{}", std::process::id(), GAMES.fetch_add(1, Ordering::Relaxed)),
+            timeout,
+            turn: 0,
+            last_request: None,
+            failure: None
+        };
+        match url.strip_prefix("http://") {
+            Some(rest) => {
+                let (address, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+                brain.address = match address.contains(':') {
+                    true => String::from(address),
+                    false => format!("{}:80", address)
+                };
+                brain.path = String::from(path.trim_end_matches('/'));
+            }
+            None => brain.failure = Some(format!("unsupported bot url '{}', expected http://HOST:PORT", url))
+        }
+        brain
+    }
+
+    fn request(&self, view: &BoardView) -> Result<String, String> {
+        let height = view.board.grid_size.1;
+        let point = |grid_pos: &GridPosition| Point { x: grid_pos.x, y: height - 1 - grid_pos.y };
+        let snake = |snake: &SnakeView| {
+            let mut body = snake.body.iter().map(point).collect::<Vec<_>>();
+            // growth shows up as segments stacked on the tail
+            if let Some(tail) = body.last().copied() {
+                body.extend(std::iter::repeat(tail).take(snake.growing));
+            }
+            Snake {
+                id: format!("player-{}", snake.player.0 + 1),
+                name: format!("Player {}", snake.player.0 + 1),
+                health: 100,
+                head: body[0],
+                length: body.len(),
+                body,
+                latency: String::from("0"),
+                shout: String::new(),
+                squad: String::new()
+            }
+        };
+        let snakes = view.snakes.iter().filter(|snake| snake.alive && !snake.body.is_empty()).map(snake).collect::<Vec<_>>();
+        let you = snakes
+            .iter()
+            .find(|snake| snake.id == format!("player-{}", view.player.0 + 1))
+            .cloned()
+            .ok_or("bot's snake isn't on the board")?;
+        let hazards = view.board.obstacles
+            .iter()
+            .chain(view.snakes.iter().filter(|snake| !snake.alive).flat_map(|snake| snake.body.iter()))
+            .map(point)
+            .collect();
+        let request = GameRequest {
+            game: Game {
+                id: &self.game_id,
+                ruleset: Ruleset {
+                    name: match view.board.boundary {
+                        BoundaryMode::Wrap => "wrapped",
+                        _ => "standard"
+                    },
+                    version: "bevy_snake",
+                    settings: RulesetSettings { food_spawn_chance: 0, minimum_food: 1, hazard_damage_per_turn: 100 }
+                },
+                map: "standard",
+                timeout: self.timeout.as_millis() as u64,
+                source: "custom"
+            },
+            turn: self.turn,
+            board: Board {
+                height,
+                width: view.board.grid_size.0,
+                food: view.food.iter().map(point).collect(),
+                hazards,
+                snakes
+            },
+            you
+        };
+        serde_json::to_string(&request).map_err(|e| e.to_string())
+    }
+
+    fn ask(&mut self, view: &BoardView) -> Result<Option<Direction>, String> {
+        let request = self.request(view)?;
+        if self.turn == 0 {
+            post(&self.address, &format!("{}/start", self.path), &request, self.timeout)?;
+        }
+        let reply = post(&self.address, &format!("{}/move", self.path), &request, self.timeout)?;
+        self.turn += 1;
+        self.last_request = Some(request);
+        let reply = match reply {
+            Some(reply) => reply,
+            None => return Ok(None)
+        };
+        let reply = serde_json::from_str::<MoveReply>(&reply).map_err(|e| format!("invalid move '{}': {}", reply, e))?;
+        match reply.direction.as_str() {
+            "up" => Ok(Some(Direction::Up)),
+            "down" => Ok(Some(Direction::Down)),
+            "left" => Ok(Some(Direction::Left)),
+            "right" => Ok(Some(Direction::Right)),
+            other => Err(format!("invalid move '{}'", other))
+        }
+    }
+}
+
+impl SnakeBrain for BattlesnakeBrain {
+    fn next_direction(&mut self, view: &BoardView) -> Direction {
+        if self.failure.is_some() {
+            return view.direction.clone();
+        }
+        match self.ask(view) {
+            Ok(Some(direction)) => direction,
+            Ok(None) => view.direction.clone(),
+            Err(e) => {
+                self.failure = Some(e);
+                view.direction.clone()
+            }
+        }
+    }
+
+    fn failure(&self) -> Option<String> {
+        self.failure.clone()
+    }
+}
+
+impl Drop for BattlesnakeBrain {
+    fn drop(&mut self) {
+        if let (Some(request), None) = (&self.last_request, &self.failure) {
+            let _ = post(&self.address, &format!("{}/end", self.path), request, self.timeout);
+        }
+    }
+}
+
+// POSTs JSON over a fresh connection and returns the response body, or None if
+// the server didn't answer in time. Only as much HTTP/1.1 as a Battlesnake
+// server needs: no TLS, no redirects.
+fn post(address: &str, path: &str, body: &str, timeout: Duration) -> Result<Option<String>, String> {
+    let deadline = Instant::now() + timeout;
+    let socket_address: SocketAddr = address
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+        .ok_or_else(|| format!("can't resolve {}", address))?;
+    let mut stream = match TcpStream::connect_timeout(&socket_address, timeout) {
+        Ok(stream) => stream,
+        Err(e) if is_timeout(&e) => return Ok(None),
+        Err(e) => return Err(format!("can't connect to {}: {}", address, e))
+    };
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        if path.is_empty() { "/" } else { path },
+        address,
+        body.len(),
+        body
+    );
+    let _ = stream.set_write_timeout(Some(timeout));
+    match stream.write_all(request.as_bytes()) {
+        Ok(()) => {}
+        Err(e) if is_timeout(&e) => return Ok(None),
+        Err(e) => return Err(format!("failed to send to {}: {}", address, e))
+    }
+
+    let mut response = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        let _ = stream.set_read_timeout(Some(remaining));
+        match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => response.extend_from_slice(&buffer[..read]),
+            Err(e) if is_timeout(&e) => return Ok(None),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(format!("failed to read from {}: {}", address, e))
+        }
+        if let Some(body) = parse_response(&response) {
+            return body.map(Some);
+        }
+    }
+    parse_response(&response)
+        .unwrap_or_else(|| Err(format!("incomplete response from {}", address)))
+        .map(Some)
+}
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock)
+}
+
+// None until the whole response has arrived.
+fn parse_response(response: &[u8]) -> Option<Result<String, String>> {
+    let header_end = response.windows(4).position(|window| window == b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&response[..header_end]);
+    let body = &response[header_end + 4..];
+    let mut lines = head.lines();
+    let status = lines.next().unwrap_or_default();
+    let mut content_length = None;
+    let mut chunked = false;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_lowercase().as_str() {
+                "content-length" => content_length = value.trim().parse::<usize>().ok(),
+                "transfer-encoding" => chunked = value.to_lowercase().contains("chunked"),
+                _ => {}
+            }
+        }
+    }
+    let body = if chunked {
+        dechunk(body)?
+    } else if let Some(length) = content_length {
+        body.get(..length)?.to_vec()
+    } else {
+        return None;
+    };
+
+    let body = String::from_utf8_lossy(&body).into_owned();
+    Some(match status.split_whitespace().nth(1) {
+        Some("200") => Ok(body),
+        _ => Err(format!("server answered '{}': {}", status, body.trim()))
+    })
+}
+
+fn dechunk(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|window| window == b"\r\n")?;
+        let size = String::from_utf8_lossy(&body[..line_end]);
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.extend_from_slice(body.get(..size)?);
+        body = body.get(size + 2..)?;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+    use crate::core::PlayerId;
+    use crate::game_board::board;
+    use super::*;
+
+    // A Battlesnake server on a free localhost port that answers every request
+    // with `reply` after `delay`, and passes on what it was sent.
+    fn stub_server(reply: &'static str, delay: Duration) -> (String, Receiver<(String, serde_json::Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/snake", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                // requests are small enough that the whole body is in once the length is
+                let request = loop {
+                    let read = stream.read(&mut buffer).unwrap_or(0);
+                    if read == 0 {
+                        break None;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request).into_owned();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|line| line.strip_prefix("Content-Length: "))
+                            .and_then(|length| length.parse::<usize>().ok())
+                            .unwrap();
+                        if body.len() >= length {
+                            break Some((head.split_whitespace().nth(1).unwrap().to_string(), body.to_string()));
+                        }
+                    }
+                };
+                let Some((path, body)) = request else { continue };
+                thread::sleep(delay);
+                let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}", reply.len(), reply);
+                if sender.send((path, serde_json::from_str(&body).unwrap())).is_err() {
+                    break;
+                }
+            }
+        });
+        (url, receiver)
+    }
+
+    fn think(brain: &mut BattlesnakeBrain) -> Direction {
+        let board = board::Desc {
+            grid_size: (5, 4),
+            cell_size: 10,
+            boundary: BoundaryMode::Solid,
+            obstacles: vec![GridPosition::new(0, 0)]
+        };
+        let snakes = [
+            SnakeView {
+                player: PlayerId(0),
+                alive: true,
+                body: vec![GridPosition::new(2, 1), GridPosition::new(1, 1)],
+                growing: 1
+            },
+            SnakeView { player: PlayerId(1), alive: false, body: vec![GridPosition::new(4, 3)], growing: 0 },
+        ];
+        brain.next_direction(&BoardView {
+            board: &board,
+            player: PlayerId(0),
+            direction: Direction::Right,
+            snakes: &snakes,
+            food: &[GridPosition::new(4, 0)]
+        })
+    }
+
+    #[test]
+    fn moves_come_from_the_server() {
+        let (url, requests) = stub_server(r#"{"move":"up","shout":"hi"}"#, Duration::ZERO);
+        let mut brain = BattlesnakeBrain::new(&url, Duration::from_millis(2000));
+        assert_eq!(think(&mut brain), Direction::Up);
+        assert_eq!(think(&mut brain), Direction::Up);
+        assert_eq!(brain.failure(), None);
+        drop(brain);
+
+        let paths = requests.iter().take(4).map(|(path, _)| path).collect::<Vec<_>>();
+        assert_eq!(paths, ["/snake/start", "/snake/move", "/snake/move", "/snake/end"]);
+    }
+
+    #[test]
+    fn server_sees_the_board_bottom_up() {
+        let (url, requests) = stub_server(r#"{"move":"up"}"#, Duration::ZERO);
+        let mut brain = BattlesnakeBrain::new(&url, Duration::from_millis(2000));
+        think(&mut brain);
+        let (_, request) = requests.recv().unwrap();
+
+        assert_eq!(request["turn"], 0);
+        assert_eq!(request["game"]["ruleset"]["name"], "standard");
+        assert_eq!(request["game"]["timeout"], 2000);
+        assert_eq!(request["board"]["width"], 5);
+        assert_eq!(request["board"]["height"], 4);
+        assert_eq!(request["board"]["food"], serde_json::json!([{"x": 4, "y": 3}]));
+        // the obstacle, then the dead snake
+        assert_eq!(request["board"]["hazards"], serde_json::json!([{"x": 0, "y": 3}, {"x": 4, "y": 0}]));
+        assert_eq!(request["board"]["snakes"].as_array().unwrap().len(), 1);
+        assert_eq!(request["you"]["id"], "player-1");
+        assert_eq!(request["you"]["head"], serde_json::json!({"x": 2, "y": 2}));
+        assert_eq!(request["you"]["body"], serde_json::json!([{"x": 2, "y": 2}, {"x": 1, "y": 2}, {"x": 1, "y": 2}]));
+        assert_eq!(request["you"]["length"], 3);
+    }
+
+    #[test]
+    fn slow_server_carries_straight_on() {
+        let (url, _requests) = stub_server(r#"{"move":"up"}"#, Duration::from_millis(300));
+        let mut brain = BattlesnakeBrain::new(&url, Duration::from_millis(50));
+        let started = Instant::now();
+        assert_eq!(think(&mut brain), Direction::Right);
+        assert!(started.elapsed() < Duration::from_millis(250));
+        assert_eq!(brain.failure(), None);
+    }
+
+    #[test]
+    fn bad_servers_fail_the_bot() {
+        let (url, _requests) = stub_server(r#"{"move":"sideways"}"#, Duration::ZERO);
+        let mut brain = BattlesnakeBrain::new(&url, Duration::from_millis(2000));
+        assert_eq!(think(&mut brain), Direction::Right);
+        assert_eq!(brain.failure(), Some(String::from("invalid move 'sideways'")));
+
+        // nothing listens on a port that was just freed
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut brain = BattlesnakeBrain::new(&format!("http://{}", address), Duration::from_millis(2000));
+        assert_eq!(think(&mut brain), Direction::Right);
+        assert!(brain.failure().unwrap().starts_with("can't connect"));
+
+        let brain = BattlesnakeBrain::new("https://example.com", Duration::from_millis(50));
+        assert!(brain.failure().unwrap().starts_with("unsupported bot url"));
+    }
+
+    #[test]
+    fn reads_chunked_and_error_responses() {
+        let chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\n{\"mov\r\n8\r\ne\":\"up\"}\r\n0\r\n\r\n";
+        assert_eq!(parse_response(chunked), Some(Ok(String::from("{\"move\":\"up\"}"))));
+        assert_eq!(parse_response(&chunked[..60]), None);
+        assert_eq!(
+            parse_response(b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 4\r\n\r\noops"),
+            Some(Err(String::from("server answered 'HTTP/1.1 500 Internal Server Error': oops")))
+        );
+    }
+}
@@ -0,0 +1,633 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::time::Duration;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::core::{DeathCause, Direction, GridPosition, PlayerId};
+use crate::food::FoodComponent;
+use crate::game_board::board::{self, BoundaryMode};
+use crate::game_board::helpers::direction_between;
+
+use super::battlesnake::BattlesnakeBrain;
+use super::controller::{Dead, LiveHead, MovementController, SnakeDied};
+use super::external::{self, ExternalBrain};
+use super::head::SnakeHead;
+use super::tail::SnakeTail;
+
+const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+// One snake as a brain sees it, head first. Segments still growing in are left
+// out of `body` and counted in `growing`.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct SnakeView {
+    pub player: PlayerId,
+    pub alive: bool,
+    pub body: Vec<GridPosition>,
+    pub growing: usize
+}
+
+// A read-only snapshot of the board for the snake being steered.
+pub struct BoardView<'a> {
+    pub board: &'a board::Desc,
+    pub player: PlayerId,
+    pub direction: Direction,
+    pub snakes: &'a [SnakeView],
+    pub food: &'a [GridPosition]
+}
+
+impl BoardView<'_> {
+    pub fn me(&self) -> Option<&SnakeView> {
+        self.snakes.iter().find(|snake| snake.player == self.player)
+    }
+
+    pub fn head(&self) -> Option<GridPosition> {
+        self.me().and_then(|snake| snake.body.first().copied())
+    }
+
+    pub fn is_free(&self, grid_pos: &GridPosition) -> bool {
+        !self.board.is_obstacle(grid_pos) && !self.snakes.iter().any(|snake| snake.body.contains(grid_pos))
+    }
+
+    // The moves from `grid_pos` that stay on the board and don't run straight
+    // into anything.
+    pub fn free_moves(&self, grid_pos: GridPosition) -> Vec<(Direction, GridPosition)> {
+        DIRECTIONS
+            .iter()
+            .filter_map(|direction| self.board.step(grid_pos, direction.clone()).map(|next| (direction.clone(), next)))
+            .filter(|(_, next)| self.is_free(next))
+            .collect()
+    }
+
+    // Steps between two cells ignoring anything in the way, taking the short way
+    // round on a wrapping board.
+    pub fn distance(&self, from: &GridPosition, to: &GridPosition) -> i32 {
+        let axis = |from: i32, to: i32, size: i32| {
+            let straight = (to - from).abs();
+            match self.board.boundary {
+                BoundaryMode::Wrap => straight.min(size - straight),
+                BoundaryMode::Solid | BoundaryMode::Reflect => straight
+            }
+        };
+        axis(from.x, to.x, self.board.grid_size.0) + axis(from.y, to.y, self.board.grid_size.1)
+    }
+}
+
+// Steers a computer-controlled snake. Called once a tick before the snakes move;
+// the direction it returns is queued like a key press, so it's subject to the
+// same rules as player input and a reversal is simply ignored.
+pub trait SnakeBrain: Send + Sync {
+    fn next_direction(&mut self, view: &BoardView) -> Direction;
+
+    // Why the brain can't carry on, once it can't. Its snake dies.
+    fn failure(&self) -> Option<String> {
+        None
+    }
+}
+
+// Takes whichever free move gets closest to the nearest food, without looking
+// any further ahead.
+#[derive(Default)]
+pub struct GreedyBrain;
+
+impl SnakeBrain for GreedyBrain {
+    fn next_direction(&mut self, view: &BoardView) -> Direction {
+        let head = match view.head() {
+            Some(head) => head,
+            None => return view.direction.clone()
+        };
+        let closest_food = |grid_pos: &GridPosition| {
+            view.food.iter().map(|food| view.distance(grid_pos, food)).min().unwrap_or_default()
+        };
+        view.free_moves(head)
+            .into_iter()
+            // ties go to carrying straight on
+            .min_by_key(|(direction, next)| (closest_food(next), *direction != view.direction))
+            .map(|(direction, _)| direction)
+            .unwrap_or_else(|| view.direction.clone())
+    }
+}
+
+// Follows the shortest clear path to any food, falling back to GreedyBrain when
+// the food is walled off.
+#[derive(Default)]
+pub struct BfsBrain;
+
+impl SnakeBrain for BfsBrain {
+    fn next_direction(&mut self, view: &BoardView) -> Direction {
+        let head = match view.head() {
+            Some(head) => head,
+            None => return view.direction.clone()
+        };
+        // each visited cell remembers the first move on the way to it
+        let mut first_moves: HashMap<GridPosition, Direction> = HashMap::default();
+        let mut frontier = VecDeque::new();
+        for (direction, next) in view.free_moves(head) {
+            first_moves.insert(next, direction);
+            frontier.push_back(next);
+        }
+        while let Some(grid_pos) = frontier.pop_front() {
+            let first_move = first_moves[&grid_pos].clone();
+            if view.food.contains(&grid_pos) {
+                return first_move;
+            }
+            for (_, next) in view.free_moves(grid_pos) {
+                if next != head && !first_moves.contains_key(&next) {
+                    first_moves.insert(next, first_move.clone());
+                    frontier.push_back(next);
+                }
+            }
+        }
+        GreedyBrain.next_direction(view)
+    }
+}
+
+// A closed path through every cell of the board, one step at a time. Only
+// boards with an even number of rows or columns have one.
+pub struct HamiltonianCycle {
+    grid_size: (i32, i32),
+    cells: Vec<GridPosition>,
+    // where each cell, by y * width + x, comes in `cells`
+    order: Vec<usize>
+}
+
+impl HamiltonianCycle {
+    pub fn new(grid_size: (i32, i32)) -> Option<HamiltonianCycle> {
+        let (width, height) = grid_size;
+        if width < 2 || height < 2 {
+            return None;
+        }
+        let transpose = height % 2 != 0;
+        let (columns, rows) = if transpose { (height, width) } else { (width, height) };
+        if rows % 2 != 0 {
+            return None;
+        }
+        // along the first row, back and forth over the rest leaving out the
+        // first column, then back up the first column
+        let mut cells = (0..columns).map(|x| (x, 0)).collect::<Vec<_>>();
+        for y in 1..rows {
+            if y % 2 == 1 {
+                cells.extend((1..columns).rev().map(|x| (x, y)));
+            } else {
+                cells.extend((1..columns).map(|x| (x, y)));
+            }
+        }
+        cells.extend((1..rows).rev().map(|y| (0, y)));
+        let cells = cells
+            .into_iter()
+            .map(|(x, y)| if transpose { GridPosition::new(y, x) } else { GridPosition::new(x, y) })
+            .collect::<Vec<_>>();
+
+        let mut order = vec![0; cells.len()];
+        for (index, cell) in cells.iter().enumerate() {
+            order[(cell.y * width + cell.x) as usize] = index;
+        }
+        Some(HamiltonianCycle { grid_size, cells, order })
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn index(&self, grid_pos: &GridPosition) -> usize {
+        self.order[(grid_pos.y * self.grid_size.0 + grid_pos.x) as usize]
+    }
+
+    // Steps forward round the cycle from one cell to another.
+    pub fn distance(&self, from: &GridPosition, to: &GridPosition) -> usize {
+        (self.index(to) + self.len() - self.index(from)) % self.len()
+    }
+
+    pub fn next(&self, grid_pos: &GridPosition) -> GridPosition {
+        self.cells[(self.index(grid_pos) + 1) % self.len()]
+    }
+}
+
+// Keeps to a Hamiltonian cycle so it can never trap itself and fills the board
+// in the end. While the snake is short it cuts across the cycle towards the
+// food, as long as the jump leaves its head behind its tail in cycle order
+// with room to spare for growing. Boards without a cycle, or with obstacles,
+// get BfsBrain instead.
+#[derive(Default)]
+pub struct HamiltonianBrain {
+    cycle: Option<HamiltonianCycle>
+}
+
+// The room kept between head and tail on top of any growing still to do.
+const SHORTCUT_SLACK: usize = 2;
+
+impl SnakeBrain for HamiltonianBrain {
+    fn next_direction(&mut self, view: &BoardView) -> Direction {
+        if !view.board.obstacles.is_empty() {
+            return BfsBrain.next_direction(view);
+        }
+        if self.cycle.as_ref().map(|cycle| cycle.grid_size) != Some(view.board.grid_size) {
+            self.cycle = HamiltonianCycle::new(view.board.grid_size);
+        }
+        let (cycle, me) = match (&self.cycle, view.me()) {
+            (Some(cycle), Some(me)) if !me.body.is_empty() => (cycle, me),
+            _ => return BfsBrain.next_direction(view)
+        };
+        let head = me.body[0];
+        let tail_end = me.body[me.body.len() - 1];
+        let room = match cycle.distance(&head, &tail_end) {
+            0 => cycle.len(),
+            distance => distance
+        };
+        let next = cycle.next(&head);
+
+        let food_distance = view.food.iter().map(|food| cycle.distance(&head, food)).min();
+        let shortcut = match food_distance {
+            Some(food_distance) if me.body.len() + me.growing < cycle.len() / 2 => view
+                .free_moves(head)
+                .into_iter()
+                .map(|(direction, grid_pos)| (direction, cycle.distance(&head, &grid_pos)))
+                .filter(|(_, jump)| *jump <= food_distance && jump + me.growing + SHORTCUT_SLACK < room)
+                .max_by_key(|(_, jump)| *jump)
+                .map(|(direction, _)| direction),
+            _ => None
+        };
+        shortcut
+            .or_else(|| direction_between(&head, &next))
+            .unwrap_or_else(|| view.direction.clone())
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum BotKind {
+    Greedy,
+    Bfs,
+    Hamiltonian,
+    // a program speaking the protocol in snake::external
+    External { command: String, timeout_ms: u64 },
+    // a Battlesnake server, see snake::battlesnake
+    Battlesnake { url: String, timeout_ms: u64 }
+}
+
+impl BotKind {
+    pub fn brain(&self) -> Box<dyn SnakeBrain> {
+        match self {
+            BotKind::Greedy => Box::new(GreedyBrain),
+            BotKind::Bfs => Box::new(BfsBrain),
+            BotKind::Hamiltonian => Box::new(HamiltonianBrain::default()),
+            BotKind::External { command, timeout_ms } => Box::new(
+                ExternalBrain::from_command_line(command, Duration::from_millis(*timeout_ms))
+            ),
+            BotKind::Battlesnake { url, timeout_ms } => Box::new(
+                BattlesnakeBrain::new(url, Duration::from_millis(*timeout_ms))
+            )
+        }
+    }
+}
+
+impl FromStr for BotKind {
+    type Err = String;
+
+    // `exec:COMMAND` runs an external bot and `http://HOST:PORT` plays a Battlesnake server
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") {
+            return Ok(BotKind::Battlesnake { url: String::from(s), timeout_ms: external::DEFAULT_MOVE_TIMEOUT_MS });
+        }
+        if let Some(command) = s.strip_prefix("exec:") {
+            if command.trim().is_empty() {
+                return Err(String::from("external bot has no command, expected exec:COMMAND"));
+            }
+            return Ok(BotKind::External {
+                command: String::from(command.trim()),
+                timeout_ms: external::DEFAULT_MOVE_TIMEOUT_MS
+            });
+        }
+        match s.to_lowercase().as_str() {
+            "greedy" => Ok(BotKind::Greedy),
+            "bfs" => Ok(BotKind::Bfs),
+            "hamiltonian" => Ok(BotKind::Hamiltonian),
+            _ => Err(format!("unknown bot '{}', expected greedy, bfs, hamiltonian, exec:COMMAND or http://HOST:PORT", s))
+        }
+    }
+}
+
+// Every snake from its head, given with whether it's alive, and its tail segments.
+pub fn snake_views(
+    game_board: &board::Desc,
+    heads: impl Iterator<Item = (PlayerId, GridPosition, bool)>,
+    tails: &[(PlayerId, usize, GridPosition)]
+) -> Vec<SnakeView> {
+    heads
+        .map(|(player, head_grid_pos, alive)| {
+            let (mut tail, growing): (Vec<_>, Vec<_>) = tails
+                .iter()
+                .filter(|(tail_player, _, _)| *tail_player == player)
+                .map(|(_, index, tail_grid_pos)| (*index, *tail_grid_pos))
+                .partition(|(_, tail_grid_pos)| game_board.is_inside(tail_grid_pos));
+            tail.sort_by_key(|(index, _)| *index);
+            SnakeView {
+                player,
+                alive,
+                body: std::iter::once(head_grid_pos).chain(tail.into_iter().map(|(_, grid_pos)| grid_pos)).collect(),
+                growing: growing.len()
+            }
+        })
+        .collect()
+}
+
+// On a snake's head in place of keyboard and gamepad input.
+#[derive(Component)]
+pub struct BotControlled(pub Box<dyn SnakeBrain>);
+
+pub fn drive_bots(
+    game_board: Res<board::Desc>,
+    mut death_events: EventWriter<SnakeDied>,
+    mut bot_query: Query<(&PlayerId, &mut BotControlled, &mut MovementController), LiveHead>,
+    head_query: Query<(&PlayerId, &GridPosition, Option<&Dead>), With<SnakeHead>>,
+    tail_query: Query<(&PlayerId, &GridPosition, &SnakeTail)>,
+    food_query: Query<&GridPosition, With<FoodComponent>>
+) {
+    if bot_query.is_empty() {
+        return;
+    }
+    let tails = tail_query
+        .iter()
+        .map(|(player, tail_grid_pos, tail)| (*player, tail.index, *tail_grid_pos))
+        .collect::<Vec<_>>();
+    let heads = head_query.iter().map(|(player, head_grid_pos, dead)| (*player, *head_grid_pos, dead.is_none()));
+    let snakes = snake_views(&game_board, heads, &tails);
+    let food = food_query.iter().copied().collect::<Vec<_>>();
+
+    for (player, mut bot, mut movement) in bot_query.iter_mut() {
+        let view = BoardView {
+            board: &game_board,
+            player: *player,
+            direction: movement.direction.clone(),
+            snakes: &snakes,
+            food: &food
+        };
+        let direction = bot.0.next_direction(&view);
+        match bot.0.failure() {
+            Some(failure) => {
+                warn!("player {} bot failed: {}", player.0 + 1, failure);
+                death_events.send(SnakeDied { player: *player, cause: DeathCause::BotError, killer: None });
+            }
+            None => movement.turns.push(direction)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::Events;
+    use crate::core::DirectionEvent;
+    use crate::food;
+    use crate::sim::{SnakeSim, StepOutcome};
+    use crate::snake::{controller, helpers};
+    use super::*;
+
+    fn board(boundary: BoundaryMode, obstacles: Vec<GridPosition>) -> board::Desc {
+        board::Desc { grid_size: (5, 5), cell_size: 10, boundary, obstacles }
+    }
+
+    fn snake(player: usize, body: &[(i32, i32)]) -> SnakeView {
+        SnakeView {
+            player: PlayerId(player),
+            alive: true,
+            body: body.iter().map(|(x, y)| GridPosition::new(*x, *y)).collect(),
+            growing: 0
+        }
+    }
+
+    fn think(brain: &mut dyn SnakeBrain, board: &board::Desc, snakes: &[SnakeView], food: &[GridPosition]) -> Direction {
+        brain.next_direction(&BoardView { board, player: PlayerId(0), direction: Direction::Right, snakes, food })
+    }
+
+    #[test]
+    fn greedy_heads_for_nearest_food() {
+        let board = board(BoundaryMode::Solid, Vec::new());
+        let snakes = [snake(0, &[(2, 2), (1, 2)])];
+        assert_eq!(think(&mut GreedyBrain, &board, &snakes, &[GridPosition::new(2, 0)]), Direction::Up);
+        assert_eq!(think(&mut GreedyBrain, &board, &snakes, &[GridPosition::new(4, 3)]), Direction::Right);
+        assert_eq!(think(&mut GreedyBrain, &board, &snakes, &[GridPosition::new(0, 4), GridPosition::new(2, 4)]), Direction::Down);
+    }
+
+    #[test]
+    fn greedy_takes_short_way_round_when_wrapping() {
+        let snakes = [snake(0, &[(1, 2), (2, 2)])];
+        let food = [GridPosition::new(4, 2)];
+        assert_eq!(think(&mut GreedyBrain, &board(BoundaryMode::Wrap, Vec::new()), &snakes, &food), Direction::Left);
+        assert_ne!(think(&mut GreedyBrain, &board(BoundaryMode::Solid, Vec::new()), &snakes, &food), Direction::Left);
+    }
+
+    #[test]
+    fn greedy_avoids_obstacles_and_snakes() {
+        let board = board(BoundaryMode::Solid, vec![GridPosition::new(3, 2)]);
+        let snakes = [snake(0, &[(2, 2), (1, 2)]), snake(1, &[(2, 1), (2, 0)])];
+        assert_eq!(think(&mut GreedyBrain, &board, &snakes, &[GridPosition::new(4, 2)]), Direction::Down);
+    }
+
+    #[test]
+    fn bfs_finds_way_around_walls() {
+        // food behind a wall with a gap at the bottom
+        let walls = (0..4).map(|y| GridPosition::new(2, y)).collect();
+        let board = board(BoundaryMode::Solid, walls);
+        let snakes = [snake(0, &[(1, 1), (0, 1)])];
+        let food = [GridPosition::new(3, 1)];
+        assert_eq!(think(&mut GreedyBrain, &board, &snakes, &food), Direction::Up);
+        assert_eq!(think(&mut BfsBrain, &board, &snakes, &food), Direction::Down);
+    }
+
+    #[test]
+    fn bfs_falls_back_to_greedy_when_food_is_walled_off() {
+        let walls = vec![GridPosition::new(3, 2), GridPosition::new(4, 1), GridPosition::new(4, 3)];
+        let board = board(BoundaryMode::Solid, walls);
+        let snakes = [snake(0, &[(1, 2), (0, 2)])];
+        assert_eq!(think(&mut BfsBrain, &board, &snakes, &[GridPosition::new(4, 2)]), Direction::Right);
+    }
+
+    #[test]
+    fn hamiltonian_cycle_visits_every_cell_once() {
+        for grid_size in [(2, 2), (4, 3), (3, 4), (6, 6), (15, 10)] {
+            let cycle = HamiltonianCycle::new(grid_size).unwrap();
+            assert_eq!(cycle.len(), (grid_size.0 * grid_size.1) as usize);
+            let mut visited = Vec::new();
+            let mut grid_pos = GridPosition::new(0, 0);
+            for _ in 0..cycle.len() {
+                let next = cycle.next(&grid_pos);
+                assert!(direction_between(&grid_pos, &next).is_some(), "{:?} to {:?} on {:?}", grid_pos, next, grid_size);
+                visited.push(grid_pos);
+                grid_pos = next;
+            }
+            assert_eq!(grid_pos, GridPosition::new(0, 0));
+            visited.sort_by_key(|grid_pos| (grid_pos.y, grid_pos.x));
+            visited.dedup();
+            assert_eq!(visited.len(), cycle.len());
+        }
+        assert!(HamiltonianCycle::new((5, 5)).is_none());
+        assert!(HamiltonianCycle::new((1, 4)).is_none());
+    }
+
+    fn fill_board(grid_size: (i32, i32), boundary: BoundaryMode, seed: u64) -> SnakeSim {
+        let mut sim = SnakeSim::new(
+            board::Desc { grid_size, cell_size: 10, boundary, obstacles: Vec::new() },
+            &helpers::InitParams::single_player(GridPosition::new(0, 0), Direction::Right, 3),
+            &food::InitParams { start_position: GridPosition::new(grid_size.0 / 2, grid_size.1 / 2), spawn_points: Vec::new() },
+            seed
+        );
+        let mut brain = HamiltonianBrain::default();
+        let cells = (grid_size.0 * grid_size.1) as usize;
+        for _ in 0..cells * cells {
+            let food = match sim.food() {
+                Some(food) => [food],
+                None => break
+            };
+            let snakes = [SnakeView {
+                player: PlayerId(0),
+                alive: true,
+                body: sim.body().copied().collect(),
+                growing: sim.pending_growth()
+            }];
+            let direction = brain.next_direction(&BoardView {
+                board: sim.board(),
+                player: PlayerId(0),
+                direction: sim.direction(),
+                snakes: &snakes,
+                food: &food
+            });
+            assert_ne!(sim.step(&[direction]), StepOutcome::Died, "died at length {} on {:?}", sim.length(), grid_size);
+        }
+        sim
+    }
+
+    #[test]
+    fn hamiltonian_fills_the_board() {
+        for (grid_size, boundary) in [((6, 6), BoundaryMode::Solid), ((10, 7), BoundaryMode::Wrap), ((5, 8), BoundaryMode::Reflect)] {
+            for seed in 0..3 {
+                let sim = fill_board(grid_size, boundary, seed);
+                assert_eq!(sim.food(), None);
+                assert_eq!(sim.length(), (grid_size.0 * grid_size.1) as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn hamiltonian_shortcuts_only_while_short() {
+        let board = board::Desc { grid_size: (4, 4), cell_size: 10, boundary: BoundaryMode::Solid, obstacles: Vec::new() };
+        // the cycle carries on along the top row, the food is straight down
+        let food = [GridPosition::new(1, 3)];
+        let snakes = [snake(0, &[(1, 0), (0, 0)])];
+        assert_eq!(think(&mut HamiltonianBrain::default(), &board, &snakes, &food), Direction::Down);
+        let snakes = [snake(0, &[(1, 0), (0, 0), (0, 1), (0, 2), (0, 3), (1, 3), (2, 3), (3, 3)])];
+        assert_eq!(think(&mut HamiltonianBrain::default(), &board, &snakes, &[GridPosition::new(1, 2)]), Direction::Right);
+    }
+
+    #[test]
+    fn parse_bot_kind() {
+        assert_eq!("BFS".parse(), Ok(BotKind::Bfs));
+        assert_eq!("greedy".parse(), Ok(BotKind::Greedy));
+        assert_eq!("hamiltonian".parse(), Ok(BotKind::Hamiltonian));
+        assert!("clever".parse::<BotKind>().is_err());
+        assert_eq!("exec: python3 bot.py".parse(), Ok(BotKind::External {
+            command: String::from("python3 bot.py"),
+            timeout_ms: external::DEFAULT_MOVE_TIMEOUT_MS
+        }));
+        assert!("exec:".parse::<BotKind>().is_err());
+        assert_eq!("http://localhost:8000".parse(), Ok(BotKind::Battlesnake {
+            url: String::from("http://localhost:8000"),
+            timeout_ms: external::DEFAULT_MOVE_TIMEOUT_MS
+        }));
+    }
+
+    fn init_bot_game(brain: Box<dyn SnakeBrain>) -> App {
+        let mut app = App::default();
+        app.add_event::<DirectionEvent>();
+        app.add_event::<controller::SnakeDied>();
+        app.insert_resource(board(BoundaryMode::Solid, Vec::new()));
+        app.world.spawn().insert(GridPosition::new(4, 4)).insert(FoodComponent);
+        app.world
+            .spawn()
+            .insert(GridPosition::new(1, 1))
+            .insert(PlayerId(0))
+            .insert(MovementController{
+                direction: Direction::Right,
+                previous_position: GridPosition::new(0, 1),
+                turns: Default::default()
+            })
+            .insert(SnakeHead{})
+            .insert(BotControlled(brain));
+        app.add_system(controller::handle_input.label("input"));
+        app.add_system(drive_bots.label("think").after("input"));
+        app.add_system(controller::move_head.after("think"));
+        app
+    }
+
+    fn head_position(app: &mut App) -> GridPosition {
+        *app.world.query_filtered::<&GridPosition, With<SnakeHead>>().iter(&app.world).next().unwrap()
+    }
+
+    #[test]
+    fn bot_steers_instead_of_input() {
+        let mut app = init_bot_game(Box::new(BfsBrain));
+        for _ in 0..6 {
+            app.world.resource_mut::<Events<DirectionEvent>>().send(DirectionEvent {
+                player: PlayerId(0),
+                direction: Direction::Up
+            });
+            app.update();
+        }
+        assert_eq!(head_position(&mut app), GridPosition::new(4, 4));
+    }
+
+    // always asks to turn back the way it came
+    struct ReversingBrain;
+
+    impl SnakeBrain for ReversingBrain {
+        fn next_direction(&mut self, view: &BoardView) -> Direction {
+            view.direction.opposite()
+        }
+    }
+
+    #[test]
+    fn bot_cannot_reverse() {
+        let mut app = init_bot_game(Box::new(ReversingBrain));
+        app.update();
+        assert_eq!(head_position(&mut app), GridPosition::new(2, 1));
+    }
+
+    // gives up on the first tick
+    struct FailingBrain;
+
+    impl SnakeBrain for FailingBrain {
+        fn next_direction(&mut self, view: &BoardView) -> Direction {
+            view.direction.opposite()
+        }
+
+        fn failure(&self) -> Option<String> {
+            Some(String::from("gave up"))
+        }
+    }
+
+    #[test]
+    fn failed_bot_kills_only_its_snake() {
+        let mut app = init_bot_game(Box::new(FailingBrain));
+        app.world
+            .spawn()
+            .insert(GridPosition::new(1, 3))
+            .insert(PlayerId(1))
+            .insert(MovementController{
+                direction: Direction::Right,
+                previous_position: GridPosition::new(0, 3),
+                turns: Default::default()
+            })
+            .insert(SnakeHead{})
+            .insert(BotControlled(Box::new(BfsBrain)));
+        app.update();
+        let deaths = app.world
+            .resource::<Events<SnakeDied>>()
+            .iter_current_update_events()
+            .map(|event| (event.player, event.cause))
+            .collect::<Vec<_>>();
+        assert_eq!(deaths, vec![(PlayerId(0), DeathCause::BotError)]);
+    }
+}
@@ -1,69 +1,143 @@
 use bevy::prelude::*;
+use bevy::utils::HashMap;
 use iyes_loopless::prelude::*;
-use crate::core::{GameState, GridPosition};
-use crate::core::Direction;
-use crate::game_board::board;
-use crate::game_board::helpers::move_grid_position;
+use crate::core::{DeathCause, Direction, DirectionEvent, GameOverRule, GameState, GridPosition, PlayerId};
+use crate::game_board::board::{self, BoundaryMode, Obstacle};
 use crate::food;
+use crate::sim;
 use crate::snake::head::SnakeHead;
+use crate::snake::helpers::InitParams;
 use crate::snake::tail::SnakeTail;
 
+use super::brain::BotControlled;
 use super::head;
 use super::tail;
 
 #[derive(Component)]
 pub struct MovementController {
     pub direction: Direction,
-    pub previous_position: GridPosition
+    pub previous_position: GridPosition,
+    pub turns: sim::TurnQueue
 }
 
+// Left on the head of a snake that has died. The rest of it stays where it fell
+// while any other snakes play on.
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct Dead(pub DeathCause);
+
+pub struct SnakeDied {
+    pub player: PlayerId,
+    pub cause: DeathCause,
+    // the snake that was run into, if any
+    pub killer: Option<PlayerId>
+}
+
+pub type LiveHead = (With<SnakeHead>, Without<Dead>);
+
 pub fn handle_input(
-    game_board: Res<board::Desc>,
-    mut direction_events: EventReader<Direction>,
-    mut query: Query<(&GridPosition,&mut MovementController), With<head::SnakeHead>>
+    mut direction_events: EventReader<DirectionEvent>,
+    mut query: Query<(&PlayerId, &mut MovementController), (LiveHead, Without<BotControlled>)>
 ){
-    if !direction_events.is_empty() {
-        if let Ok((grid_pos, mut controller)) = query.get_single_mut() {
-            let new_direction = direction_events.iter().last().unwrap().clone();
-            let predicted_position = move_grid_position(
-                *grid_pos,
-                new_direction.clone(),
-                game_board.grid_size
-            );
-            // prevent snake reversing on itself immediately
-            if predicted_position != controller.previous_position {
-                controller.direction = new_direction;
-            }
+    for event in direction_events.iter() {
+        if let Some((_, mut controller)) = query.iter_mut().find(|(player, _)| **player == event.player) {
+            controller.turns.push(event.direction.clone());
         }
     }
 }
 
+pub fn turn(
+    grid_pos: &GridPosition,
+    controller: &mut MovementController,
+    new_direction: Direction,
+    game_board: &board::Desc
+) {
+    // prevent snake reversing on itself immediately
+    if !sim::is_reversal(
+        *grid_pos,
+        controller.previous_position,
+        new_direction.clone(),
+        game_board
+    ) {
+        controller.direction = new_direction;
+    }
+}
+
 pub fn move_head(
     game_board: Res<board::Desc>,
-    mut query: Query<(&mut GridPosition, &mut MovementController, With<head::SnakeHead>)>
+    mut death_events: EventWriter<SnakeDied>,
+    mut query: Query<(&PlayerId, &mut GridPosition, &mut MovementController), LiveHead>,
+    mut tail_query: Query<(&PlayerId, &mut GridPosition, &mut SnakeTail), Without<head::SnakeHead>>
 ){
-    let (mut grid_pos, mut movement, _) = query.single_mut();
-    movement.previous_position = *grid_pos;
+    for (player, mut grid_pos, mut movement) in query.iter_mut() {
+        let current = movement.direction.clone();
+        let previous_position = movement.previous_position;
+        if let Some(direction) = movement.turns.next_turn(*grid_pos, previous_position, &current, &game_board) {
+            movement.direction = direction;
+        }
 
-    let updated_position = move_grid_position(
-        *grid_pos,
-        movement.direction.clone(),
-        game_board.grid_size
-    );
-    *grid_pos = updated_position;
+        match game_board.step(*grid_pos, movement.direction.clone()) {
+            Some(updated_position) => {
+                movement.previous_position = *grid_pos;
+                *grid_pos = updated_position;
+            }
+            None if game_board.boundary == BoundaryMode::Reflect => {
+                let mut segments = tail_query
+                    .iter_mut()
+                    .filter(|(tail_player, _, _)| *tail_player == player)
+                    .map(|(_, tail_grid_pos, tail)| (tail_grid_pos, tail))
+                    .collect::<Vec<_>>();
+                segments.sort_by_key(|(_, tail)| tail.index);
+                // segments still growing in sit off the board and are left to follow on
+                let on_board = segments
+                    .iter()
+                    .take_while(|(tail_grid_pos, _)| game_board.is_inside(tail_grid_pos))
+                    .count();
+                let body = std::iter::once(*grid_pos)
+                    .chain(segments[..on_board].iter().map(|(tail_grid_pos, _)| **tail_grid_pos))
+                    .collect::<Vec<GridPosition>>();
+                let (reflected, direction) = sim::reflect(&body, movement.direction.clone(), &game_board);
+                // boxed in at both ends, there's nowhere to bounce to
+                let Some(new_head) = game_board.step(reflected[0], direction.clone()) else {
+                    death_events.send(SnakeDied { player: *player, cause: DeathCause::HitWall, killer: None });
+                    continue;
+                };
+
+                // place each segment one step behind its new target so the tail
+                // follows on this tick just as it would after a normal move
+                for (index, (tail_grid_pos, tail)) in segments.iter_mut().enumerate() {
+                    if index < on_board {
+                        tail_grid_pos.set(&reflected[index + 1]);
+                        tail.next_position.set(&reflected[index]);
+                    } else if index == on_board {
+                        tail.next_position.set(&reflected[on_board]);
+                    }
+                }
+                movement.direction = direction;
+                movement.previous_position = reflected[0];
+                *grid_pos = new_head;
+            }
+            None => {
+                death_events.send(SnakeDied { player: *player, cause: DeathCause::HitWall, killer: None });
+            }
+        }
+    }
 }
 
 
+// Runs after the crashes, as Dead isn't added until the end of the tick. Heads
+// that meet on food settle it between them, the one left alive eats it.
 pub fn check_collide_with_food(
-    mut head_query: Query<(&Transform, With<head::SnakeHead>)>,
-    other_query: Query<((&Transform, Entity), With<food::FoodComponent>)>,
+    mut death_events: EventReader<SnakeDied>,
+    head_query: Query<(&PlayerId, &GridPosition), LiveHead>,
+    food_query: Query<(Entity, &GridPosition), With<food::FoodComponent>>,
     mut consume_events: EventWriter<food::ConsumeEvent>,
 ) {
-    if let Ok((head_transform, _)) = head_query.get_single_mut() {
-        for ((transform, other_entity), _ ) in other_query.iter() {
-            if head_transform.translation == transform.translation {
-                consume_events.send(food::ConsumeEvent{target: other_entity});
-                return;
+    let died = death_events.iter().map(|event| event.player).collect::<Vec<_>>();
+    for (player, head_grid_pos) in head_query.iter().filter(|(player, _)| !died.contains(player)) {
+        for (food_entity, food_grid_pos) in food_query.iter() {
+            if head_grid_pos == food_grid_pos {
+                consume_events.send(food::ConsumeEvent{target: food_entity, eater: *player});
+                break;
             }
         }
     }
@@ -72,14 +146,15 @@ pub fn check_collide_with_food(
 pub fn consume_food(
     game_board: Res<board::Desc>,
     mut commands: Commands,
-    tail_query: Query<(Entity, &GridPosition, &SnakeTail)>,
+    head_query: Query<&PlayerId, LiveHead>,
+    tail_query: Query<(Entity, &GridPosition, &SnakeTail, &PlayerId)>,
     mut consume_events: EventReader<food::ConsumeEvent>,
 ) {
-    if consume_events.iter().next().is_some() {
-        println!("snake consume event");
-
+    let live = head_query.iter().collect::<Vec<_>>();
+    for consume_event in consume_events.iter().filter(|event| live.contains(&&event.eater)) {
         if let Some(end_of_tail) = tail_query
             .iter()
+            .filter(|(_, _, _, player)| **player == consume_event.eater)
             .max_by(| a, b|{ a.2.index.cmp(&b.2.index) }) {
                 let next_index = end_of_tail.2.index + 1;
                 let follow_target_entity = end_of_tail.0;
@@ -87,6 +162,7 @@ pub fn consume_food(
 
                 tail::spawn_node(
                     &mut commands,
+                    consume_event.eater,
                     next_index,
                     game_board.cell_size as f32,
                     (follow_target_entity, follow_target_pos)
@@ -95,107 +171,395 @@ pub fn consume_food(
     }
 }
 
-pub fn check_for_bite_self(
-    mut commands: Commands,
-    head_query: Query<&GridPosition, With<SnakeHead>>,
-    tail_query: Query<&GridPosition, With<SnakeTail>>
+pub fn check_collide_with_obstacle(
+    mut death_events: EventWriter<SnakeDied>,
+    head_query: Query<(&PlayerId, &GridPosition), LiveHead>,
+    obstacle_query: Query<&GridPosition, With<Obstacle>>
 ) {
-    if let Ok(head_grid_pos) = head_query.get_single() {
-        if tail_query
-            .iter()
-            .any(|tail_grid_pos| { head_grid_pos == tail_grid_pos }) {
-            println!("dead at {:?}", head_grid_pos);
-            commands.insert_resource(NextState(GameState::DEAD));
+    for (player, head_grid_pos) in head_query.iter() {
+        if obstacle_query.iter().any(|obstacle_grid_pos| obstacle_grid_pos == head_grid_pos) {
+            death_events.send(SnakeDied { player: *player, cause: DeathCause::HitObstacle, killer: None });
+        }
+    }
+}
+
+// Runs after every head has moved, so snakes running into each other are settled
+// together by sim::resolve_collisions, along with snakes biting themselves.
+pub fn resolve_snake_collisions(
+    mut death_events: EventWriter<SnakeDied>,
+    head_query: Query<(&PlayerId, &GridPosition, &MovementController, Option<&Dead>), With<SnakeHead>>,
+    tail_query: Query<(&PlayerId, &GridPosition), With<SnakeTail>>
+) {
+    let mut tails: HashMap<PlayerId, Vec<GridPosition>> = HashMap::new();
+    for (player, tail_grid_pos) in tail_query.iter() {
+        tails.entry(*player).or_default().push(*tail_grid_pos);
+    }
+    let snakes = head_query
+        .iter()
+        .map(|(player, grid_pos, movement, dead)| sim::SnakeCells {
+            player: *player,
+            alive: dead.is_none(),
+            head: *grid_pos,
+            previous_head: movement.previous_position,
+            tail: tails.get(player).map(Vec::as_slice).unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    for collision in sim::resolve_collisions(&snakes) {
+        death_events.send(SnakeDied { player: collision.player, cause: collision.cause, killer: collision.killer });
+    }
+}
+
+// Marks the snakes that died this tick and ends the game once the game over rule
+// is met, recording the first cause of death.
+pub fn check_game_over(
+    snake_params: Res<InitParams>,
+    mut death_events: EventReader<SnakeDied>,
+    head_query: Query<(Entity, &PlayerId), LiveHead>,
+    mut commands: Commands
+) {
+    let mut died: Vec<(PlayerId, DeathCause)> = Vec::new();
+    for event in death_events.iter() {
+        if !died.iter().any(|(player, _)| *player == event.player) {
+            died.push((event.player, event.cause));
+        }
+    }
+    if died.is_empty() {
+        return;
+    }
+    let mut survivors = 0;
+    for (entity, player) in head_query.iter() {
+        match died.iter().find(|(dead_player, _)| dead_player == player) {
+            Some((_, cause)) => {
+                commands.entity(entity).insert(Dead(*cause));
+            }
+            None => survivors += 1
         }
     }
+    let game_over = match snake_params.game_over {
+        GameOverRule::AnyPlayerDies => true,
+        GameOverRule::AllPlayersDie => survivors == 0
+    };
+    if game_over {
+        commands.insert_resource(died[0].1);
+        commands.insert_resource(NextState(GameState::DEAD));
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use bevy::ecs::event::Events;
+    use crate::snake::helpers::PlayerStart;
     use super::*;
 
-    #[test]
-    fn handle_input_basic() {
+    fn init_params(game_over: GameOverRule) -> InitParams {
+        InitParams {
+            game_over,
+            ..InitParams::single_player(GridPosition::new(0, 0), Direction::Right, 0)
+        }
+    }
+
+    fn init_steering_snake(grid_pos: GridPosition, previous_position: GridPosition) -> App {
         let mut app = App::default();
-        app.add_event::<Direction>();
+        app.add_event::<DirectionEvent>();
+        app.add_event::<SnakeDied>();
         app.world.insert_resource(board::Desc {
             grid_size: (5, 5),
-            cell_size: 10
+            cell_size: 10,
+            boundary: board::BoundaryMode::Wrap,
+            obstacles: Vec::new()
         });
         app.world
             .spawn()
-            .insert(GridPosition::new(1, 0))
+            .insert(grid_pos)
             .insert(MovementController{
                 direction: Direction::Right,
-                previous_position: GridPosition::new(-1, 0),
+                previous_position,
+                turns: Default::default()
             })
+            .insert(PlayerId(0))
             .insert(SnakeHead{});
-        app.add_system(handle_input);
+        app.add_system(handle_input.label("input"));
+        app.add_system(move_head.after("input"));
+        app
+    }
 
-        app.world.resource_mut::<Events<Direction>>().send(Direction::Down);
+    fn send_and_move(app: &mut App, directions: &[Direction]) -> (GridPosition, Direction) {
+        for direction in directions {
+            app.world.resource_mut::<Events<DirectionEvent>>().send(DirectionEvent {
+                player: PlayerId(0),
+                direction: direction.clone()
+            });
+        }
         app.update();
-        let movement_controller = app.world
-            .query::<&MovementController>()
+        app.world
+            .query::<(&GridPosition, &MovementController)>()
             .iter(&app.world)
+            .map(|(grid_pos, controller)| (*grid_pos, controller.direction.clone()))
             .next()
-            .unwrap();
-        assert_eq!(movement_controller.direction, Direction::Down);
+            .unwrap()
+    }
 
-        app.world.resource_mut::<Events<Direction>>().send(Direction::Right);
-        app.update();
-        let movement_controller = app.world
-            .query::<&MovementController>()
-            .iter(&app.world)
-            .next()
-            .unwrap();
-        assert_eq!(movement_controller.direction, Direction::Right);
+    #[test]
+    fn handle_input_basic() {
+        let mut app = init_steering_snake(GridPosition::new(1, 0), GridPosition::new(0, 0));
+        assert_eq!(send_and_move(&mut app, &[Direction::Down]), (GridPosition::new(1, 1), Direction::Down));
+        assert_eq!(send_and_move(&mut app, &[Direction::Right]), (GridPosition::new(2, 1), Direction::Right));
+        assert_eq!(send_and_move(&mut app, &[]), (GridPosition::new(3, 1), Direction::Right));
     }
 
     #[test]
     fn handle_input_prevent_reverse() {
+        let mut app = init_steering_snake(GridPosition::new(1, 1), GridPosition::new(0, 1));
+        assert_eq!(send_and_move(&mut app, &[Direction::Left]), (GridPosition::new(2, 1), Direction::Right));
+        assert_eq!(send_and_move(&mut app, &[Direction::Down]), (GridPosition::new(2, 2), Direction::Down));
+        assert_eq!(send_and_move(&mut app, &[Direction::Up]), (GridPosition::new(2, 3), Direction::Down));
+    }
+
+    #[test]
+    fn quick_turns_apply_one_per_tick() {
+        let mut app = init_steering_snake(GridPosition::new(1, 1), GridPosition::new(0, 1));
+        assert_eq!(send_and_move(&mut app, &[Direction::Up, Direction::Left]), (GridPosition::new(1, 0), Direction::Up));
+        assert_eq!(send_and_move(&mut app, &[]), (GridPosition::new(0, 0), Direction::Left));
+    }
+
+    fn init_moving_snake(boundary: BoundaryMode, tail: &[(GridPosition, GridPosition)]) -> App {
+        let board = board::Desc { grid_size: (5, 5), cell_size: 10, boundary, obstacles: Vec::new() };
+        init_snake_moving_right(board, GridPosition::new(4, 2), tail)
+    }
+
+    fn init_snake_moving_right(board: board::Desc, head: GridPosition, tail: &[(GridPosition, GridPosition)]) -> App {
         let mut app = App::default();
-        app.add_event::<Direction>();
-        app.world.insert_resource(board::Desc {
-            grid_size: (5, 5),
-            cell_size: 10
-        });
-        app.world
+        app.add_event::<SnakeDied>();
+        app.insert_resource(init_params(GameOverRule::AnyPlayerDies));
+        app.world.insert_resource(board);
+        let mut follow_target = app.world
             .spawn()
-            .insert(GridPosition::new(1, 1))
+            .insert(head)
+            .insert(PlayerId(0))
             .insert(MovementController{
                 direction: Direction::Right,
-                previous_position: GridPosition::new(0, 1),
+                previous_position: GridPosition::new(head.x - 1, head.y),
+                turns: Default::default()
             })
-            .insert(SnakeHead{});
-        app.add_system(handle_input);
+            .insert(SnakeHead{})
+            .id();
+        for (index, (grid_pos, next_position)) in tail.iter().enumerate() {
+            follow_target = app.world
+                .spawn()
+                .insert(*grid_pos)
+                .insert(PlayerId(0))
+                .insert(Transform::default())
+                .insert(SnakeTail{ index: index + 1, follow_target, next_position: *next_position })
+                .id();
+        }
+        app.add_system(move_head.label("move"));
+        app.add_system(check_game_over.after("move"));
+        app.add_system(tail::tick_position.after("move"));
+        app
+    }
 
-        app.world.resource_mut::<Events<Direction>>().send(Direction::Left);
-        app.update();
-        let movement_controller = app.world
-            .query::<&MovementController>()
+    fn snake_positions(app: &mut App) -> Vec<GridPosition> {
+        let head = *app.world
+            .query_filtered::<&GridPosition, With<SnakeHead>>()
             .iter(&app.world)
             .next()
             .unwrap();
-        assert_eq!(movement_controller.direction, Direction::Right);
+        let mut tail = app.world
+            .query::<(&GridPosition, &SnakeTail)>()
+            .iter(&app.world)
+            .map(|(grid_pos, tail)| (tail.index, *grid_pos))
+            .collect::<Vec<_>>();
+        tail.sort_by_key(|(index, _)| *index);
+        std::iter::once(head).chain(tail.into_iter().map(|(_, grid_pos)| grid_pos)).collect()
+    }
 
-        app.world.resource_mut::<Events<Direction>>().send(Direction::Down);
+    #[test]
+    fn move_head_into_solid_wall_dies() {
+        let mut app = init_moving_snake(BoundaryMode::Solid, &[]);
         app.update();
-        let mut movement_controller = app.world
-            .query::<&mut MovementController>()
-            .iter_mut(&mut app.world)
-            .next()
-            .unwrap();
-        assert_eq!(movement_controller.direction, Direction::Down);
+        assert_eq!(snake_positions(&mut app), vec![GridPosition::new(4, 2)]);
+        assert_eq!(*app.world.resource::<DeathCause>(), DeathCause::HitWall);
+        assert_eq!(app.world.resource::<NextState<GameState>>().0, GameState::DEAD);
+    }
 
-        movement_controller.previous_position.set(&GridPosition::new(1, 0));
-        app.world.resource_mut::<Events<Direction>>().send(Direction::Up);
+    #[test]
+    fn move_head_into_reflect_wall_turns_around() {
+        let mut app = init_moving_snake(BoundaryMode::Reflect, &[
+            (GridPosition::new(3, 2), GridPosition::new(4, 2)),
+            (GridPosition::new(2, 2), GridPosition::new(3, 2)),
+            (GridPosition::new(-1, -1), GridPosition::new(2, 2)),
+        ]);
         app.update();
+        assert_eq!(snake_positions(&mut app), vec![
+            GridPosition::new(1, 2),
+            GridPosition::new(2, 2),
+            GridPosition::new(3, 2),
+            GridPosition::new(4, 2),
+        ]);
         let movement_controller = app.world
             .query::<&MovementController>()
             .iter(&app.world)
             .next()
             .unwrap();
-        assert_eq!(movement_controller.direction, Direction::Down);
+        assert_eq!(movement_controller.direction, Direction::Left);
+        assert!(app.world.get_resource::<DeathCause>().is_none());
+    }
+
+    #[test]
+    fn move_head_into_reflect_wall_with_nowhere_to_turn_dies() {
+        let board = board::Desc { grid_size: (3, 1), cell_size: 10, boundary: BoundaryMode::Reflect, obstacles: Vec::new() };
+        let mut app = init_snake_moving_right(board, GridPosition::new(2, 0), &[
+            (GridPosition::new(1, 0), GridPosition::new(2, 0)),
+            (GridPosition::new(0, 0), GridPosition::new(1, 0)),
+        ]);
+        app.update();
+        assert_eq!(snake_positions(&mut app), vec![
+            GridPosition::new(2, 0),
+            GridPosition::new(1, 0),
+            GridPosition::new(0, 0),
+        ]);
+        assert_eq!(*app.world.resource::<DeathCause>(), DeathCause::HitWall);
+    }
+
+    fn init_collisions(game_over: GameOverRule) -> App {
+        let mut app = App::default();
+        app.add_event::<SnakeDied>();
+        app.insert_resource(init_params(game_over));
+        app.add_system(check_collide_with_obstacle.label("collide"));
+        app.add_system(resolve_snake_collisions.label("collide"));
+        app.add_system(check_game_over.after("collide"));
+        app
+    }
+
+    fn spawn_head(app: &mut App, player: usize, grid_pos: GridPosition, previous_position: GridPosition) -> Entity {
+        app.world
+            .spawn()
+            .insert(grid_pos)
+            .insert(PlayerId(player))
+            .insert(MovementController{ direction: Direction::Right, previous_position, turns: Default::default() })
+            .insert(SnakeHead{})
+            .id()
+    }
+
+    fn death_events(app: &App) -> Vec<(usize, DeathCause, Option<usize>)> {
+        app.world
+            .resource::<Events<SnakeDied>>()
+            .iter_current_update_events()
+            .map(|event| (event.player.0, event.cause, event.killer.map(|killer| killer.0)))
+            .collect()
+    }
+
+    #[test]
+    fn collide_with_obstacle_dies() {
+        let mut app = init_collisions(GameOverRule::AnyPlayerDies);
+        spawn_head(&mut app, 0, GridPosition::new(1, 1), GridPosition::new(0, 1));
+        app.world.spawn().insert(GridPosition::new(2, 1)).insert(Obstacle);
+        app.update();
+        assert!(app.world.get_resource::<DeathCause>().is_none());
+
+        app.world.spawn().insert(GridPosition::new(1, 1)).insert(Obstacle);
+        app.update();
+        assert_eq!(*app.world.resource::<DeathCause>(), DeathCause::HitObstacle);
+        assert_eq!(app.world.resource::<NextState<GameState>>().0, GameState::DEAD);
+    }
+
+    #[test]
+    fn heads_meeting_kill_both_snakes() {
+        let mut app = init_collisions(GameOverRule::AllPlayersDie);
+        let first = spawn_head(&mut app, 0, GridPosition::new(2, 2), GridPosition::new(1, 2));
+        let second = spawn_head(&mut app, 1, GridPosition::new(2, 2), GridPosition::new(3, 2));
+        app.update();
+        assert_eq!(death_events(&app), vec![(0, DeathCause::HeadOn, Some(1)), (1, DeathCause::HeadOn, Some(0))]);
+        assert_eq!(app.world.get::<Dead>(first), Some(&Dead(DeathCause::HeadOn)));
+        assert_eq!(app.world.get::<Dead>(second), Some(&Dead(DeathCause::HeadOn)));
+        assert_eq!(app.world.resource::<NextState<GameState>>().0, GameState::DEAD);
+    }
+
+    #[test]
+    fn game_plays_on_until_all_players_die() {
+        let mut app = init_collisions(GameOverRule::AllPlayersDie);
+        let first = spawn_head(&mut app, 0, GridPosition::new(1, 1), GridPosition::new(0, 1));
+        spawn_head(&mut app, 1, GridPosition::new(3, 3), GridPosition::new(2, 3));
+        app.world.spawn().insert(GridPosition::new(1, 1)).insert(Obstacle);
+        app.update();
+        assert_eq!(app.world.get::<Dead>(first), Some(&Dead(DeathCause::HitObstacle)));
+        assert!(app.world.get_resource::<NextState<GameState>>().is_none());
+
+        // the dead snake is now in the way of the other
+        let mut tail = app.world.spawn();
+        tail.insert(GridPosition::new(3, 3)).insert(PlayerId(0));
+        tail.insert(SnakeTail{ index: 0, follow_target: first, next_position: GridPosition::new(1, 1) });
+        app.update();
+        assert_eq!(death_events(&app), vec![(1, DeathCause::HitSnake, Some(0))]);
+        assert_eq!(*app.world.resource::<DeathCause>(), DeathCause::HitSnake);
+        assert_eq!(app.world.resource::<NextState<GameState>>().0, GameState::DEAD);
+    }
+
+    #[test]
+    fn first_death_ends_game_by_default() {
+        let mut app = init_collisions(GameOverRule::AnyPlayerDies);
+        spawn_head(&mut app, 0, GridPosition::new(1, 1), GridPosition::new(0, 1));
+        spawn_head(&mut app, 1, GridPosition::new(3, 3), GridPosition::new(2, 3));
+        app.world.spawn().insert(GridPosition::new(3, 3)).insert(Obstacle);
+        app.update();
+        assert_eq!(app.world.resource::<NextState<GameState>>().0, GameState::DEAD);
+    }
+
+    #[test]
+    fn input_steers_its_own_player() {
+        let mut app = init_steering_snake(GridPosition::new(1, 1), GridPosition::new(0, 1));
+        app.world
+            .spawn()
+            .insert(GridPosition::new(1, 3))
+            .insert(MovementController{
+                direction: Direction::Right,
+                previous_position: GridPosition::new(0, 3),
+                turns: Default::default()
+            })
+            .insert(PlayerId(1))
+            .insert(SnakeHead{});
+        app.world.resource_mut::<Events<DirectionEvent>>().send(DirectionEvent {
+            player: PlayerId(1),
+            direction: Direction::Down
+        });
+        app.update();
+        let mut heads = app.world
+            .query::<(&PlayerId, &GridPosition)>()
+            .iter(&app.world)
+            .map(|(player, grid_pos)| (player.0, *grid_pos))
+            .collect::<Vec<_>>();
+        heads.sort_by_key(|(player, _)| *player);
+        assert_eq!(heads, vec![(0, GridPosition::new(2, 1)), (1, GridPosition::new(1, 4))]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn snakes_meeting_on_food_settle_it_head_on() {
+        let mut level = crate::batch::open_level((7, 5));
+        level.snake = InitParams {
+            other_players: vec![PlayerStart { position: GridPosition::new(5, 2), direction: Direction::Left }],
+            ..InitParams::single_player(GridPosition::new(1, 2), Direction::Right, 2)
+        };
+        level.food.start_position = GridPosition::new(3, 2);
+        let mut app = App::default();
+        app.add_plugins(MinimalPlugins);
+        crate::batch::add_headless_game(&mut app, &level, 1);
+        for _ in 0..10 {
+            app.update();
+        }
+
+        // the same length, so both die and neither gets the food
+        let heads = app.world
+            .query_filtered::<(&GridPosition, &Dead), With<SnakeHead>>()
+            .iter(&app.world)
+            .map(|(grid_pos, dead)| (*grid_pos, dead.0))
+            .collect::<Vec<_>>();
+        assert_eq!(heads, vec![(GridPosition::new(3, 2), DeathCause::HeadOn); 2]);
+        let food = app.world
+            .query_filtered::<&GridPosition, With<food::FoodComponent>>()
+            .iter(&app.world)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(food, vec![GridPosition::new(3, 2)]);
+        assert_eq!(app.world.query::<&SnakeTail>().iter(&app.world).count(), 4);
+    }
+}
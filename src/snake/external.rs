@@ -0,0 +1,246 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::core::{Direction, GridPosition, PlayerId};
+use crate::game_board::board::BoundaryMode;
+
+use super::brain::{BoardView, SnakeBrain, SnakeView};
+
+pub const DEFAULT_MOVE_TIMEOUT_MS: u64 = 100;
+
+// The protocol is one line of JSON each way per tick. The game sends
+//
+//   {"tick":1,"you":0,"direction":"Right",
+//    "board":{"width":15,"height":15,"boundary":"Wrap","obstacles":[{"x":3,"y":4}]},
+//    "snakes":[{"player":0,"alive":true,"body":[{"x":5,"y":7},{"x":4,"y":7}],"growing":2}],
+//    "food":[{"x":7,"y":7}]}
+//
+// with bodies listed head first, and the bot answers with {"direction":"Up"}.
+// Directions are "Up", "Down", "Left" or "Right", and y counts down from the top.
+#[derive(Serialize)]
+struct TickMessage<'a> {
+    tick: u64,
+    you: PlayerId,
+    direction: &'a Direction,
+    board: BoardMessage<'a>,
+    snakes: &'a [SnakeView],
+    food: &'a [GridPosition]
+}
+
+#[derive(Serialize)]
+struct BoardMessage<'a> {
+    width: i32,
+    height: i32,
+    boundary: BoundaryMode,
+    obstacles: &'a [GridPosition]
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    direction: Direction
+}
+
+// A bot in another process speaking the protocol above on its stdin and stdout.
+// A bot that doesn't answer within the timeout carries straight on, and its
+// answer is thrown away when it does turn up. A bot that exits or says
+// something that isn't a reply fails, and its snake dies.
+//
+// Waiting for the answer holds up the tick, and everything else in the frame
+// with it, for up to the timeout per bot. In a windowed game a slow bot makes
+// the frame rate stutter, so keep the timeout well under the tick time there.
+pub struct ExternalBrain {
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+    replies: Option<Mutex<Receiver<String>>>,
+    timeout: Duration,
+    tick: u64,
+    failure: Option<String>
+}
+
+impl ExternalBrain {
+    pub fn spawn(mut command: Command, timeout: Duration) -> ExternalBrain {
+        let mut brain = ExternalBrain {
+            child: None,
+            stdin: None,
+            replies: None,
+            timeout,
+            tick: 0,
+            failure: None
+        };
+        let mut child = match command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                brain.failure = Some(format!("failed to start bot {:?}: {}", command, e));
+                return brain;
+            }
+        };
+        let (sender, receiver) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            // the channel disconnects once the bot closes its stdout
+            thread::spawn(move || {
+                for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        brain.stdin = child.stdin.take();
+        brain.replies = Some(Mutex::new(receiver));
+        brain.child = Some(child);
+        brain
+    }
+
+    // `exec:` bots are a program and its arguments split on whitespace.
+    pub fn from_command_line(command_line: &str, timeout: Duration) -> ExternalBrain {
+        let mut words = command_line.split_whitespace();
+        let mut command = Command::new(words.next().unwrap_or_default());
+        command.args(words);
+        ExternalBrain::spawn(command, timeout)
+    }
+
+    fn ask(&mut self, view: &BoardView) -> Result<Option<Direction>, String> {
+        let replies = self.replies.as_ref().ok_or("bot isn't running")?.lock().map_err(|e| e.to_string())?;
+        // anything still waiting answered a tick that has already been played
+        while replies.try_recv().is_ok() {}
+
+        self.tick += 1;
+        let message = TickMessage {
+            tick: self.tick,
+            you: view.player,
+            direction: &view.direction,
+            board: BoardMessage {
+                width: view.board.grid_size.0,
+                height: view.board.grid_size.1,
+                boundary: view.board.boundary,
+                obstacles: &view.board.obstacles
+            },
+            snakes: view.snakes,
+            food: view.food
+        };
+        let json = serde_json::to_string(&message).map_err(|e| e.to_string())?;
+        let stdin = self.stdin.as_mut().ok_or("bot isn't running")?;
+        writeln!(stdin, "{}", json)
+            .and_then(|_| stdin.flush())
+            .map_err(|e| format!("bot stopped reading: {}", e))?;
+
+        match replies.recv_timeout(self.timeout) {
+            Ok(line) => serde_json::from_str::<Reply>(&line)
+                .map(|reply| Some(reply.direction))
+                .map_err(|e| format!("invalid reply '{}': {}", line, e)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(String::from("bot exited"))
+        }
+    }
+}
+
+impl SnakeBrain for ExternalBrain {
+    fn next_direction(&mut self, view: &BoardView) -> Direction {
+        if self.failure.is_some() {
+            return view.direction.clone();
+        }
+        match self.ask(view) {
+            Ok(Some(direction)) => direction,
+            Ok(None) => view.direction.clone(),
+            Err(e) => {
+                self.failure = Some(e);
+                view.direction.clone()
+            }
+        }
+    }
+
+    fn failure(&self) -> Option<String> {
+        self.failure.clone()
+    }
+}
+
+impl Drop for ExternalBrain {
+    fn drop(&mut self) {
+        self.stdin.take();
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Instant;
+    use crate::game_board::board;
+    use super::*;
+
+    fn script(script: &str, timeout_ms: u64) -> ExternalBrain {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        ExternalBrain::spawn(command, Duration::from_millis(timeout_ms))
+    }
+
+    fn think(brain: &mut ExternalBrain) -> Direction {
+        let board = board::Desc { grid_size: (5, 5), cell_size: 10, boundary: BoundaryMode::Solid, obstacles: Vec::new() };
+        let snakes = [SnakeView {
+            player: PlayerId(0),
+            alive: true,
+            body: vec![GridPosition::new(2, 2), GridPosition::new(1, 2)],
+            growing: 1
+        }];
+        brain.next_direction(&BoardView {
+            board: &board,
+            player: PlayerId(0),
+            direction: Direction::Right,
+            snakes: &snakes,
+            food: &[GridPosition::new(4, 4)]
+        })
+    }
+
+    #[test]
+    fn replies_steer_the_snake() {
+        let mut brain = script(r#"while read state; do echo '{"direction":"Down"}'; done"#, 2000);
+        assert_eq!(think(&mut brain), Direction::Down);
+        assert_eq!(think(&mut brain), Direction::Down);
+        assert_eq!(brain.failure(), None);
+    }
+
+    #[test]
+    fn bot_sees_the_board() {
+        // answers Up only if it was told where the food is
+        let mut brain = script(r#"read state; case "$state" in
+            *'"tick":1,"you":0,"direction":"Right"'*'"food":[{"x":4,"y":4}]'*) echo '{"direction":"Up"}';;
+            *) echo '{"direction":"Down"}';;
+        esac; sleep 5"#, 2000);
+        assert_eq!(think(&mut brain), Direction::Up);
+    }
+
+    #[test]
+    fn slow_bot_carries_straight_on() {
+        let mut brain = script(r#"while read state; do sleep 0.3; echo '{"direction":"Up"}'; done"#, 50);
+        let started = Instant::now();
+        assert_eq!(think(&mut brain), Direction::Right);
+        assert!(started.elapsed() < Duration::from_millis(250));
+        assert_eq!(brain.failure(), None);
+
+        // the late answer to the first tick isn't taken as the answer to a later one
+        thread::sleep(Duration::from_millis(400));
+        assert_eq!(think(&mut brain), Direction::Right);
+    }
+
+    #[test]
+    fn crashes_and_bad_replies_fail_the_bot() {
+        let mut brain = script("exit 1", 2000);
+        assert_eq!(think(&mut brain), Direction::Right);
+        assert!(brain.failure().is_some());
+
+        let mut brain = script(r#"read state; echo 'left please'; sleep 5"#, 2000);
+        assert_eq!(think(&mut brain), Direction::Right);
+        assert!(brain.failure().unwrap().starts_with("invalid reply 'left please'"));
+
+        let mut brain = ExternalBrain::from_command_line("/does/not/exist", Duration::from_millis(50));
+        assert!(brain.failure().unwrap().starts_with("failed to start bot"));
+        assert_eq!(think(&mut brain), Direction::Right);
+    }
+}
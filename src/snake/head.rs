@@ -1,8 +1,7 @@
 use bevy::prelude::*;
 use super::helpers;
 use super::controller::MovementController;
-use crate::core::GridPosition;
-use crate::core::Direction;
+use crate::core::{Direction, GridPosition, PlayerId};
 use crate::game_board::board;
 
 #[derive(Component)]
@@ -12,25 +11,29 @@ pub fn tick_position(
     game_board: Res<board::Desc>,
     mut query: Query<(&GridPosition, &mut Transform), With<SnakeHead>>
 ) {
-    if let Ok((grid_pos, mut transform)) = query.get_single_mut() {
+    for (grid_pos, mut transform) in query.iter_mut() {
         transform.translation = game_board.grid_pos_to_world_pos(grid_pos)
     }
 }
 
 pub fn spawn(
     commands: &mut Commands,
+    player: PlayerId,
     start_position: GridPosition,
+    start_direction: Direction,
     cell_size: f32
 ) -> Entity {
     commands
         .spawn()
         .insert(SnakeHead{})
         .insert(start_position)
+        .insert(player)
         .insert(MovementController{
-            direction: Direction::Right,
-            previous_position: start_position
+            direction: start_direction,
+            previous_position: start_position,
+            turns: Default::default()
         })
-        .insert_bundle(helpers::get_snake_sprite_bundle(cell_size))
+        .insert_bundle(helpers::get_snake_sprite_bundle(cell_size, player))
         .id()
 }
 
@@ -51,7 +54,9 @@ mod tests {
         let mut commands = state.get_mut(&mut (app.world));
         let entity_id = spawn(
             &mut commands,
+            PlayerId(0),
             start_position,
+            Direction::Up,
             cell_size
         );
         state.apply(&mut app.world);
@@ -66,7 +71,7 @@ mod tests {
         let (_, grid_pos, movement_controller, _) = head_query.iter(&app.world).next().unwrap();
         assert_eq!(grid_pos, &start_position);
         assert_eq!(&movement_controller.previous_position, &start_position);
-        assert_eq!(movement_controller.direction, Direction::Right);
+        assert_eq!(movement_controller.direction, Direction::Up);
     }
 
     #[test]
@@ -74,7 +79,9 @@ mod tests {
         let mut app = App::default();
         app.world.insert_resource(board::Desc {
             grid_size: (5, 5),
-            cell_size: 10
+            cell_size: 10,
+            boundary: board::BoundaryMode::Wrap,
+            obstacles: Vec::new()
         });
         let start_position = GridPosition{x:3, y:3};
         let cell_size = random::<f32>();
@@ -82,7 +89,9 @@ mod tests {
         let mut commands = state.get_mut(&mut (app.world));
         let entity_id = spawn(
             &mut commands,
+            PlayerId(0),
             start_position,
+            Direction::Right,
             cell_size
         );
         state.apply(&mut app.world);
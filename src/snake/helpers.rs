@@ -1,20 +1,92 @@
 use bevy::prelude::*;
 use bevy::sprite::Anchor;
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use crate::game_board::board;
-use crate::core::GridPosition;
+use crate::core::{DeathCause, Direction, GameOverRule, GridPosition, PlayerId};
 
+use super::brain::{BotControlled, BotKind};
+use super::controller::Dead;
 use super::head;
 use super::tail;
 use crate::food;
+use crate::pixel_text;
+use crate::speed::SpeedCurve;
 
-type WithAnySnakeType = Or<(With<head::SnakeHead>, With<tail::SnakeTail>)>;
+pub type WithAnySnakeType = Or<(With<head::SnakeHead>, With<tail::SnakeTail>)>;
+type WithoutSceneryType = (
+    Without<food::FoodComponent>,
+    Without<board::Obstacle>,
+    Without<pixel_text::PixelText>
+);
 
-#[derive(Clone)]
+const PLAYER_COLOURS: [Color; 4] = [
+    Color::rgb(0.25, 0.25, 0.75),
+    Color::rgb(0.85, 0.55, 0.1),
+    Color::rgb(0.6, 0.25, 0.75),
+    Color::rgb(0.1, 0.7, 0.7),
+];
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PlayerStart {
+    pub position: GridPosition,
+    pub direction: Direction
+}
+
+// Player 1 starts from `start_position`, any other players from `other_players`.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct InitParams{
     pub movement_time_step: Duration,
     pub start_position: GridPosition,
-    pub initial_tail_length: usize
+    #[serde(default)]
+    pub start_direction: Direction,
+    pub initial_tail_length: usize,
+    #[serde(default)]
+    pub speed_curve: SpeedCurve,
+    #[serde(default)]
+    pub other_players: Vec<PlayerStart>,
+    #[serde(default)]
+    pub game_over: GameOverRule,
+    // players steered by the computer
+    #[serde(default)]
+    pub bots: Vec<(PlayerId, BotKind)>
+}
+
+impl InitParams {
+    // A lone player at the default tick time, with nothing else turned on.
+    pub fn single_player(start_position: GridPosition, start_direction: Direction, initial_tail_length: usize) -> Self {
+        InitParams {
+            movement_time_step: Duration::from_millis(100),
+            start_position,
+            start_direction,
+            initial_tail_length,
+            speed_curve: Default::default(),
+            other_players: Vec::new(),
+            game_over: Default::default(),
+            bots: Vec::new()
+        }
+    }
+
+    pub fn starts(&self) -> Vec<(PlayerId, PlayerStart)> {
+        let first = PlayerStart { position: self.start_position, direction: self.start_direction.clone() };
+        std::iter::once(first)
+            .chain(self.other_players.iter().cloned())
+            .enumerate()
+            .map(|(index, start)| (PlayerId(index), start))
+            .collect()
+    }
+
+    pub fn player_count(&self) -> usize {
+        1 + self.other_players.len()
+    }
+
+    pub fn bot(&self, player: PlayerId) -> Option<BotKind> {
+        self.bots.iter().find(|(bot_player, _)| *bot_player == player).map(|(_, kind)| kind.clone())
+    }
+}
+
+pub fn player_colour(player: PlayerId) -> Color {
+    PLAYER_COLOURS[player.0 % PLAYER_COLOURS.len()]
 }
 
 pub fn add_snake(
@@ -22,28 +94,36 @@ pub fn add_snake(
     game_board: Res<board::Desc>,
     mut commands: Commands
 ) {
-    let head = head::spawn(
-        &mut commands,
-        init_data.start_position,
-        game_board.cell_size as f32
-    );
-    let mut follow_target = head;
-    let tail_default_pos = GridPosition{x: -1, y: -1};
-    for tail_index in 0..init_data.initial_tail_length {
-        let tail = tail::spawn_node(
+    for (player, start) in init_data.starts() {
+        let head = head::spawn(
             &mut commands,
-            tail_index,
-            game_board.cell_size as f32,
-            (follow_target, tail_default_pos)
+            player,
+            start.position,
+            start.direction,
+            game_board.cell_size as f32
         );
-        follow_target = tail;
+        if let Some(kind) = init_data.bot(player) {
+            commands.entity(head).insert(BotControlled(kind.brain()));
+        }
+        let mut follow_target = head;
+        let tail_default_pos = GridPosition{x: -1, y: -1};
+        for tail_index in 0..init_data.initial_tail_length {
+            let tail = tail::spawn_node(
+                &mut commands,
+                player,
+                tail_index,
+                game_board.cell_size as f32,
+                (follow_target, tail_default_pos)
+            );
+            follow_target = tail;
+        }
     }
 }
 
-pub fn get_snake_sprite_bundle(size: f32) -> SpriteBundle {
+pub fn get_snake_sprite_bundle(size: f32, player: PlayerId) -> SpriteBundle {
     SpriteBundle {
         sprite: Sprite {
-            color: Color::rgb(0.25, 0.25, 0.75),
+            color: player_colour(player),
             custom_size: Some(Vec2::new(size, size)),
             anchor: Anchor::TopLeft,
             ..default()
@@ -60,14 +140,29 @@ pub fn cleanup_snake(
     for entity in query.iter() {
         commands.entity(entity).despawn();
     }
+    commands.remove_resource::<DeathCause>();
 }
 
-pub fn set_death_sprites(mut query: Query<&mut Sprite, Without<food::FoodComponent>>) {
+pub fn set_death_sprites(mut query: Query<&mut Sprite, WithoutSceneryType>) {
     for mut sprite in query.iter_mut() {
         sprite.color = Color::RED;
     }
 }
 
+// Snakes that die while others play on are greyed out where they lie.
+pub fn set_dead_player_sprites(
+    dead_query: Query<&PlayerId, (With<head::SnakeHead>, Added<Dead>)>,
+    mut sprite_query: Query<(&PlayerId, &mut Sprite), WithAnySnakeType>
+) {
+    for dead_player in dead_query.iter() {
+        for (player, mut sprite) in sprite_query.iter_mut() {
+            if player == dead_player {
+                sprite.color = Color::rgb(0.4, 0.1, 0.1);
+            }
+        }
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -136,13 +231,11 @@ mod tests {
         let mut app = App::default();
         app.world.insert_resource(board::Desc {
             grid_size: (5, 5),
-            cell_size: 10
+            cell_size: 10,
+            boundary: board::BoundaryMode::Wrap,
+            obstacles: Vec::new()
         });
-        let init_params = InitParams{
-            movement_time_step: Default::default(),
-            start_position: GridPosition::new(3, 3),
-            initial_tail_length: 5
-        };
+        let init_params = InitParams::single_player(GridPosition::new(3, 3), Direction::Right, 5);
         app.insert_resource(init_params.clone());
         app.add_startup_system(add_snake);
         app.update();
@@ -166,13 +259,11 @@ mod tests {
         let mut app = App::default();
         app.world.insert_resource(board::Desc {
             grid_size: (5, 5),
-            cell_size: 10
-        });
-        app.insert_resource(InitParams{
-            movement_time_step: Default::default(),
-            start_position: GridPosition::new(3, 3),
-            initial_tail_length: 2
+            cell_size: 10,
+            boundary: board::BoundaryMode::Wrap,
+            obstacles: Vec::new()
         });
+        app.insert_resource(InitParams::single_player(GridPosition::new(3, 3), Direction::Right, 2));
         app.add_startup_system(add_snake);
         app.update();
 
@@ -196,10 +287,11 @@ mod tests {
     #[test]
     fn get_snake_sprite_bundle_correct_size_and_color() {
         let size = random::<f32>().abs();
-        let bundle = get_snake_sprite_bundle(size);
+        let bundle = get_snake_sprite_bundle(size, PlayerId(0));
         assert_eq!(bundle.sprite.custom_size.unwrap().x, size);
         assert_eq!(bundle.sprite.custom_size.unwrap().y, size);
         assert_eq!(bundle.sprite.color, Color::rgb(0.25, 0.25, 0.75));
+        assert_ne!(get_snake_sprite_bundle(size, PlayerId(1)).sprite.color, bundle.sprite.color);
         assert!(std::matches!(bundle.sprite.anchor, Anchor::TopLeft));
     }
 }
\ No newline at end of file
@@ -0,0 +1,178 @@
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::core::{not_resuming, GameState, GridPosition};
+use crate::game_board::board;
+use crate::tick::{AppTickExt, TickClock};
+use super::helpers::WithAnySnakeType;
+
+// Tweens snake sprites from cell to cell over the course of a tick instead of
+// snapping them. Only the drawing moves smoothly, the game still plays on the grid.
+pub struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_tick_system(track_moves
+                .run_in_state(GameState::RUNNING)
+                .after("grow"))
+            .add_system(interpolate
+                .run_in_state(GameState::RUNNING)
+                .after("snap"))
+            // the tick that ends the game is drawn where it ended
+            .add_enter_system(GameState::DEAD, finish_moves.run_if(not_resuming));
+    }
+}
+
+// The cells a segment moved between on the last tick.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Tween {
+    pub from: GridPosition,
+    pub to: GridPosition
+}
+
+impl Tween {
+    // Where the segment is drawn `progress` of the way through its move, in
+    // grid coordinates. A move across a wrapping edge slides out of one side
+    // and in at the other, anything that isn't a single step just jumps.
+    pub fn at(&self, game_board: &board::Desc, progress: f32) -> (f32, f32) {
+        let (width, height) = game_board.grid_size;
+        let mut dx = self.to.x - self.from.x;
+        let mut dy = self.to.y - self.from.y;
+        if game_board.boundary == board::BoundaryMode::Wrap {
+            dx = shortest(dx, width);
+            dy = shortest(dy, height);
+        }
+        if dx.abs() + dy.abs() != 1 || !game_board.is_inside(&self.from) {
+            return (self.to.x as f32, self.to.y as f32);
+        }
+        let x = self.from.x as f32 + dx as f32 * progress;
+        let y = self.from.y as f32 + dy as f32 * progress;
+        (wrap(x, width), wrap(y, height))
+    }
+}
+
+fn shortest(delta: i32, size: i32) -> i32 {
+    if delta > size / 2 {
+        delta - size
+    } else if delta < -size / 2 {
+        delta + size
+    } else {
+        delta
+    }
+}
+
+// Past the middle of an edge a sprite shows up on the opposite side.
+fn wrap(coord: f32, size: i32) -> f32 {
+    if coord < -0.5 {
+        coord + size as f32
+    } else if coord >= size as f32 - 0.5 {
+        coord - size as f32
+    } else {
+        coord
+    }
+}
+
+pub fn track_moves(
+    mut commands: Commands,
+    mut query: Query<(Entity, &GridPosition, Option<&mut Tween>), WithAnySnakeType>
+) {
+    for (entity, grid_pos, tween) in query.iter_mut() {
+        match tween {
+            Some(mut tween) => {
+                tween.from = tween.to;
+                tween.to = *grid_pos;
+            }
+            None => {
+                commands.entity(entity).insert(Tween { from: *grid_pos, to: *grid_pos });
+            }
+        }
+    }
+}
+
+pub fn interpolate(
+    game_board: Res<board::Desc>,
+    clock: Res<TickClock>,
+    mut query: Query<(&Tween, &mut Transform)>
+) {
+    let progress = clock.progress();
+    for (tween, mut transform) in query.iter_mut() {
+        let (x, y) = tween.at(&game_board, progress);
+        transform.translation = game_board.grid_coords_to_world_pos(x, y);
+    }
+}
+
+fn finish_moves(game_board: Res<board::Desc>, mut query: Query<(&Tween, &mut Transform)>) {
+    for (tween, mut transform) in query.iter_mut() {
+        transform.translation = game_board.grid_pos_to_world_pos(&tween.to);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use bevy::math::vec3;
+    use crate::snake::head::SnakeHead;
+    use super::*;
+
+    fn board(boundary: board::BoundaryMode) -> board::Desc {
+        board::Desc { grid_size: (5, 4), cell_size: 10, boundary, obstacles: Vec::new() }
+    }
+
+    fn tween(from: (i32, i32), to: (i32, i32)) -> Tween {
+        Tween { from: GridPosition::new(from.0, from.1), to: GridPosition::new(to.0, to.1) }
+    }
+
+    #[test]
+    fn tween_moves_between_neighbouring_cells() {
+        let board = board(board::BoundaryMode::Solid);
+        assert_eq!(tween((1, 1), (2, 1)).at(&board, 0.), (1., 1.));
+        assert_eq!(tween((1, 1), (2, 1)).at(&board, 0.25), (1.25, 1.));
+        assert_eq!(tween((1, 1), (1, 0)).at(&board, 0.5), (1., 0.5));
+        assert_eq!(tween((1, 1), (1, 1)).at(&board, 0.5), (1., 1.));
+    }
+
+    #[test]
+    fn tween_crosses_wrapping_edges_without_sliding_across_the_board() {
+        let board = board(board::BoundaryMode::Wrap);
+        assert_eq!(tween((4, 2), (0, 2)).at(&board, 0.25), (4.25, 2.));
+        assert_eq!(tween((4, 2), (0, 2)).at(&board, 0.75), (-0.25, 2.));
+        assert_eq!(tween((0, 2), (4, 2)).at(&board, 0.25), (-0.25, 2.));
+        assert_eq!(tween((0, 2), (4, 2)).at(&board, 0.75), (4.25, 2.));
+        assert_eq!(tween((1, 0), (1, 3)).at(&board, 0.75), (1., 3.25));
+    }
+
+    #[test]
+    fn tween_jumps_when_the_move_is_not_a_step() {
+        let wrap = board(board::BoundaryMode::Wrap);
+        let solid = board(board::BoundaryMode::Solid);
+        // a tail segment coming on to the board
+        assert_eq!(tween((-1, -1), (0, 0)).at(&wrap, 0.5), (0., 0.));
+        // a new game
+        assert_eq!(tween((3, 3), (0, 0)).at(&wrap, 0.5), (0., 0.));
+        assert_eq!(tween((4, 2), (0, 2)).at(&solid, 0.5), (0., 2.));
+    }
+
+    #[test]
+    fn interpolate_places_sprites_by_tick_progress() {
+        let mut app = App::default();
+        app.insert_resource(board(board::BoundaryMode::Wrap));
+        let mut clock = TickClock::new(Duration::from_millis(100));
+        clock.advance(Duration::from_millis(40));
+        app.insert_resource(clock);
+        app.add_system(track_moves.label("track"));
+        app.add_system(interpolate.after("track"));
+        let head = app.world
+            .spawn()
+            .insert(SnakeHead{})
+            .insert(GridPosition::new(4, 1))
+            .insert(Transform::default())
+            .id();
+        app.update();
+        app.world.entity_mut(head).insert(GridPosition::new(0, 1));
+        app.update();
+
+        assert_eq!(app.world.get::<Tween>(head), Some(&tween((4, 1), (0, 1))));
+        assert_eq!(app.world.get::<Transform>(head).unwrap().translation, vec3(44., -10., 0.));
+    }
+}
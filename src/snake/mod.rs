@@ -1,5 +1,9 @@
-mod head;
-mod tail;
-mod controller;
+pub mod battlesnake;
+pub mod brain;
+pub mod head;
+pub mod tail;
+pub mod controller;
+pub mod external;
 pub mod helpers;
+pub mod interpolation;
 pub mod plugin;
\ No newline at end of file
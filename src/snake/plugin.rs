@@ -1,9 +1,12 @@
 use bevy::prelude::*;
 use iyes_loopless::prelude::*;
 
-use std::collections::VecDeque;
-use crate::core::{GameState, GridPosition};
+use crate::core::{not_resuming, GameState};
+use crate::netplay;
+use crate::replay;
+use crate::tick::{AppTickExt, TickClock};
 
+use super::brain;
 use super::head;
 use super::tail;
 use super::controller;
@@ -13,46 +16,69 @@ pub struct SnakePlugin {
     pub init_params: helpers::InitParams
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, StageLabel)]
-struct FixedUpdate;
-
 impl Plugin for SnakePlugin {
     fn build(&self, app: &mut App) {
-
-        let fixed_time_step = SystemStage::parallel()
-            .with_system_set(ConditionSet::new()
+        app
+            .add_event::<controller::SnakeDied>()
+            .insert_resource(self.init_params.clone())
+            .insert_resource(TickClock::new(self.init_params.movement_time_step))
+            .add_enter_system(GameState::COUNTDOWN, helpers::cleanup_snake)
+            .add_enter_system(GameState::COUNTDOWN, helpers::add_snake)
+            .add_enter_system(GameState::DEAD, helpers::set_death_sprites.run_if(not_resuming))
+            .add_tick_system(brain::drive_bots
+                .run_in_state(GameState::RUNNING)
+                .label("think")
+                .before("move"))
+            .add_tick_system_set(ConditionSet::new()
                 .run_in_state(GameState::RUNNING)
                 .label("move")
                 .with_system(controller::move_head)
                 .into())
-            .with_system_set(
+            .add_tick_system_set(
                 ConditionSet::new()
                     .run_in_state(GameState::RUNNING)
+                    .label("collide")
+                    .label("crash")
                     .after("move")
-
-                    .with_system(controller::check_collide_with_food)
-                    .with_system(controller::check_for_bite_self)
-                    .into());
-
-        app
-            .insert_resource(VecDeque::<GridPosition>::new())
-            .insert_resource(self.init_params.clone())
-            .add_enter_system(GameState::RUNNING, helpers::add_snake)
-            .add_exit_system(GameState::DEAD, helpers::cleanup_snake)
-            .add_enter_system(GameState::DEAD, helpers::set_death_sprites)
-            .add_stage_before(
-                CoreStage::Update,
-                FixedUpdate,
-                FixedTimestepStage::new( self.init_params.movement_time_step)
-                    .with_stage(fixed_time_step)
-            )
-            .add_system_set(
+                    .with_system(controller::check_collide_with_obstacle)
+                    .with_system(controller::resolve_snake_collisions)
+                    .into())
+            // only snakes that live through the tick get to eat
+            .add_tick_system(controller::check_collide_with_food
+                .run_in_state(GameState::RUNNING)
+                .label("collide")
+                .after("crash"))
+            .add_tick_system(controller::check_game_over
+                .run_in_state(GameState::RUNNING)
+                .after("collide"))
+            .add_tick_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::RUNNING)
+                    .label("follow")
+                    .after("collide")
+                    .with_system(tail::tick_position)
+                    .into())
+            .add_tick_system_set(
                 ConditionSet::new()
                     .run_in_state(GameState::RUNNING)
-                    .with_system(controller::handle_input)
+                    .label("grow")
+                    .after("follow")
                     .with_system(controller::consume_food)
+                    .into())
+            // in a networked game turns only take effect once every player has
+            // them, and a replay feeds its turns in on the tick they were taken
+            .add_system(controller::handle_input
+                .run_in_state(GameState::RUNNING)
+                .run_if(netplay::not_networked)
+                .run_if(replay::not_playing_back)
+                .label("turn_input")
+                .after("input"))
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::RUNNING)
+                    .label("snap")
                     .with_system(head::tick_position)
-                    .with_system(tail::tick_position)
+                    .with_system(helpers::set_dead_player_sprites)
                     .into()
             );
     }
@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use crate::core::GridPosition;
+use crate::core::{GridPosition, PlayerId};
 use crate::game_board::board;
 use super::helpers;
 
@@ -12,11 +12,12 @@ pub struct SnakeTail{
 
 pub fn spawn_node(
     commands: &mut Commands,
+    player: PlayerId,
     tail_index: usize,
     cell_size: f32,
     follow_target: (Entity, GridPosition)
 ) -> Entity {
-    println!("spawn tail segment: {}", tail_index);
+    debug!("spawn tail segment: {}", tail_index);
     commands
         .spawn()
         .insert(SnakeTail{
@@ -25,7 +26,8 @@ pub fn spawn_node(
             next_position: follow_target.1
         })
         .insert(GridPosition{ x: -1, y: -1})
-        .insert_bundle(helpers::get_snake_sprite_bundle(cell_size))
+        .insert(player)
+        .insert_bundle(helpers::get_snake_sprite_bundle(cell_size, player))
         .id()
 }
 
@@ -34,7 +36,11 @@ pub fn tick_position(
     mut tail_query: Query<(Entity, &mut Transform, &mut SnakeTail)>,
     mut grid_pos_query: Query<&mut GridPosition>,
 ) {
-    for (tail_segment, mut transform, mut tail) in tail_query.iter_mut() {
+    // a segment only sees its target move if the target has already been
+    // updated this tick, so go from the head down whatever order the query has
+    let mut segments: Vec<_> = tail_query.iter_mut().collect();
+    segments.sort_by_key(|(_, _, tail)| tail.index);
+    for (tail_segment, mut transform, mut tail) in segments {
         if let Ok(target_grid_pos) = grid_pos_query.get(tail.follow_target) {
             if target_grid_pos != &tail.next_position {
                 let new_next_position = *target_grid_pos;
@@ -72,6 +78,7 @@ mod tests {
 
         spawn_node(
             &mut commands,
+            PlayerId(0),
             tail_params.segment_index,
             tail_params.cell_size,
             (follow_target, tail_params.follow_target_grid_pos)
@@ -109,7 +116,9 @@ mod tests {
         let mut app = App::default();
         app.world.insert_resource(board::Desc {
             grid_size: (5, 5),
-            cell_size: 10
+            cell_size: 10,
+            boundary: board::BoundaryMode::Wrap,
+            obstacles: Vec::new()
         });
         let tail_params = TailParams{
             segment_index: random::<usize>(),
@@ -154,7 +163,9 @@ mod tests {
         let mut app = App::default();
         app.world.insert_resource(board::Desc {
             grid_size: (5, 5),
-            cell_size: 10
+            cell_size: 10,
+            boundary: board::BoundaryMode::Wrap,
+            obstacles: Vec::new()
         });
         let tail_params = TailParams{
             segment_index: random::<usize>(),
@@ -208,6 +219,34 @@ mod tests {
         assert_eq!(tail_grid_pos, &GridPosition{x: 3, y: 2});
     }
 
+    #[test]
+    fn tick_position_keeps_a_tail_spawned_out_of_order_contiguous() {
+        let mut app = App::default();
+        app.world.insert_resource(board::Desc {
+            grid_size: (10, 10),
+            cell_size: 10,
+            boundary: board::BoundaryMode::Wrap,
+            obstacles: Vec::new()
+        });
+        let head = app.world.spawn().insert(GridPosition{x: 5, y: 2}).id();
+        // reserve the entities in order, then build them from the end of the tail up
+        let segments: Vec<Entity> = (0..4).map(|_| app.world.spawn().id()).collect();
+        for index in (0..4).rev() {
+            let follow_target = if index == 0 { head } else { segments[index - 1] };
+            app.world.entity_mut(segments[index])
+                .insert(SnakeTail{ index, follow_target, next_position: GridPosition{x: 5 - index as i32, y: 2} })
+                .insert(GridPosition{x: 4 - index as i32, y: 2})
+                .insert(Transform::default());
+        }
+        app.add_system(tick_position);
 
-
-}
\ No newline at end of file
+        for step in 1..=6 {
+            app.world.get_mut::<GridPosition>(head).unwrap().x += 1;
+            app.update();
+            let head_x = 5 + step;
+            for (index, segment) in segments.iter().enumerate() {
+                assert_eq!(app.world.get::<GridPosition>(*segment), Some(&GridPosition{x: head_x - 1 - index as i32, y: 2}));
+            }
+        }
+    }
+}
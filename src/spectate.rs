@@ -0,0 +1,453 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::Serialize;
+use crate::core::{GameState, GridPosition, PlayerId};
+use crate::food::FoodComponent;
+use crate::game_board::board;
+use crate::score::Score;
+use crate::snake::brain::{self, SnakeView};
+use crate::snake::controller::Dead;
+use crate::snake::head::SnakeHead;
+use crate::snake::tail::SnakeTail;
+use crate::tick::{AppTickExt, TickClock};
+
+// How long a new connection has to send a WebSocket handshake before it's taken
+// for a plain TCP client.
+const HANDSHAKE_WAIT: Duration = Duration::from_millis(200);
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// What spectators are sent after every tick and whenever the game state changes,
+// e.g.
+//
+//   {"tick":42,"state":"RUNNING","score":3,
+//    "board":{"grid_size":[15,15],"cell_size":15,"boundary":"Wrap","obstacles":[]},
+//    "snakes":[{"player":0,"alive":true,"body":[{"x":5,"y":7},{"x":4,"y":7}],"growing":1}],
+//    "food":[{"x":7,"y":7}]}
+//
+// with bodies head first and y counting down from the top. Every snapshot holds
+// the whole game, so a spectator can join at any time.
+#[derive(Serialize)]
+struct Snapshot {
+    tick: u64,
+    state: Option<GameState>,
+    score: u32,
+    board: Option<board::Desc>,
+    snakes: Vec<SnakeView>,
+    food: Vec<GridPosition>
+}
+
+// Publishes the game to anyone connected to `address`, which can be a port 0
+// address to let the system pick. Plain TCP clients get one snapshot per line.
+// Clients that open with a WebSocket handshake, such as a browser, get one per
+// text message. Nothing the spectators send is read, and a spectator that
+// can't keep up is dropped.
+pub struct SpectatorPlugin {
+    pub address: SocketAddr
+}
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = TcpListener::bind(self.address)
+            .unwrap_or_else(|e| panic!("failed to listen for spectators on {}: {}", self.address, e));
+        let address = listener.local_addr().unwrap_or(self.address);
+        info!("spectators can connect to {}", address);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                // a handshake can take a while, so each waits on its own thread
+                thread::spawn(move || match greet(stream) {
+                    Ok(spectator) => {
+                        let _ = sender.send(spectator);
+                    }
+                    Err(e) => warn!("spectator failed to connect: {}", e)
+                });
+            }
+        });
+        app
+            .insert_resource(Spectators {
+                address,
+                arrivals: Mutex::new(receiver),
+                spectators: Vec::new(),
+                latest: None,
+                last_state: None
+            })
+            .add_tick_system(publish_tick.exclusive_system().at_end())
+            .add_system_to_stage(CoreStage::PostUpdate, serve_spectators.exclusive_system());
+    }
+}
+
+pub struct Spectators {
+    address: SocketAddr,
+    arrivals: Mutex<Receiver<Spectator>>,
+    spectators: Vec<Spectator>,
+    latest: Option<String>,
+    last_state: Option<GameState>
+}
+
+impl Spectators {
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn count(&self) -> usize {
+        self.spectators.len()
+    }
+
+    fn publish(&mut self, snapshot: String) {
+        self.spectators.retain_mut(|spectator| spectator.send(&snapshot));
+        self.latest = Some(snapshot);
+    }
+
+    // New spectators start from the latest snapshot.
+    fn welcome(&mut self) {
+        let arrivals = match self.arrivals.lock() {
+            Ok(arrivals) => arrivals.try_iter().collect::<Vec<_>>(),
+            Err(_) => return
+        };
+        for mut spectator in arrivals {
+            if self.latest.as_ref().is_none_or(|latest| spectator.send(latest)) {
+                self.spectators.push(spectator);
+            }
+        }
+    }
+}
+
+struct Spectator {
+    stream: TcpStream,
+    websocket: bool
+}
+
+impl Spectator {
+    // false once the spectator has gone or fallen behind
+    fn send(&mut self, snapshot: &str) -> bool {
+        let message = match self.websocket {
+            true => websocket_frame(snapshot),
+            false => format!("{}\n", snapshot).into_bytes()
+        };
+        self.stream.write_all(&message).is_ok()
+    }
+}
+
+fn greet(mut stream: TcpStream) -> std::io::Result<Spectator> {
+    stream.set_read_timeout(Some(HANDSHAKE_WAIT))?;
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < 8192 {
+        match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => request.extend_from_slice(&buffer[..read]),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e)
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    let key = request
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("sec-websocket-key"))
+        .map(|(_, key)| key.trim());
+    if let Some(key) = key {
+        write!(
+            stream,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            websocket_accept(key)
+        )?;
+    }
+    // writes that would block mean the spectator is too far behind
+    stream.set_nonblocking(true)?;
+    Ok(Spectator { stream, websocket: key.is_some() })
+}
+
+fn snapshot(world: &mut World) -> String {
+    let heads = world
+        .query_filtered::<(&PlayerId, &GridPosition, Option<&Dead>), With<SnakeHead>>()
+        .iter(world)
+        .map(|(player, grid_pos, dead)| (*player, *grid_pos, dead.is_none()))
+        .collect::<Vec<_>>();
+    let tails = world
+        .query::<(&PlayerId, &GridPosition, &SnakeTail)>()
+        .iter(world)
+        .map(|(player, grid_pos, tail)| (*player, tail.index, *grid_pos))
+        .collect::<Vec<_>>();
+    let mut food = world
+        .query_filtered::<&GridPosition, With<FoodComponent>>()
+        .iter(world)
+        .copied()
+        .collect::<Vec<_>>();
+    food.sort_by_key(|grid_pos| (grid_pos.y, grid_pos.x));
+    let board = world.get_resource::<board::Desc>().cloned();
+    let mut snakes = match &board {
+        Some(board) => brain::snake_views(board, heads.into_iter(), &tails),
+        None => Vec::new()
+    };
+    snakes.sort_by_key(|snake| snake.player.0);
+
+    let snapshot = Snapshot {
+        tick: world.get_resource::<TickClock>().map(|clock| clock.tick()).unwrap_or(0),
+        state: world.get_resource::<CurrentState<GameState>>().map(|state| state.0.clone()),
+        score: world.get_resource::<Score>().map(|score| score.value).unwrap_or(0),
+        board,
+        snakes,
+        food
+    };
+    serde_json::to_string(&snapshot).unwrap_or_default()
+}
+
+// Runs at the end of each tick, once everything the tick did has been applied.
+fn publish_tick(world: &mut World) {
+    let snapshot = snapshot(world);
+    world.resource_mut::<Spectators>().publish(snapshot);
+}
+
+fn serve_spectators(world: &mut World) {
+    let state = world.get_resource::<CurrentState<GameState>>().map(|state| state.0.clone());
+    let spectators = world.resource::<Spectators>();
+    let changed = spectators.latest.is_none() || spectators.last_state != state;
+    if changed {
+        let snapshot = snapshot(world);
+        let mut spectators = world.resource_mut::<Spectators>();
+        spectators.publish(snapshot);
+        spectators.last_state = state;
+    }
+    world.resource_mut::<Spectators>().welcome();
+}
+
+// A single unmasked text frame, as a server sends them.
+fn websocket_frame(text: &str) -> Vec<u8> {
+    let mut frame = vec![0x81];
+    match text.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(text.as_bytes());
+    frame
+}
+
+fn websocket_accept(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for index in 16..80 {
+            words[index] = (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (index, word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6)
+            };
+            let next = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = next;
+        }
+        for (value, added) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let value = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for index in 0..4 {
+            match index <= chunk.len() {
+                true => encoded.push(ALPHABET[(value >> (18 - 6 * index) & 63) as usize] as char),
+                false => encoded.push('=')
+            }
+        }
+    }
+    encoded
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::batch;
+    use crate::score::ScorePlugin;
+    use super::*;
+
+    fn init_game() -> App {
+        let mut level = batch::open_level((10, 10));
+        level.snake.initial_tail_length = 2;
+        let mut app = App::default();
+        app.add_plugins(MinimalPlugins);
+        batch::add_headless_game(&mut app, &level, 3);
+        app.add_plugin(ScorePlugin { hud: false });
+        app.insert_resource(TickClock::unpaced());
+        app.add_plugin(SpectatorPlugin { address: "127.0.0.1:0".parse().unwrap() });
+        app
+    }
+
+    // Keeps the game going until the client has been sent `count` messages.
+    fn receive(app: &mut App, client: &mut TcpStream, websocket: bool, count: usize) -> Vec<serde_json::Value> {
+        client.set_read_timeout(Some(Duration::from_millis(5))).unwrap();
+        let mut received = Vec::new();
+        let mut buffer = [0; 4096];
+        for _ in 0..1000 {
+            if let Ok(read) = client.read(&mut buffer) {
+                received.extend_from_slice(&buffer[..read]);
+            }
+            let messages = match websocket {
+                true => websocket_messages(&received),
+                false => received
+                    .split(|byte| *byte == b'\n')
+                    .filter(|line| !line.is_empty())
+                    .map(|line| line.to_vec())
+                    .collect()
+            };
+            if messages.len() >= count {
+                return messages.iter().take(count).map(|message| serde_json::from_slice(message).unwrap()).collect();
+            }
+            app.update();
+        }
+        panic!("spectator was only sent {:?}", String::from_utf8_lossy(&received));
+    }
+
+    fn websocket_messages(mut bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        while bytes.len() >= 2 {
+            assert_eq!(bytes[0], 0x81);
+            let (length, start) = match bytes[1] {
+                126 if bytes.len() >= 4 => (u16::from_be_bytes([bytes[2], bytes[3]]) as usize, 4),
+                126 => break,
+                length => (length as usize, 2)
+            };
+            match bytes.get(start..start + length) {
+                Some(message) => messages.push(message.to_vec()),
+                None => break
+            }
+            bytes = &bytes[start + length..];
+        }
+        messages
+    }
+
+    #[test]
+    fn accepts_websocket_handshake() {
+        // the example from RFC 6455
+        assert_eq!(websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(base64(b"snake"), "c25ha2U=");
+        assert_eq!(base64(b"sn"), "c24=");
+    }
+
+    #[test]
+    fn spectators_follow_every_tick() {
+        let mut app = init_game();
+        let mut client = TcpStream::connect(app.world.resource::<Spectators>().address()).unwrap();
+        let snapshots = receive(&mut app, &mut client, false, 4);
+        let ticks = snapshots.iter().map(|snapshot| snapshot["tick"].as_u64().unwrap()).collect::<Vec<_>>();
+        for pair in ticks[1..].windows(2) {
+            assert_eq!(pair[1], pair[0] + 1);
+        }
+
+        // by now the snake has gone past the first food at x = 5
+        let last = &snapshots[3];
+        let tick = last["tick"].as_i64().unwrap() as i32;
+        assert!(tick > 5);
+        assert_eq!(last["state"], "RUNNING");
+        assert!(last["score"].as_u64().unwrap() >= 1);
+        assert_eq!(last["board"]["grid_size"], serde_json::json!([10, 10]));
+        assert_eq!(last["food"].as_array().unwrap().len(), 1);
+        assert_eq!(last["snakes"][0]["body"][0], serde_json::json!({"x": tick % 10, "y": 5}));
+        assert_eq!(last["snakes"][0]["alive"], true);
+    }
+
+    #[test]
+    fn late_spectators_get_the_whole_game() {
+        let mut app = init_game();
+        for _ in 0..10 {
+            app.update();
+        }
+        let joined_at = app.world.resource::<TickClock>().tick();
+        assert!(joined_at > 0);
+        let mut client = TcpStream::connect(app.world.resource::<Spectators>().address()).unwrap();
+        let first = receive(&mut app, &mut client, false, 1).remove(0);
+        assert!(first["tick"].as_u64().unwrap() >= joined_at);
+        assert!(first["snakes"][0]["body"].as_array().unwrap().len() >= 3);
+        assert_eq!(first["food"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn websocket_spectators_get_text_frames() {
+        let mut app = init_game();
+        let mut client = TcpStream::connect(app.world.resource::<Spectators>().address()).unwrap();
+        write!(client, "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+            Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n").unwrap();
+
+        let mut response = Vec::new();
+        let mut byte = [0];
+        while !response.ends_with(b"\r\n\r\n") {
+            client.read_exact(&mut byte).unwrap();
+            response.push(byte[0]);
+        }
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+
+        let snapshots = receive(&mut app, &mut client, true, 2);
+        assert!(snapshots[1]["tick"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn gone_spectators_are_dropped() {
+        let mut app = init_game();
+        let client = TcpStream::connect(app.world.resource::<Spectators>().address()).unwrap();
+        for _ in 0..200 {
+            if app.world.resource::<Spectators>().count() == 1 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(2));
+            app.update();
+        }
+        assert_eq!(app.world.resource::<Spectators>().count(), 1);
+        drop(client);
+        for _ in 0..20 {
+            app.update();
+        }
+        assert_eq!(app.world.resource::<Spectators>().count(), 0);
+    }
+}
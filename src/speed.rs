@@ -0,0 +1,189 @@
+use std::str::FromStr;
+use std::time::Duration;
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::core::GameState;
+use crate::score::Score;
+use crate::snake;
+use crate::tick::{AppTickExt, TickClock};
+
+const DEFAULT_LINEAR_STEP_MS: u64 = 2;
+const DEFAULT_STEPPED_EVERY: u32 = 5;
+const DEFAULT_STEPPED_STEP_MS: u64 = 10;
+const DEFAULT_MIN_MS: u64 = 50;
+
+// How the tick time shrinks as the score goes up. Neither curve ever goes below
+// `min_ms`, or slows down a game that already starts faster than that.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub enum SpeedCurve {
+    #[default]
+    Constant,
+    // every point takes `step_ms` off the tick time
+    Linear { step_ms: u64, min_ms: u64 },
+    // every `every` points take `step_ms` off the tick time
+    Stepped { every: u32, step_ms: u64, min_ms: u64 }
+}
+
+impl SpeedCurve {
+    pub fn time_step(&self, base: Duration, score: u32) -> Duration {
+        let (speed_up, min_ms) = match *self {
+            SpeedCurve::Constant => return base,
+            SpeedCurve::Linear { step_ms, min_ms } => (step_ms * score as u64, min_ms),
+            SpeedCurve::Stepped { every, step_ms, min_ms } => (step_ms * (score / every.max(1)) as u64, min_ms)
+        };
+        let min = Duration::from_millis(min_ms).min(base);
+        base.saturating_sub(Duration::from_millis(speed_up)).max(min)
+    }
+}
+
+// `constant`, `linear[:step_ms[:min_ms]]` or `stepped[:every[:step_ms[:min_ms]]]`.
+impl FromStr for SpeedCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default().to_lowercase();
+        let mut numbers = Vec::new();
+        for part in parts {
+            numbers.push(part.trim().parse::<u64>().map_err(|_| format!("invalid number '{}' in speed curve '{}'", part, s))?);
+        }
+        let number = |index: usize, default: u64| numbers.get(index).copied().unwrap_or(default);
+        let (curve, arguments) = match name.trim() {
+            "constant" => (SpeedCurve::Constant, 0),
+            "linear" => (SpeedCurve::Linear {
+                step_ms: number(0, DEFAULT_LINEAR_STEP_MS),
+                min_ms: number(1, DEFAULT_MIN_MS)
+            }, 2),
+            "stepped" => (SpeedCurve::Stepped {
+                every: number(0, DEFAULT_STEPPED_EVERY as u64) as u32,
+                step_ms: number(1, DEFAULT_STEPPED_STEP_MS),
+                min_ms: number(2, DEFAULT_MIN_MS)
+            }, 3),
+            _ => return Err(format!("unknown speed curve '{}', expected constant, linear or stepped", s))
+        };
+        if numbers.len() > arguments {
+            return Err(format!("too many numbers in speed curve '{}'", s));
+        }
+        if let SpeedCurve::Stepped { every: 0, .. } = curve {
+            return Err(format!("speed curve '{}' must step every one or more points", s));
+        }
+        Ok(curve)
+    }
+}
+
+// Applies the snake's speed curve to the tick clock as the score changes.
+pub struct SpeedPlugin;
+
+impl Plugin for SpeedPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_enter_system(GameState::COUNTDOWN, reset_speed)
+            .add_tick_system(speed_up
+                .run_in_state(GameState::RUNNING)
+                .after("score"));
+    }
+}
+
+fn reset_speed(snake_params: Res<snake::helpers::InitParams>, mut clock: ResMut<TickClock>) {
+    clock.set_step(snake_params.speed_curve.time_step(snake_params.movement_time_step, 0));
+}
+
+fn speed_up(
+    score: Res<Score>,
+    snake_params: Res<snake::helpers::InitParams>,
+    mut clock: ResMut<TickClock>
+) {
+    clock.set_step(snake_params.speed_curve.time_step(snake_params.movement_time_step, score.value));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::Events;
+    use crate::core::{Direction, GridPosition, PlayerId};
+    use crate::food;
+    use crate::score::ScorePlugin;
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn constant_never_changes() {
+        assert_eq!(SpeedCurve::Constant.time_step(ms(100), 0), ms(100));
+        assert_eq!(SpeedCurve::Constant.time_step(ms(100), 1000), ms(100));
+    }
+
+    #[test]
+    fn linear_speeds_up_every_point_down_to_min() {
+        let curve = SpeedCurve::Linear { step_ms: 5, min_ms: 60 };
+        assert_eq!(curve.time_step(ms(100), 0), ms(100));
+        assert_eq!(curve.time_step(ms(100), 3), ms(85));
+        assert_eq!(curve.time_step(ms(100), 8), ms(60));
+        assert_eq!(curve.time_step(ms(100), 100), ms(60));
+        assert_eq!(curve.time_step(ms(40), 10), ms(40));
+    }
+
+    #[test]
+    fn stepped_speeds_up_in_steps() {
+        let curve = SpeedCurve::Stepped { every: 5, step_ms: 10, min_ms: 50 };
+        assert_eq!(curve.time_step(ms(100), 4), ms(100));
+        assert_eq!(curve.time_step(ms(100), 5), ms(90));
+        assert_eq!(curve.time_step(ms(100), 14), ms(80));
+        assert_eq!(curve.time_step(ms(100), 1000), ms(50));
+    }
+
+    #[test]
+    fn parse_speed_curve() {
+        assert_eq!("constant".parse(), Ok(SpeedCurve::Constant));
+        assert_eq!("Linear".parse(), Ok(SpeedCurve::Linear { step_ms: DEFAULT_LINEAR_STEP_MS, min_ms: DEFAULT_MIN_MS }));
+        assert_eq!("linear:4:70".parse(), Ok(SpeedCurve::Linear { step_ms: 4, min_ms: 70 }));
+        assert_eq!("stepped:3".parse(), Ok(SpeedCurve::Stepped {
+            every: 3,
+            step_ms: DEFAULT_STEPPED_STEP_MS,
+            min_ms: DEFAULT_MIN_MS
+        }));
+        assert!("linear:1:2:3".parse::<SpeedCurve>().is_err());
+        assert!("linear:fast".parse::<SpeedCurve>().is_err());
+        assert!("stepped:0".parse::<SpeedCurve>().is_err());
+        assert!("warp".parse::<SpeedCurve>().is_err());
+    }
+
+    #[test]
+    fn clock_follows_score_and_resets_for_new_game() {
+        let mut app = App::default();
+        app.add_loopless_state(GameState::COUNTDOWN);
+        app.insert_resource(TickClock::new(ms(1)));
+        app.insert_resource(snake::helpers::InitParams {
+            speed_curve: SpeedCurve::Linear { step_ms: 10, min_ms: 50 },
+            ..snake::helpers::InitParams::single_player(GridPosition::new(0, 0), Direction::Right, 0)
+        });
+        app.add_event::<food::ConsumeEvent>();
+        app.add_plugin(ScorePlugin { hud: false });
+        app.add_plugin(SpeedPlugin);
+        app.update();
+        assert_eq!(app.world.resource::<TickClock>().step(), ms(100));
+
+        app.world.insert_resource(NextState(GameState::RUNNING));
+        app.update();
+        app.world.resource_mut::<Score>().value = 3;
+        // the changed step only shows once a tick has run
+        app.world.resource_mut::<TickClock>().advance(ms(100));
+        app.update();
+        assert_eq!(app.world.resource::<TickClock>().step(), ms(70));
+
+        app.world.resource_mut::<Events<food::ConsumeEvent>>().send(
+            food::ConsumeEvent{ target: Entity::from_raw(0), eater: PlayerId(0) }
+        );
+        app.world.resource_mut::<TickClock>().advance(ms(70));
+        app.update();
+        assert_eq!(app.world.resource::<Score>().value, 4);
+        assert_eq!(app.world.resource::<TickClock>().step(), ms(60));
+
+        app.world.insert_resource(NextState(GameState::COUNTDOWN));
+        app.update();
+        assert_eq!(app.world.resource::<TickClock>().step(), ms(100));
+    }
+}
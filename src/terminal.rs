@@ -0,0 +1,441 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::core::{CountdownTimer, Direction, DirectionEvent, GameState, GridPosition, PlayerId};
+use crate::food::FoodComponent;
+use crate::game_board::board::{self, BoundaryMode};
+use crate::input::{Binding, MetaAction};
+use crate::menu;
+use crate::score::Score;
+use crate::snake::brain::{self, SnakeView};
+use crate::snake::controller::Dead;
+use crate::snake::head::SnakeHead;
+use crate::snake::helpers::player_colour;
+use crate::snake::tail::SnakeTail;
+
+// Head and tail characters for each player, taken in turn like the colours.
+const PLAYER_GLYPHS: [(char, char); 4] = [('@', 'o'), ('&', 'x'), ('%', '+'), ('$', '=')];
+const DEAD_HEAD: char = 'X';
+const FOOD: char = '*';
+const OBSTACLE: char = '#';
+const EMPTY: char = ' ';
+// How long a lone ESC waits for the rest of an arrow before it's the escape key.
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(50);
+
+// Draws the game as text in the terminal it was started from, in place of the
+// window, so it plays the same over SSH. Goes with MinimalPlugins, and with
+// TerminalInputPlugin to play rather than watch.
+pub struct TerminalPlugin;
+
+impl Plugin for TerminalPlugin {
+    fn build(&self, app: &mut App) {
+        // hide the cursor and start from a clear screen
+        print!("\x1b[?25l\x1b[2J");
+        app
+            .insert_resource(TerminalScreen { last_frame: String::new() })
+            .add_system_to_stage(CoreStage::PostUpdate, draw_terminal);
+    }
+}
+
+pub struct TerminalScreen {
+    last_frame: String
+}
+
+impl Drop for TerminalScreen {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\r\n");
+        let _ = std::io::stdout().flush();
+    }
+}
+
+// The board inside a border, a solid one for walls and a dotted one for edges
+// that wrap, and `status` below. Heads go over tails and live heads over dead
+// ones, so a head is never hidden.
+pub fn render(game_board: &board::Desc, snakes: &[SnakeView], food: &[GridPosition], status: &str) -> Vec<String> {
+    let (width, height) = game_board.grid_size;
+    let mut cells = vec![vec![EMPTY; width as usize]; height as usize];
+    let mut draw = |grid_pos: &GridPosition, glyph: char| {
+        if game_board.is_inside(grid_pos) {
+            cells[grid_pos.y as usize][grid_pos.x as usize] = glyph;
+        }
+    };
+    for grid_pos in game_board.obstacles.iter() {
+        draw(grid_pos, OBSTACLE);
+    }
+    for grid_pos in food.iter() {
+        draw(grid_pos, FOOD);
+    }
+    for snake in snakes.iter() {
+        let (_, tail) = PLAYER_GLYPHS[snake.player.0 % PLAYER_GLYPHS.len()];
+        for grid_pos in snake.body.iter().skip(1) {
+            draw(grid_pos, tail);
+        }
+    }
+    for snake in snakes.iter().filter(|snake| !snake.alive).chain(snakes.iter().filter(|snake| snake.alive)) {
+        let (head, _) = PLAYER_GLYPHS[snake.player.0 % PLAYER_GLYPHS.len()];
+        if let Some(grid_pos) = snake.body.first() {
+            draw(grid_pos, if snake.alive { head } else { DEAD_HEAD });
+        }
+    }
+
+    let (edge, side) = match game_board.boundary {
+        BoundaryMode::Wrap => ('.', ':'),
+        BoundaryMode::Solid | BoundaryMode::Reflect => ('-', '|')
+    };
+    let border = format!("+{}+", edge.to_string().repeat(width as usize));
+    std::iter::once(border.clone())
+        .chain(cells.iter().map(|row| format!("{}{}{}", side, row.iter().collect::<String>(), side)))
+        .chain([border, String::from(status)])
+        .collect()
+}
+
+pub fn status(state: &GameState, score: u32, countdown_seconds: u32) -> String {
+    match state {
+        GameState::MAIN_MENU => String::from("SNAKE"),
+        GameState::COUNTDOWN => format!("SCORE {}  GET READY {}", score, countdown_seconds),
+        GameState::RUNNING => format!("SCORE {}", score),
+        GameState::PAUSED => format!("SCORE {}  PAUSED, P TO GO ON", score),
+        GameState::DEAD => format!("SCORE {}  GAME OVER", score)
+    }
+}
+
+// Wraps each character of a frame in its colour, snakes in their player colour.
+fn colour(lines: &[String]) -> String {
+    let ansi = |color: Color| format!(
+        "\x1b[38;2;{};{};{}m",
+        (color.r() * 255.) as u8,
+        (color.g() * 255.) as u8,
+        (color.b() * 255.) as u8
+    );
+    let mut coloured = String::from("\x1b[H");
+    for line in lines {
+        for glyph in line.chars() {
+            let player = PLAYER_GLYPHS.iter().position(|(head, tail)| glyph == *head || glyph == *tail);
+            let color = match glyph {
+                FOOD => Some(Color::rgb(1., 1., 0.)),
+                OBSTACLE => Some(Color::GRAY),
+                DEAD_HEAD => Some(Color::RED),
+                _ => player.map(|player| player_colour(PlayerId(player)))
+            };
+            match color {
+                Some(color) => coloured.push_str(&format!("{}{}\x1b[0m", ansi(color), glyph)),
+                None => coloured.push(glyph)
+            }
+        }
+        // clear whatever a longer line left behind
+        coloured.push_str("\x1b[K\r\n");
+    }
+    coloured.push_str("\x1b[J");
+    coloured
+}
+
+// Redraws whenever the picture changes, so once per tick while a game is on.
+#[allow(clippy::too_many_arguments)]
+fn draw_terminal(
+    mut screen: ResMut<TerminalScreen>,
+    game_board: Res<board::Desc>,
+    state: Res<CurrentState<GameState>>,
+    score: Option<Res<Score>>,
+    countdown: Option<Res<CountdownTimer>>,
+    head_query: Query<(&PlayerId, &GridPosition, Option<&Dead>), With<SnakeHead>>,
+    tail_query: Query<(&PlayerId, &GridPosition, &SnakeTail)>,
+    food_query: Query<&GridPosition, With<FoodComponent>>
+) {
+    let tails = tail_query
+        .iter()
+        .map(|(player, grid_pos, tail)| (*player, tail.index, *grid_pos))
+        .collect::<Vec<_>>();
+    let heads = head_query.iter().map(|(player, grid_pos, dead)| (*player, *grid_pos, dead.is_none()));
+    let mut snakes = brain::snake_views(&game_board, heads, &tails);
+    snakes.sort_by_key(|snake| snake.player.0);
+    let food = food_query.iter().copied().collect::<Vec<_>>();
+    let status = status(
+        &state.0,
+        score.map(|score| score.value).unwrap_or(0),
+        countdown.map(|countdown| countdown.seconds_left()).unwrap_or(0)
+    );
+
+    let frame = render(&game_board, &snakes, &food, &status).join("\n");
+    if frame != screen.last_frame {
+        let lines = frame.lines().map(String::from).collect::<Vec<_>>();
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(colour(&lines).as_bytes()).and_then(|_| stdout.flush());
+        screen.last_frame = frame;
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Key {
+    Arrow(Direction),
+    Char(char),
+    Escape,
+    Interrupt
+}
+
+// Keys in `bytes` read from a terminal, and how many bytes they took up. An
+// escape sequence cut off at the end is left for the next read.
+pub fn parse_keys(bytes: &[u8]) -> (Vec<Key>, usize) {
+    let mut keys = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            // arrows are ESC [ A to D, or ESC O A to D in application mode
+            0x1b => match (bytes.get(index + 1), bytes.get(index + 2)) {
+                (None, _) | (Some(b'[' | b'O'), None) => break,
+                (Some(b'[' | b'O'), Some(code)) => {
+                    let direction = match code {
+                        b'A' => Some(Direction::Up),
+                        b'B' => Some(Direction::Down),
+                        b'C' => Some(Direction::Right),
+                        b'D' => Some(Direction::Left),
+                        _ => None
+                    };
+                    keys.extend(direction.map(Key::Arrow));
+                    index += 3;
+                }
+                (Some(_), _) => index += 1
+            },
+            0x03 => {
+                keys.push(Key::Interrupt);
+                index += 1;
+            }
+            byte => {
+                keys.push(Key::Char(byte as char));
+                index += 1;
+            }
+        }
+    }
+    (keys, index)
+}
+
+// The keys in what parse_keys left over once nothing more arrived for it, an
+// ESC on its own being the escape key.
+pub fn flush_keys(bytes: &[u8]) -> Vec<Key> {
+    let (mut keys, used) = parse_keys(bytes);
+    if used < bytes.len() {
+        keys.push(Key::Escape);
+        keys.extend(bytes[used + 1..].iter().map(|byte| Key::Char(*byte as char)));
+    }
+    keys
+}
+
+// Arrows steer player 1 and WASD player 2, as with the default key bindings.
+pub fn key_binding(key: &Key) -> Option<Binding> {
+    match key {
+        Key::Arrow(direction) => Some(Binding::Turn(PlayerId(0), direction.clone())),
+        Key::Escape => Some(Binding::Meta(MetaAction::Pause)),
+        Key::Interrupt => Some(Binding::Meta(MetaAction::Quit)),
+        Key::Char(key) => match key.to_ascii_lowercase() {
+            'w' => Some(Binding::Turn(PlayerId(1), Direction::Up)),
+            's' => Some(Binding::Turn(PlayerId(1), Direction::Down)),
+            'a' => Some(Binding::Turn(PlayerId(1), Direction::Left)),
+            'd' => Some(Binding::Turn(PlayerId(1), Direction::Right)),
+            'p' | ' ' => Some(Binding::Meta(MetaAction::Pause)),
+            'r' => Some(Binding::Meta(MetaAction::Restart)),
+            'q' => Some(Binding::Meta(MetaAction::Quit)),
+            _ => None
+        }
+    }
+}
+
+// Reads keys from the terminal in place of GameInputPlugin. The terminal is put
+// into raw mode with `stty` for as long as the app runs, so keys arrive as
+// they're pressed, and Ctrl-C quits through the game, which puts it back. A
+// panic puts it back too.
+pub struct TerminalInputPlugin;
+
+impl Plugin for TerminalInputPlugin {
+    fn build(&self, app: &mut App) {
+        let saved_mode = stty(&["-g"]);
+        match &saved_mode {
+            Some(mode) if stty(&["-icanon", "-echo", "-isig", "min", "1", "time", "0"]).is_some() => {
+                restore_on_panic(mode.clone());
+            }
+            _ => warn!("stdin isn't a terminal, keys won't be read until enter is pressed")
+        }
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 64];
+            while let Ok(read) = std::io::stdin().lock().read(&mut buffer) {
+                if read == 0 || sender.send(buffer[..read].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        app
+            .insert_resource(TerminalInput {
+                bytes: Mutex::new(receiver),
+                pending: Vec::new(),
+                pending_since: None,
+                saved_mode
+            })
+            .add_event::<DirectionEvent>()
+            .add_event::<MetaAction>()
+            .add_system(read_terminal_keys.label("input"))
+            .add_system(menu::meta_action_input.after("input"));
+    }
+}
+
+pub struct TerminalInput {
+    bytes: Mutex<Receiver<Vec<u8>>>,
+    pending: Vec<u8>,
+    pending_since: Option<Instant>,
+    saved_mode: Option<String>
+}
+
+impl Drop for TerminalInput {
+    fn drop(&mut self) {
+        if let Some(mode) = self.saved_mode.take() {
+            stty(&[&mode]);
+        }
+    }
+}
+
+// The screen and the terminal mode as they were, before the panic message.
+fn restore_on_panic(mode: String) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        stty(&[&mode]);
+        print!("\x1b[0m\x1b[?25h\r\n");
+        let _ = std::io::stdout().flush();
+        default_hook(info);
+    }));
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn read_terminal_keys(
+    mut input: ResMut<TerminalInput>,
+    mut direction_events: EventWriter<DirectionEvent>,
+    mut meta_events: EventWriter<MetaAction>
+) {
+    let input = input.as_mut();
+    if let Ok(bytes) = input.bytes.lock() {
+        input.pending.extend(bytes.try_iter().flatten());
+    }
+    let (mut keys, used) = parse_keys(&input.pending);
+    input.pending.drain(..used);
+    if used > 0 {
+        input.pending_since = None;
+    }
+    if !input.pending.is_empty() {
+        let since = *input.pending_since.get_or_insert_with(Instant::now);
+        if since.elapsed() >= ESCAPE_TIMEOUT {
+            keys.extend(flush_keys(&input.pending));
+            input.pending.clear();
+            input.pending_since = None;
+        }
+    }
+    for binding in keys.iter().filter_map(key_binding) {
+        match binding {
+            Binding::Turn(player, direction) => direction_events.send(DirectionEvent { player, direction }),
+            Binding::Meta(action) => meta_events.send(action)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snake(player: usize, alive: bool, body: &[(i32, i32)]) -> SnakeView {
+        SnakeView {
+            player: PlayerId(player),
+            alive,
+            body: body.iter().map(|(x, y)| GridPosition::new(*x, *y)).collect(),
+            growing: 0
+        }
+    }
+
+    #[test]
+    fn renders_a_wrapping_board() {
+        let game_board = board::Desc {
+            grid_size: (6, 4),
+            cell_size: 10,
+            boundary: BoundaryMode::Wrap,
+            obstacles: vec![GridPosition::new(5, 0)]
+        };
+        let snakes = [snake(0, true, &[(3, 1), (2, 1), (1, 1), (1, 2)])];
+        let frame = render(&game_board, &snakes, &[GridPosition::new(4, 3)], &status(&GameState::RUNNING, 2, 0));
+        assert_eq!(frame.join("\n"), "\
++......+
+:     #:
+: oo@  :
+: o    :
+:    * :
++......+
+SCORE 2");
+    }
+
+    #[test]
+    fn renders_players_and_dead_snakes() {
+        let game_board = board::Desc { grid_size: (5, 3), cell_size: 10, boundary: BoundaryMode::Solid, obstacles: Vec::new() };
+        // the second snake died running into the first one's head
+        let snakes = [
+            snake(0, true, &[(2, 1), (1, 1), (0, 1)]),
+            snake(1, false, &[(2, 1), (3, 1), (4, 1), (4, 2)]),
+        ];
+        let frame = render(&game_board, &snakes, &[], &status(&GameState::DEAD, 0, 0));
+        assert_eq!(frame.join("\n"), "\
++-----+
+|     |
+|oo@xx|
+|    x|
++-----+
+SCORE 0  GAME OVER");
+    }
+
+    #[test]
+    fn countdown_and_pause_show_in_status() {
+        assert_eq!(status(&GameState::COUNTDOWN, 0, 3), "SCORE 0  GET READY 3");
+        assert_eq!(status(&GameState::PAUSED, 7, 0), "SCORE 7  PAUSED, P TO GO ON");
+    }
+
+    #[test]
+    fn parses_arrows_and_letters() {
+        let (keys, used) = parse_keys(b"\x1b[A\x1bOCwq\x03");
+        assert_eq!(keys, vec![
+            Key::Arrow(Direction::Up),
+            Key::Arrow(Direction::Right),
+            Key::Char('w'),
+            Key::Char('q'),
+            Key::Interrupt,
+        ]);
+        assert_eq!(used, 9);
+        assert_eq!(key_binding(&keys[0]), Some(Binding::Turn(PlayerId(0), Direction::Up)));
+        assert_eq!(key_binding(&keys[2]), Some(Binding::Turn(PlayerId(1), Direction::Up)));
+        assert_eq!(key_binding(&keys[4]), Some(Binding::Meta(MetaAction::Quit)));
+        assert_eq!(key_binding(&Key::Char('z')), None);
+    }
+
+    #[test]
+    fn split_escape_sequences_wait_for_the_rest() {
+        let (keys, used) = parse_keys(b"p\x1b[");
+        assert_eq!(keys, vec![Key::Char('p')]);
+        assert_eq!(used, 1);
+        let (keys, used) = parse_keys(b"\x1b[B");
+        assert_eq!(keys, vec![Key::Arrow(Direction::Down)]);
+        assert_eq!(used, 3);
+    }
+
+    #[test]
+    fn a_lone_escape_flushes_as_the_escape_key() {
+        assert_eq!(flush_keys(b"q\x1b"), vec![Key::Char('q'), Key::Escape]);
+        assert_eq!(flush_keys(b"\x1b["), vec![Key::Escape, Key::Char('[')]);
+        assert_eq!(key_binding(&Key::Escape), Some(Binding::Meta(MetaAction::Pause)));
+    }
+}
@@ -0,0 +1,275 @@
+use std::time::Duration;
+use bevy::ecs::schedule::IntoSystemDescriptor;
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use crate::core::GameState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, StageLabel)]
+pub struct FixedUpdate;
+
+// Drives the FixedUpdate stage. All game rules run inside that stage so a game
+// plays out the same no matter how ticks happen to line up with frames.
+pub struct TickClock {
+    step: Duration,
+    accumulator: Duration,
+    unpaced_tick_due: bool,
+    tick: u64,
+    limit: Option<u64>
+}
+
+impl TickClock {
+    pub fn new(step: Duration) -> Self {
+        TickClock {
+            step,
+            accumulator: Duration::ZERO,
+            unpaced_tick_due: false,
+            tick: 0,
+            limit: None
+        }
+    }
+
+    // One tick per frame, as fast as the app updates.
+    pub fn unpaced() -> Self {
+        TickClock::new(Duration::ZERO)
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    pub fn set_step(&mut self, step: Duration) {
+        self.step = step;
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    // How far the clock is towards its next tick, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        if self.step.is_zero() {
+            return 1.;
+        }
+        (self.accumulator.as_secs_f32() / self.step.as_secs_f32()).min(1.)
+    }
+
+    // Ticks past `limit` wait, e.g. for other players' inputs. A held clock
+    // doesn't bank the time, so it carries on at its usual pace once let go.
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+
+    pub fn advance(&mut self, delta: Duration) {
+        if self.step.is_zero() {
+            self.unpaced_tick_due = true;
+        } else {
+            self.accumulator += delta;
+        }
+    }
+
+    pub fn start_tick(&mut self) -> bool {
+        if self.limit.is_some_and(|limit| self.tick >= limit) {
+            self.accumulator = self.accumulator.min(self.step);
+            return false;
+        }
+        let due = if self.step.is_zero() {
+            std::mem::replace(&mut self.unpaced_tick_due, false)
+        } else if self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            true
+        } else {
+            false
+        };
+        if due {
+            self.tick += 1;
+        }
+        due
+    }
+}
+
+pub struct TickStage {
+    stage: SystemStage
+}
+
+impl Default for TickStage {
+    fn default() -> Self {
+        TickStage { stage: SystemStage::parallel() }
+    }
+}
+
+impl TickStage {
+    // Runs every tick that falls due once the clock has moved on by `delta`.
+    fn advance(&mut self, world: &mut World, delta: Duration) {
+        let running = world
+            .get_resource::<CurrentState<GameState>>()
+            .is_none_or(|state| state.0 == GameState::RUNNING);
+        if !running {
+            return;
+        }
+        match world.get_resource_mut::<TickClock>() {
+            Some(mut clock) => clock.advance(delta),
+            None => return
+        }
+
+        // a pending state change (e.g. death) is applied before any further tick
+        while !world.contains_resource::<NextState<GameState>>()
+            && world.resource_mut::<TickClock>().start_tick() {
+            self.stage.run(world);
+        }
+    }
+}
+
+impl Stage for TickStage {
+    fn run(&mut self, world: &mut World) {
+        let delta = world
+            .get_resource::<Time>()
+            .map(|time| time.delta())
+            .unwrap_or_default();
+        self.advance(world, delta);
+    }
+}
+
+pub trait AppTickExt {
+    fn add_tick_system<Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut App;
+    fn add_tick_system_set(&mut self, system_set: SystemSet) -> &mut App;
+}
+
+impl AppTickExt for App {
+    fn add_tick_system<Params>(&mut self, system: impl IntoSystemDescriptor<Params>) -> &mut App {
+        tick_stage(self).stage.add_system(system);
+        self
+    }
+
+    fn add_tick_system_set(&mut self, system_set: SystemSet) -> &mut App {
+        tick_stage(self).stage.add_system_set(system_set);
+        self
+    }
+}
+
+fn tick_stage(app: &mut App) -> &mut TickStage {
+    if app.schedule.get_stage::<TickStage>(&FixedUpdate).is_none() {
+        app.add_stage_before(CoreStage::Update, FixedUpdate, TickStage::default());
+    }
+    app.schedule.get_stage_mut::<TickStage>(&FixedUpdate).unwrap()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct TickCount(u32);
+
+    fn count_tick(mut count: ResMut<TickCount>) {
+        count.0 += 1;
+    }
+
+    #[test]
+    fn tick_clock_accumulates_partial_steps() {
+        let mut clock = TickClock::new(Duration::from_millis(100));
+        clock.advance(Duration::from_millis(60));
+        assert!(!clock.start_tick());
+        clock.advance(Duration::from_millis(60));
+        assert!(clock.start_tick());
+        assert!(!clock.start_tick());
+        clock.advance(Duration::from_millis(190));
+        assert!(clock.start_tick());
+        assert!(clock.start_tick());
+        assert!(!clock.start_tick());
+        assert_eq!(clock.tick(), 3);
+    }
+
+    #[test]
+    fn tick_clock_progress_is_the_fraction_of_a_step_banked() {
+        let mut clock = TickClock::new(Duration::from_millis(100));
+        assert_eq!(clock.progress(), 0.);
+        clock.advance(Duration::from_millis(125));
+        assert!(clock.start_tick());
+        assert!((clock.progress() - 0.25).abs() < 1e-6);
+        clock.set_limit(Some(1));
+        clock.advance(Duration::from_millis(200));
+        assert!(!clock.start_tick());
+        assert_eq!(clock.progress(), 1.);
+        assert_eq!(TickClock::unpaced().progress(), 1.);
+    }
+
+    #[test]
+    fn unpaced_tick_clock_ticks_once_per_advance() {
+        let mut clock = TickClock::unpaced();
+        assert!(!clock.start_tick());
+        clock.advance(Duration::ZERO);
+        assert!(clock.start_tick());
+        assert!(!clock.start_tick());
+        clock.advance(Duration::from_secs(10));
+        assert!(clock.start_tick());
+        assert!(!clock.start_tick());
+    }
+
+    #[test]
+    fn limit_holds_ticks_without_banking_time() {
+        let mut clock = TickClock::new(Duration::from_millis(100));
+        clock.set_limit(Some(1));
+        clock.advance(Duration::from_millis(550));
+        assert!(clock.start_tick());
+        assert!(!clock.start_tick());
+        clock.set_limit(Some(10));
+        assert!(clock.start_tick());
+        assert!(!clock.start_tick());
+        assert_eq!(clock.tick(), 2);
+    }
+
+    #[test]
+    fn tick_systems_run_once_per_tick() {
+        let mut app = App::default();
+        app.insert_resource(TickClock::unpaced());
+        app.init_resource::<TickCount>();
+        app.add_tick_system(count_tick);
+        for _ in 0..5 {
+            app.update();
+        }
+        assert_eq!(app.world.resource::<TickCount>().0, 5);
+        assert_eq!(app.world.resource::<TickClock>().tick(), 5);
+    }
+
+    #[test]
+    fn ticks_only_while_running() {
+        let mut app = App::default();
+        app.insert_resource(TickClock::unpaced());
+        app.init_resource::<TickCount>();
+        app.add_tick_system(count_tick);
+        app.insert_resource(CurrentState(GameState::COUNTDOWN));
+        app.update();
+        assert_eq!(app.world.resource::<TickCount>().0, 0);
+        app.insert_resource(CurrentState(GameState::RUNNING));
+        app.update();
+        assert_eq!(app.world.resource::<TickCount>().0, 1);
+    }
+
+    #[test]
+    fn resume_frame_keeps_tick_phase() {
+        let mut world = World::default();
+        let mut clock = TickClock::new(Duration::from_secs(1));
+        clock.advance(Duration::from_millis(980));
+        world.insert_resource(clock);
+        world.init_resource::<TickCount>();
+        world.insert_resource(CurrentState(GameState::RUNNING));
+        let mut stage = TickStage::default();
+        stage.stage.add_system(count_tick);
+        // the frame that resumes still counts towards the next tick
+        stage.advance(&mut world, Duration::from_millis(30));
+        assert_eq!(world.resource::<TickCount>().0, 1);
+        assert!((world.resource::<TickClock>().progress() - 0.01).abs() < 1e-6);
+    }
+
+    #[test]
+    fn pending_state_change_holds_ticks() {
+        let mut app = App::default();
+        app.insert_resource(TickClock::unpaced());
+        app.init_resource::<TickCount>();
+        app.add_tick_system(count_tick);
+        app.insert_resource(NextState(GameState::DEAD));
+        app.update();
+        assert_eq!(app.world.resource::<TickCount>().0, 0);
+    }
+}